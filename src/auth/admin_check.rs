@@ -0,0 +1,42 @@
+use crate::error::Result;
+use crate::jellyfin::JellyfinClient;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Recent live admin checks against Jellyfin, per user
+#[derive(Default)]
+pub struct LiveAdminChecks {
+    results: Mutex<HashMap<String, (Instant, bool)>>,
+}
+
+impl LiveAdminChecks {
+    /// Whether Jellyfin currently considers the user an administrator
+    ///
+    /// Results are reused for `max_age`. Failures are not cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if Jellyfin can't be asked
+    pub async fn is_admin(
+        &self,
+        client: &JellyfinClient,
+        user_id: &str,
+        max_age: Duration,
+    ) -> Result<bool> {
+        if let Some((checked, is_admin)) = self.lock().get(user_id).copied()
+            && checked.elapsed() < max_age
+        {
+            return Ok(is_admin);
+        }
+
+        let is_admin = client.get_user(user_id).await?.is_administrator;
+        self.lock()
+            .insert(user_id.to_string(), (Instant::now(), is_admin));
+        Ok(is_admin)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Instant, bool)>> {
+        self.results.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,90 @@
+use crate::config::{SameSiteMode, SecurityConfig};
+use axum::http::HeaderMap;
+use tower_cookies::{
+    Cookie,
+    cookie::{SameSite, time::Duration},
+};
+
+/// Name of the cookie holding the post-login redirect target
+pub const REDIRECT_COOKIE_NAME: &str = "bouncarr_redirect";
+
+/// `SameSite` attribute for cookies issued in response to a request
+///
+/// This is the configured value, except with `adaptive_same_site` for
+/// requests the browser marks as cross-site (`Sec-Fetch-Site: cross-site`),
+/// which get `None` so the cookies come back on requests from that site.
+/// Browsers reject `None` on cookies that aren't `Secure`, so the configured
+/// value is kept without `secure_cookies`.
+pub fn same_site_for(config: &SecurityConfig, headers: &HeaderMap) -> SameSite {
+    let cross_site = headers
+        .get("sec-fetch-site")
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"cross-site"));
+
+    if config.adaptive_same_site && config.secure_cookies && cross_site {
+        return SameSite::None;
+    }
+    match config.same_site {
+        SameSiteMode::Strict => SameSite::Strict,
+        SameSiteMode::Lax => SameSite::Lax,
+        SameSiteMode::None => SameSite::None,
+    }
+}
+
+/// Build the access token cookie, expiring together with its token
+pub fn access_cookie_until(
+    config: &SecurityConfig,
+    same_site: SameSite,
+    token: String,
+    exp: i64,
+) -> Cookie<'static> {
+    let mut cookie = Cookie::new(config.cookie_name.clone(), token);
+    apply_attributes(config, same_site, &mut cookie);
+
+    let remaining = exp - chrono::Utc::now().timestamp();
+    cookie.set_max_age(Duration::seconds(remaining.max(0)));
+
+    cookie
+}
+
+/// Build the refresh token cookie
+pub fn refresh_cookie(
+    config: &SecurityConfig,
+    same_site: SameSite,
+    token: String,
+) -> Cookie<'static> {
+    let mut cookie = Cookie::new(config.refresh_cookie_name.clone(), token);
+    apply_attributes(config, same_site, &mut cookie);
+
+    let refresh_max_age = config.refresh_token_expiry_days as i64 * 86400;
+    cookie.set_max_age(Duration::seconds(refresh_max_age));
+
+    cookie
+}
+
+/// Build the cookie remembering where to go after login
+///
+/// The target is percent-encoded since paths may contain characters that are
+/// not valid in a cookie value.
+pub fn redirect_cookie(
+    config: &SecurityConfig,
+    same_site: SameSite,
+    target: &str,
+) -> Cookie<'static> {
+    let mut cookie = Cookie::new(
+        REDIRECT_COOKIE_NAME,
+        urlencoding::encode(target).into_owned(),
+    );
+    apply_attributes(config, same_site, &mut cookie);
+    cookie.set_max_age(Duration::seconds(
+        config.redirect_cookie_max_age_seconds as i64,
+    ));
+
+    cookie
+}
+
+fn apply_attributes(config: &SecurityConfig, same_site: SameSite, cookie: &mut Cookie<'static>) {
+    cookie.set_http_only(true);
+    cookie.set_secure(config.secure_cookies);
+    cookie.set_same_site(same_site);
+    cookie.set_path("/");
+}
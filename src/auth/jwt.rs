@@ -4,6 +4,7 @@ use crate::jellyfin::types::UserInfo;
 use chrono::{Duration, Utc};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// JWT token claims
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -20,6 +21,25 @@ pub struct Claims {
     pub iat: i64,
     /// Type of token (access or refresh)
     pub token_type: TokenType,
+    /// Login session the token belongs to (empty for tokens issued before
+    /// sessions were tracked)
+    #[serde(default)]
+    pub sid: String,
+    /// Issuer, set when `security.jwt_issuer` is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    /// Audience, set when `security.jwt_audience` is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    /// Groups the user was in when the token was issued
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// Token profile (`security.token_profiles`) picked at login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Unique id of a refresh token, for `security.track_refresh_tokens`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
 }
 
 /// Type of JWT token
@@ -36,7 +56,12 @@ pub enum TokenType {
 pub struct JwtManager {
     encoding_key: EncodingKey,
     decoding_key: DecodingKey,
+    access_token_expiry: Duration,
     refresh_token_expiry: Duration,
+    issuer: Option<String>,
+    audience: Option<String>,
+    /// Access token lifetimes by token profile name
+    profiles: HashMap<String, Duration>,
 }
 
 impl JwtManager {
@@ -69,7 +94,20 @@ impl JwtManager {
         Self {
             encoding_key: EncodingKey::from_secret(secret.as_bytes()),
             decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            access_token_expiry: Duration::hours(config.access_token_expiry_hours as i64),
             refresh_token_expiry: Duration::days(config.refresh_token_expiry_days as i64),
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            profiles: config
+                .token_profiles
+                .iter()
+                .map(|(name, profile)| {
+                    (
+                        name.clone(),
+                        Duration::hours(profile.access_token_hours as i64),
+                    )
+                })
+                .collect(),
         }
     }
 
@@ -88,43 +126,139 @@ impl JwtManager {
     /// Create an access token for a user
     ///
     /// Access tokens expire at the end of the current day.
-    pub fn create_access_token(&self, user_info: &UserInfo) -> Result<String> {
-        let now = Utc::now();
-        // Access token expires at end of day
-        let end_of_day = now
-            .date_naive()
-            .and_hms_opt(23, 59, 59)
-            .ok_or_else(|| {
-                AppError::Internal(anyhow::anyhow!("Failed to create end of day timestamp"))
-            })?
-            .and_utc();
+    #[cfg(test)]
+    pub fn create_access_token(&self, user_info: &UserInfo, sid: &str) -> Result<String> {
+        self.create_access_token_for(user_info, sid, None)
+            .map(|(token, _)| token)
+    }
 
-        let claims = Claims {
-            sub: user_info.user_id.clone(),
-            username: user_info.username.clone(),
-            is_admin: user_info.is_administrator,
-            exp: end_of_day.timestamp(),
-            iat: now.timestamp(),
-            token_type: TokenType::Access,
+    /// Create an access token lasting as long as the named token profile
+    /// says, or until the end of the current day without one
+    ///
+    /// Returns the token together with its expiration timestamp.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the profile is not configured
+    pub fn create_access_token_for(
+        &self,
+        user_info: &UserInfo,
+        sid: &str,
+        profile: Option<&str>,
+    ) -> Result<(String, i64)> {
+        let now = Utc::now();
+        let exp = match profile {
+            Some(profile) => {
+                let lifetime = self.profiles.get(profile).ok_or_else(|| {
+                    AppError::BadRequest(format!("Unknown token profile '{}'", profile))
+                })?;
+                (now + *lifetime).timestamp()
+            }
+            // Access token expires at end of day
+            None => now
+                .date_naive()
+                .and_hms_opt(23, 59, 59)
+                .ok_or_else(|| {
+                    AppError::Internal(anyhow::anyhow!("Failed to create end of day timestamp"))
+                })?
+                .and_utc()
+                .timestamp(),
         };
 
-        encode(&Header::default(), &claims, &self.encoding_key).map_err(AppError::JwtError)
+        let token = self.sign(user_info, sid, TokenType::Access, exp, profile, None)?;
+        Ok((token, exp))
+    }
+
+    /// Token profile of existing claims, if it is still configured
+    ///
+    /// Tokens whose profile was removed from the config fall back to the
+    /// default lifetime when refreshed.
+    pub fn known_profile<'c>(&self, claims: &'c Claims) -> Option<&'c str> {
+        claims
+            .profile
+            .as_deref()
+            .filter(|profile| self.profiles.contains_key(*profile))
+    }
+
+    /// Create an access token for a rolling session
+    ///
+    /// Unlike regular access tokens these last the configured number of hours
+    /// from now, but never past `not_after` (the refresh token expiry).
+    /// Returns the token together with its expiration timestamp.
+    pub fn create_rolling_access_token(
+        &self,
+        user_info: &UserInfo,
+        sid: &str,
+        not_after: i64,
+    ) -> Result<(String, i64)> {
+        let exp = (Utc::now() + self.access_token_expiry)
+            .timestamp()
+            .min(not_after);
+        let token = self.sign(user_info, sid, TokenType::Access, exp, None, None)?;
+        Ok((token, exp))
     }
 
     /// Create a refresh token for a user
     ///
     /// Refresh tokens expire after the configured number of days.
-    pub fn create_refresh_token(&self, user_info: &UserInfo) -> Result<String> {
-        let now = Utc::now();
-        let expiry = now + self.refresh_token_expiry;
+    #[cfg(test)]
+    pub fn create_refresh_token(&self, user_info: &UserInfo, sid: &str) -> Result<String> {
+        self.create_refresh_token_for(user_info, sid, None)
+            .map(|(token, _)| token)
+    }
 
+    /// Create a refresh token remembering the token profile access tokens
+    /// refreshed with it are issued for
+    ///
+    /// Returns the token together with its unique id (`jti`).
+    pub fn create_refresh_token_for(
+        &self,
+        user_info: &UserInfo,
+        sid: &str,
+        profile: Option<&str>,
+    ) -> Result<(String, String)> {
+        let exp = (Utc::now() + self.refresh_token_expiry).timestamp();
+        let jti = Self::generate_token_id();
+        let token = self.sign(
+            user_info,
+            sid,
+            TokenType::Refresh,
+            exp,
+            profile,
+            Some(jti.clone()),
+        )?;
+        Ok((token, jti))
+    }
+
+    fn generate_token_id() -> String {
+        use base64::Engine;
+        use rand::Rng;
+        let bytes: [u8; 16] = rand::thread_rng().r#gen();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    fn sign(
+        &self,
+        user_info: &UserInfo,
+        sid: &str,
+        token_type: TokenType,
+        exp: i64,
+        profile: Option<&str>,
+        jti: Option<String>,
+    ) -> Result<String> {
         let claims = Claims {
             sub: user_info.user_id.clone(),
             username: user_info.username.clone(),
             is_admin: user_info.is_administrator,
-            exp: expiry.timestamp(),
-            iat: now.timestamp(),
-            token_type: TokenType::Refresh,
+            exp,
+            iat: Utc::now().timestamp(),
+            token_type,
+            sid: sid.to_string(),
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+            groups: user_info.groups.clone(),
+            profile: profile.map(String::from),
+            jti,
         };
 
         encode(&Header::default(), &claims, &self.encoding_key).map_err(AppError::JwtError)
@@ -141,7 +275,7 @@ impl JwtManager {
     ///
     /// Returns error if token is invalid, expired, or type mismatch
     pub fn validate_token(&self, token: &str, expected_type: TokenType) -> Result<Claims> {
-        let token_data = decode::<Claims>(token, &self.decoding_key, &Validation::default())?;
+        let token_data = decode::<Claims>(token, &self.decoding_key, &self.validation())?;
 
         if token_data.claims.token_type != expected_type {
             return Err(AppError::InvalidToken);
@@ -149,6 +283,50 @@ impl JwtManager {
 
         Ok(token_data.claims)
     }
+
+    /// Validate a JWT token that may have expired up to `grace_seconds` ago
+    ///
+    /// The signature and token type are still fully verified.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if token is invalid, expired beyond the grace window, or
+    /// type mismatch
+    pub fn validate_token_with_grace(
+        &self,
+        token: &str,
+        expected_type: TokenType,
+        grace_seconds: u64,
+    ) -> Result<Claims> {
+        let mut validation = self.validation();
+        validation.leeway = grace_seconds;
+        let token_data = decode::<Claims>(token, &self.decoding_key, &validation)?;
+
+        if token_data.claims.token_type != expected_type {
+            return Err(AppError::InvalidToken);
+        }
+
+        Ok(token_data.claims)
+    }
+
+    /// Validation rules, requiring the issuer and audience only if configured
+    fn validation(&self) -> Validation {
+        let mut validation = Validation::default();
+        let mut required = vec!["exp"];
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+            required.push("iss");
+        }
+        match &self.audience {
+            Some(audience) => {
+                validation.set_audience(&[audience]);
+                required.push("aud");
+            }
+            None => validation.validate_aud = false,
+        }
+        validation.set_required_spec_claims(&required);
+        validation
+    }
 }
 
 #[cfg(test)]
@@ -161,8 +339,40 @@ mod tests {
             refresh_token_expiry_days: 30,
             cookie_name: "test_token".to_string(),
             refresh_cookie_name: "test_refresh".to_string(),
+            bootstrap_admin: None,
+            require_admin: true,
+            legacy_cookie_names: Vec::new(),
+            token_profiles: HashMap::new(),
+            single_session: false,
+            no_store_auth_responses: true,
+            track_refresh_tokens: false,
+            login_webhook_url: None,
+            login_webhook_failures: false,
             secure_cookies: false,
             jwt_secret: Some("test-secret-key-for-testing".to_string()),
+            access_grace_seconds: 0,
+            redirect_cookie_max_age_seconds: 300,
+            rolling_session: false,
+            allow_query_token: false,
+            idle_session_timeout_seconds: None,
+            same_site: Default::default(),
+            adaptive_same_site: false,
+            username_denylist: Vec::new(),
+            min_username_length: None,
+            min_password_length: None,
+            check_login_origin: false,
+            login_allowed_origins: Vec::new(),
+            refresh_retries: 0,
+            refresh_retry_delay_ms: 200,
+            refresh_fallback_seconds: None,
+            live_admin_cache_seconds: 60,
+            max_token_bytes: 8192,
+            refresh_reissue_window_days: None,
+            jwt_issuer: None,
+            jwt_audience: None,
+            disable_cookies: false,
+            credential_conflict: Default::default(),
+            api_paths: Vec::new(),
         }
     }
 
@@ -171,9 +381,36 @@ mod tests {
             user_id: "test-user-123".to_string(),
             username: "testuser".to_string(),
             is_administrator: true,
+            groups: Vec::new(),
         }
     }
 
+    #[test]
+    fn test_token_profile_sets_access_token_lifetime() {
+        let mut config = test_config();
+        config.token_profiles.insert(
+            "automation".to_string(),
+            crate::config::TokenProfile {
+                access_token_hours: 72,
+            },
+        );
+        let manager = JwtManager::new(&config);
+
+        let (token, exp) = manager
+            .create_access_token_for(&test_user_info(), "session-1", Some("automation"))
+            .unwrap();
+        let expected = (Utc::now() + Duration::hours(72)).timestamp();
+        assert!((exp - expected).abs() <= 5);
+
+        let claims = manager.validate_token(&token, TokenType::Access).unwrap();
+        assert_eq!(claims.exp, exp);
+        assert_eq!(manager.known_profile(&claims), Some("automation"));
+
+        let result =
+            manager.create_access_token_for(&test_user_info(), "session-1", Some("unknown"));
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
     #[test]
     fn test_create_and_validate_access_token() {
         let config = test_config();
@@ -181,7 +418,9 @@ mod tests {
         let user_info = test_user_info();
 
         // Create access token
-        let token = manager.create_access_token(&user_info).unwrap();
+        let token = manager
+            .create_access_token(&user_info, "session-1")
+            .unwrap();
         assert!(!token.is_empty());
 
         // Validate token
@@ -190,6 +429,7 @@ mod tests {
         assert_eq!(claims.username, user_info.username);
         assert_eq!(claims.is_admin, user_info.is_administrator);
         assert_eq!(claims.token_type, TokenType::Access);
+        assert_eq!(claims.sid, "session-1");
     }
 
     #[test]
@@ -199,7 +439,9 @@ mod tests {
         let user_info = test_user_info();
 
         // Create refresh token
-        let token = manager.create_refresh_token(&user_info).unwrap();
+        let token = manager
+            .create_refresh_token(&user_info, "session-1")
+            .unwrap();
         assert!(!token.is_empty());
 
         // Validate token
@@ -216,12 +458,16 @@ mod tests {
         let user_info = test_user_info();
 
         // Create access token but try to validate as refresh
-        let token = manager.create_access_token(&user_info).unwrap();
+        let token = manager
+            .create_access_token(&user_info, "session-1")
+            .unwrap();
         let result = manager.validate_token(&token, TokenType::Refresh);
         assert!(result.is_err());
 
         // Create refresh token but try to validate as access
-        let token = manager.create_refresh_token(&user_info).unwrap();
+        let token = manager
+            .create_refresh_token(&user_info, "session-1")
+            .unwrap();
         let result = manager.validate_token(&token, TokenType::Access);
         assert!(result.is_err());
     }
@@ -235,6 +481,59 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_issuer_and_audience() {
+        let user_info = test_user_info();
+        let mut config = test_config();
+        config.jwt_issuer = Some("bouncarr".to_string());
+        config.jwt_audience = Some("arr-apps".to_string());
+        let manager = JwtManager::new(&config);
+
+        let token = manager
+            .create_access_token(&user_info, "session-1")
+            .unwrap();
+        let claims = manager.validate_token(&token, TokenType::Access).unwrap();
+        assert_eq!(claims.iss.as_deref(), Some("bouncarr"));
+        assert_eq!(claims.aud.as_deref(), Some("arr-apps"));
+
+        // Tokens for another issuer or audience are rejected
+        for (issuer, audience) in [("bouncarr", "other-app"), ("someone-else", "arr-apps")] {
+            let mut other = test_config();
+            other.jwt_issuer = Some(issuer.to_string());
+            other.jwt_audience = Some(audience.to_string());
+            let token = JwtManager::new(&other)
+                .create_access_token(&user_info, "session-1")
+                .unwrap();
+            assert!(manager.validate_token(&token, TokenType::Access).is_err());
+        }
+
+        // As are tokens without the claims once they are configured
+        let token = JwtManager::new(&test_config())
+            .create_access_token(&user_info, "session-1")
+            .unwrap();
+        assert!(manager.validate_token(&token, TokenType::Access).is_err());
+    }
+
+    #[test]
+    fn test_issuer_and_audience_ignored_when_not_configured() {
+        let user_info = test_user_info();
+        let mut config = test_config();
+        config.jwt_issuer = Some("bouncarr".to_string());
+        config.jwt_audience = Some("arr-apps".to_string());
+        let token = JwtManager::new(&config)
+            .create_access_token(&user_info, "session-1")
+            .unwrap();
+
+        let manager = JwtManager::new(&test_config());
+        assert!(manager.validate_token(&token, TokenType::Access).is_ok());
+        let token = manager
+            .create_access_token(&user_info, "session-1")
+            .unwrap();
+        let claims = manager.validate_token(&token, TokenType::Access).unwrap();
+        assert_eq!(claims.iss, None);
+        assert_eq!(claims.aud, None);
+    }
+
     #[test]
     fn test_different_secrets_produce_different_tokens() {
         let user_info = test_user_info();
@@ -246,8 +545,12 @@ mod tests {
         config2.jwt_secret = Some("different-secret".to_string());
         let manager2 = JwtManager::new(&config2);
 
-        let token1 = manager1.create_access_token(&user_info).unwrap();
-        let token2 = manager2.create_access_token(&user_info).unwrap();
+        let token1 = manager1
+            .create_access_token(&user_info, "session-1")
+            .unwrap();
+        let token2 = manager2
+            .create_access_token(&user_info, "session-1")
+            .unwrap();
 
         // Tokens should be different
         assert_ne!(token1, token2);
@@ -0,0 +1,61 @@
+use serde::Serialize;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// How long a webhook receiver gets to accept a notification
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Result of a login attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginOutcome {
+    Success,
+    /// Jellyfin rejected the credentials
+    Failure,
+    /// Valid credentials of a user who is not an administrator
+    Forbidden,
+}
+
+/// JSON payload posted to `security.login_webhook_url`
+#[derive(Debug, Serialize)]
+pub struct LoginEvent {
+    pub username: String,
+    /// Client address, if known
+    pub ip: Option<IpAddr>,
+    /// RFC 3339 time of the attempt
+    pub timestamp: String,
+    pub outcome: LoginOutcome,
+}
+
+impl LoginEvent {
+    pub fn new(username: &str, ip: Option<IpAddr>, outcome: LoginOutcome) -> Self {
+        Self {
+            username: username.to_string(),
+            ip,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            outcome,
+        }
+    }
+}
+
+/// Post a login event to the webhook in the background
+///
+/// The login response never waits for the receiver. Delivery failures are
+/// only logged.
+pub fn notify(client: &reqwest::Client, url: &str, event: LoginEvent) {
+    let request = client.post(url).timeout(WEBHOOK_TIMEOUT).json(&event);
+    tokio::spawn(async move {
+        match request.send().await.and_then(|res| res.error_for_status()) {
+            Ok(_) => tracing::debug!(
+                "Sent login webhook for '{}' ({:?})",
+                event.username,
+                event.outcome
+            ),
+            Err(e) => tracing::warn!(
+                "Failed to send login webhook for '{}': {}",
+                event.username,
+                e.without_url()
+            ),
+        }
+    });
+}
@@ -1,16 +1,24 @@
 use crate::AppState;
-use crate::auth::jwt::TokenType;
+use crate::auth::cookies::{access_cookie_until, same_site_for};
+use crate::auth::jwt::{Claims, TokenType};
+use crate::config::{CredentialConflict, SecurityConfig};
 use crate::error::{AppError, Result};
 use crate::jellyfin::types::UserInfo;
+use crate::proxy::handler::resolve_route;
 use axum::{
     body::Body,
     extract::{Request, State},
-    http::{StatusCode, header},
+    http::{HeaderMap, StatusCode, header},
     middleware::Next,
     response::{IntoResponse, Redirect, Response},
 };
 use std::sync::Arc;
-use tower_cookies::Cookies;
+use tower_cookies::{Cookies, cookie::SameSite};
+
+/// Whether a path belongs to Bouncarr itself rather than a proxied app
+fn is_bouncarr_path(path: &str) -> bool {
+    path == "/bouncarr" || path.starts_with("/bouncarr/")
+}
 
 pub async fn auth_middleware(
     State(state): State<Arc<AppState>>,
@@ -18,52 +26,98 @@ pub async fn auth_middleware(
     mut req: Request<Body>,
     next: Next,
 ) -> Response {
-    tracing::debug!("Auth middleware: checking authentication for {}", req.uri());
+    tracing::debug!(
+        "Auth middleware: checking authentication for {}",
+        crate::config::redact_query(
+            &req.uri().to_string(),
+            &state.config.server.redact_query_params
+        )
+    );
+
+    // CORS preflights carry no credentials; apps that answer them locally
+    // never forward them upstream
+    if crate::proxy::cors::is_local_preflight(&state.config, &req) {
+        return next.run(req).await;
+    }
 
-    // Check if this is a browser request (wants HTML)
+    // Check if this is a browser request (wants HTML). API paths never get
+    // redirected, whatever they accept.
     let is_browser = req
         .headers()
         .get(header::ACCEPT)
         .and_then(|v| v.to_str().ok())
         .map(|v| v.contains("text/html"))
-        .unwrap_or(false);
+        .unwrap_or(false)
+        && !state.config.security.is_api_path(req.uri().path());
+
+    if let Err(e) = check_credential_conflict(&state, &req, &cookies) {
+        return e.into_response();
+    }
 
     // Extract token from cookie or Authorization header
-    let token = match extract_token(&req, cookies, &state.config.security.cookie_name) {
+    let token = match extract_token(&req, &cookies, &state.config.security) {
         Ok(t) => t,
         Err(e) => {
             tracing::debug!("No valid token found for {}", req.uri().path());
             if is_browser {
-                let redirect_url = format!(
-                    "/bouncarr/login?redirect={}",
-                    urlencoding::encode(req.uri().path())
-                );
-                return Redirect::to(&redirect_url).into_response();
+                return login_redirect(req.uri().path());
             }
             return e.into_response();
         }
     };
 
+    // Cookies re-issued below follow the SameSite policy for this request
+    let same_site = same_site_for(&state.config.security, req.headers());
+
     // Validate the access token
     let claims = match state.jwt_manager.validate_token(&token, TokenType::Access) {
         Ok(c) => c,
+        Err(e)
+            if is_expired(&e)
+                && state.config.security.access_grace_seconds > 0
+                && !state.config.security.disable_cookies =>
+        {
+            match refresh_within_grace(&state, &cookies, same_site, &token).await {
+                Some(c) => c,
+                None => {
+                    tracing::debug!("Access token expired for {}", req.uri().path());
+                    if is_browser {
+                        return login_redirect(req.uri().path());
+                    }
+                    return AppError::Unauthorized.into_response();
+                }
+            }
+        }
         Err(e) => {
             // Only log validation failures at debug level to reduce noise
             // (common after server restart with old cookies)
             tracing::debug!("Token validation failed for {}: {:?}", req.uri().path(), e);
             if is_browser {
-                let redirect_url = format!(
-                    "/bouncarr/login?redirect={}",
-                    urlencoding::encode(req.uri().path())
-                );
-                return Redirect::to(&redirect_url).into_response();
+                return login_redirect(req.uri().path());
             }
             return AppError::Unauthorized.into_response();
         }
     };
 
-    // Check if user is an administrator
-    if !claims.is_admin {
+    // Reject revoked sessions and those idle for too long
+    let idle_timeout = state.config.security.idle_session_timeout();
+    if !state.sessions.touch(&claims.sid, idle_timeout) {
+        tracing::debug!("Session of {} is no longer active", claims.username);
+        if is_browser {
+            return login_redirect(req.uri().path());
+        }
+        return AppError::Unauthorized.into_response();
+    }
+
+    // Check if user is an administrator. Apps that admit everyone are left
+    // to the proxy handler. Bouncarr's own endpoints are served locally
+    // whatever app the Host or X-Bouncarr-App header names, so they are
+    // always admin-only.
+    if !claims.is_admin
+        && (is_bouncarr_path(req.uri().path())
+            || resolve_route(&state.config, &req)
+                .is_none_or(|route| state.config.require_admin(route.app)))
+    {
         tracing::warn!("User {} is not an admin", claims.username);
         if is_browser {
             return (
@@ -77,11 +131,20 @@ pub async fn auth_middleware(
 
     tracing::debug!("Auth successful for user: {}", claims.username);
 
+    if state.config.security.rolling_session
+        && !state.config.security.disable_cookies
+        && claims.profile.is_none()
+        && past_halfway(&claims)
+    {
+        roll_session(&state, &cookies, same_site, &claims);
+    }
+
     // Create UserInfo from claims and attach to request
     let user_info = UserInfo {
         user_id: claims.sub,
         username: claims.username,
         is_administrator: claims.is_admin,
+        groups: claims.groups,
     };
 
     req.extensions_mut().insert(user_info);
@@ -89,25 +152,874 @@ pub async fn auth_middleware(
     next.run(req).await
 }
 
-fn extract_token(req: &Request<Body>, cookies: Cookies, cookie_name: &str) -> Result<String> {
+/// Redirect a browser to the login page, returning to `path` afterwards
+fn login_redirect(path: &str) -> Response {
+    let redirect_url = format!("/bouncarr/login?redirect={}", urlencoding::encode(path));
+    Redirect::to(&redirect_url).into_response()
+}
+
+fn is_expired(error: &AppError) -> bool {
+    matches!(
+        error,
+        AppError::JwtError(e) if *e.kind() == jsonwebtoken::errors::ErrorKind::ExpiredSignature
+    )
+}
+
+/// Silently refresh an access token that expired within the grace window
+///
+/// Requires a valid refresh token for the same user. Fresh user data is
+/// fetched from Jellyfin, exactly like the refresh endpoint, and a new access
+/// token cookie is issued so following requests skip this path.
+async fn refresh_within_grace(
+    state: &AppState,
+    cookies: &Cookies,
+    same_site: SameSite,
+    token: &str,
+) -> Option<Claims> {
+    let security = &state.config.security;
+    let expired = state
+        .jwt_manager
+        .validate_token_with_grace(token, TokenType::Access, security.access_grace_seconds)
+        .ok()?;
+
+    let refresh_token = cookies.get(&security.refresh_cookie_name)?;
+    let refresh_claims = state
+        .jwt_manager
+        .validate_token(refresh_token.value(), TokenType::Refresh)
+        .ok()?;
+    if refresh_claims.sub != expired.sub {
+        tracing::warn!("Refresh token does not belong to user of expired access token");
+        return None;
+    }
+    if security.track_refresh_tokens && !state.refresh_tokens.accepts(&refresh_claims) {
+        tracing::warn!(
+            "Unrecorded refresh token for {}, not refreshing",
+            expired.username
+        );
+        return None;
+    }
+
+    let user_info = match state.jellyfin_client.get_user(&refresh_claims.sub).await {
+        Ok(user_info) => user_info,
+        Err(e) => {
+            tracing::warn!("Grace refresh failed for {}: {}", expired.username, e);
+            return None;
+        }
+    };
+
+    let profile = state.jwt_manager.known_profile(&expired);
+    let (access_token, exp) = state
+        .jwt_manager
+        .create_access_token_for(&user_info, &expired.sid, profile)
+        .ok()?;
+    let claims = state
+        .jwt_manager
+        .validate_token(&access_token, TokenType::Access)
+        .ok()?;
+    cookies.add(access_cookie_until(security, same_site, access_token, exp));
+
+    tracing::debug!(
+        "Silently refreshed expired access token for {}",
+        claims.username
+    );
+    Some(claims)
+}
+
+/// Whether more than half of a token's lifetime has elapsed
+fn past_halfway(claims: &Claims) -> bool {
+    let now = chrono::Utc::now().timestamp();
+    now - claims.iat > (claims.exp - claims.iat) / 2
+}
+
+/// Re-issue the access token of a rolling session
+///
+/// The new token never outlives the refresh token, which bounds the total
+/// session length. Without a valid refresh token for the same user the
+/// current token is left to expire normally.
+fn roll_session(state: &AppState, cookies: &Cookies, same_site: SameSite, claims: &Claims) {
+    let security = &state.config.security;
+    let Some(refresh_claims) = cookies
+        .get(&security.refresh_cookie_name)
+        .and_then(|c| {
+            state
+                .jwt_manager
+                .validate_token(c.value(), TokenType::Refresh)
+                .ok()
+        })
+        .filter(|r| r.sub == claims.sub)
+    else {
+        tracing::debug!(
+            "Not rolling session for {}: no valid refresh token",
+            claims.username
+        );
+        return;
+    };
+
+    let user_info = UserInfo {
+        user_id: claims.sub.clone(),
+        username: claims.username.clone(),
+        is_administrator: claims.is_admin,
+        groups: claims.groups.clone(),
+    };
+    match state
+        .jwt_manager
+        .create_rolling_access_token(&user_info, &claims.sid, refresh_claims.exp)
+    {
+        Ok((token, exp)) => {
+            cookies.add(access_cookie_until(security, same_site, token, exp));
+            tracing::debug!("Rolled access token for {}", claims.username);
+        }
+        Err(e) => tracing::warn!("Failed to roll access token for {}: {}", claims.username, e),
+    }
+}
+
+/// Detect a session cookie and bearer token that are both valid but for
+/// different users, which `credential_conflict` may reject
+///
+/// # Errors
+///
+/// Returns `BadRequest` on a conflict when configured to reject
+fn check_credential_conflict(
+    state: &AppState,
+    req: &Request<Body>,
+    cookies: &Cookies,
+) -> Result<()> {
+    let security = &state.config.security;
+    if security.disable_cookies {
+        return Ok(());
+    }
+    let (Some(cookie), Some(bearer)) = (
+        presented_access_cookie(cookies, security),
+        bearer_token(req.headers()),
+    ) else {
+        return Ok(());
+    };
+    if cookie.value() == bearer {
+        return Ok(());
+    }
+
+    let validate = |token| {
+        state
+            .jwt_manager
+            .validate_token(token, TokenType::Access)
+            .ok()
+    };
+    let (Some(from_cookie), Some(from_bearer)) = (validate(cookie.value()), validate(bearer))
+    else {
+        return Ok(());
+    };
+    if from_cookie.sub == from_bearer.sub {
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "Session cookie of {} and bearer token of {} on the same request to {}",
+        from_cookie.username,
+        from_bearer.username,
+        req.uri().path()
+    );
+    if security.credential_conflict == CredentialConflict::Reject {
+        return Err(AppError::BadRequest(
+            "Session cookie and Authorization header belong to different users".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Token from an `Authorization: Bearer ...` header
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Query parameter carrying an access token when `allow_query_token` is on
+pub const QUERY_TOKEN_PARAM: &str = "_bouncarr_token";
+
+/// Find the access token in the cookie, Authorization header or (if
+/// enabled) query string
+///
+/// # Errors
+///
+/// Returns `Unauthorized` if there is no token or it is longer than
+/// `max_token_bytes`, which is checked before any decoding is attempted
+pub(crate) fn extract_token(
+    req: &Request<Body>,
+    cookies: &Cookies,
+    security: &SecurityConfig,
+) -> Result<String> {
+    let token = find_token(req, cookies, security)?;
+    if token.len() > security.max_token_bytes {
+        tracing::debug!(
+            "Rejecting {} byte token (limit {})",
+            token.len(),
+            security.max_token_bytes
+        );
+        return Err(AppError::Unauthorized);
+    }
+    Ok(token)
+}
+
+/// Access token cookie under `cookie_name`, or else under one of the
+/// `legacy_cookie_names`
+pub(crate) fn presented_access_cookie<'c>(
+    cookies: &'c Cookies,
+    security: &SecurityConfig,
+) -> Option<tower_cookies::Cookie<'c>> {
+    std::iter::once(&security.cookie_name)
+        .chain(&security.legacy_cookie_names)
+        .find_map(|name| cookies.get(name))
+}
+
+fn find_token(req: &Request<Body>, cookies: &Cookies, security: &SecurityConfig) -> Result<String> {
+    // The Authorization header goes first if it is to win conflicts
+    if security.credential_conflict == CredentialConflict::Bearer
+        && let Some(token) = bearer_token(req.headers())
+    {
+        tracing::debug!("Found token in Authorization header");
+        return Ok(token.to_string());
+    }
+
     // Try to get token from cookie first
-    if let Some(cookie) = cookies.get(cookie_name) {
+    if !security.disable_cookies
+        && let Some(cookie) = presented_access_cookie(cookies, security)
+    {
         // Note: Logging cookie NAME only (not the value/token itself) - safe for production
-        tracing::debug!("Found token in cookie: {}", cookie_name);
+        tracing::debug!("Found token in cookie: {}", cookie.name());
         return Ok(cookie.value().to_string());
     }
 
     // Try to get token from Authorization header
     // Using let-chain syntax for clean sequential error handling
-    if let Some(auth_header) = req.headers().get(header::AUTHORIZATION)
-        && let Ok(auth_str) = auth_header.to_str()
-        && let Some(token) = auth_str.strip_prefix("Bearer ")
-    {
+    if let Some(token) = bearer_token(req.headers()) {
         // Note: Not logging the actual token value - safe for production
         tracing::debug!("Found token in Authorization header");
         return Ok(token.to_string());
     }
 
+    // Finally, the query parameter used by deep links if enabled. API-only
+    // deployments take the Authorization header alone.
+    if security.allow_query_token
+        && !security.disable_cookies
+        && let Some(token) = query_token(req.uri().query())
+    {
+        tracing::debug!("Found token in query parameter");
+        return Ok(token);
+    }
+
     tracing::debug!("No token found in cookies or headers");
     Err(AppError::Unauthorized)
 }
+
+fn query_token(query: Option<&str>) -> Option<String> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.strip_prefix(QUERY_TOKEN_PARAM)?.strip_prefix('='))
+        .find_map(|value| urlencoding::decode(value).ok())
+        .map(|token| token.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{
+        jellyfin_user, sign_claims, spawn_jellyfin, test_config, test_state, test_user_info,
+    };
+    use axum::{Extension, Router, middleware, routing::get};
+    use tower::ServiceExt;
+    use tower_cookies::CookieManagerLayer;
+
+    /// Router with a single protected route echoing the authenticated user
+    fn protected_router(config: Config) -> Router {
+        protected_router_with_state(test_state(config))
+    }
+
+    fn protected_router_with_state(state: Arc<AppState>) -> Router {
+        Router::new()
+            .route(
+                "/sonarr/api",
+                get(|Extension(user): Extension<UserInfo>| async move { user.username }),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            ))
+            .layer(CookieManagerLayer::new())
+            .with_state(state)
+    }
+
+    fn access_token_expired_for(seconds: i64) -> String {
+        let user = test_user_info();
+        let now = chrono::Utc::now().timestamp();
+        sign_claims(&Claims {
+            sub: user.user_id,
+            username: user.username,
+            is_admin: true,
+            exp: now - seconds,
+            iat: now - 86400,
+            token_type: TokenType::Access,
+            sid: "test-session".to_string(),
+            iss: None,
+            aud: None,
+            groups: Vec::new(),
+            profile: None,
+            jti: None,
+        })
+    }
+
+    fn refresh_token() -> String {
+        let state = test_state(test_config());
+        state
+            .jwt_manager
+            .create_refresh_token(&test_user_info(), "test-session")
+            .unwrap()
+    }
+
+    fn request(access_token: &str, refresh_token: &str, accept: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/sonarr/api")
+            .header(header::ACCEPT, accept)
+            .header(
+                header::COOKIE,
+                format!(
+                    "bouncarr_token={}; bouncarr_refresh={}",
+                    access_token, refresh_token
+                ),
+            )
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn grace_config() -> Config {
+        let mut config = test_config();
+        config.security.access_grace_seconds = 600;
+        config.jellyfin.url =
+            spawn_jellyfin(jellyfin_user("test-user-123", "testuser", true)).await;
+        config
+    }
+
+    #[tokio::test]
+    async fn test_oversized_token_rejected() {
+        let app = protected_router(test_config());
+        let huge = "a".repeat(9000);
+
+        let by_cookie = request(&huge, "", "application/json");
+        let by_header = Request::builder()
+            .uri("/sonarr/api")
+            .header(header::AUTHORIZATION, format!("Bearer {}", huge))
+            .body(Body::empty())
+            .unwrap();
+        for req in [by_cookie, by_header] {
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        // A valid token of normal size still gets through
+        let res = app
+            .oneshot(
+                Request::builder()
+                    .uri("/sonarr/api")
+                    .header(
+                        header::AUTHORIZATION,
+                        format!("Bearer {}", crate::test_support::access_token(true)),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_disable_cookies_only_accepts_bearer() {
+        let mut config = test_config();
+        config.security.disable_cookies = true;
+        let app = protected_router(config);
+        let token = crate::test_support::access_token(true);
+
+        let res = app
+            .clone()
+            .oneshot(request(&token, &refresh_token(), "application/json"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = Request::builder()
+            .uri("/sonarr/api")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_cookie_and_bearer_users() {
+        let state = test_state(test_config());
+        let token_for = |user_id: &str, username: &str| {
+            let user = UserInfo {
+                user_id: user_id.to_string(),
+                username: username.to_string(),
+                is_administrator: true,
+                groups: Vec::new(),
+            };
+            state.jwt_manager.create_access_token(&user, "").unwrap()
+        };
+        let alice = token_for("user-1", "alice");
+        let alice_again = token_for("user-1", "alice");
+        let bob = token_for("user-2", "bob");
+
+        let send = |conflict: CredentialConflict, cookie: Option<&str>, bearer: Option<&str>| {
+            let mut config = test_config();
+            config.security.credential_conflict = conflict;
+            let app = protected_router(config);
+            let mut req = Request::builder().uri("/sonarr/api");
+            if let Some(cookie) = cookie {
+                req = req.header(header::COOKIE, format!("bouncarr_token={}", cookie));
+            }
+            if let Some(bearer) = bearer {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {}", bearer));
+            }
+            let req = req.body(Body::empty()).unwrap();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                let status = res.status();
+                let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        // Same user in both places, or only one source, passes as usual
+        let (status, body) =
+            send(CredentialConflict::Reject, Some(&alice), Some(&alice_again)).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "alice"));
+        let (status, body) = send(CredentialConflict::Reject, None, Some(&bob)).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "bob"));
+
+        // Different users: rejected or resolved as configured
+        let (status, _) = send(CredentialConflict::Reject, Some(&alice), Some(&bob)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, body) = send(CredentialConflict::Cookie, Some(&alice), Some(&bob)).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "alice"));
+        let (status, body) = send(CredentialConflict::Bearer, Some(&alice), Some(&bob)).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "bob"));
+    }
+
+    #[tokio::test]
+    async fn test_api_paths_get_401_json_for_browsers() {
+        let request = || {
+            Request::builder()
+                .uri("/sonarr/api")
+                .header(header::ACCEPT, "text/html,application/xhtml+xml")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let res = protected_router(test_config())
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+
+        let mut config = test_config();
+        config.security.api_paths = vec!["/sonarr/api*".to_string()];
+        let res = protected_router(config).oneshot(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+    }
+
+    #[tokio::test]
+    async fn test_legacy_cookie_name_still_accepted() {
+        // The access cookie was renamed from the `bouncarr_token` sent by
+        // `request`
+        let mut config = grace_config().await;
+        config.security.cookie_name = "bouncarr_session".to_string();
+        let token = crate::test_support::access_token(true);
+
+        let res = protected_router(config.clone())
+            .oneshot(request(&token, &refresh_token(), "application/json"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        config.security.legacy_cookie_names = vec!["bouncarr_token".to_string()];
+        let app = protected_router(config);
+        let res = app
+            .clone()
+            .oneshot(request(&token, &refresh_token(), "application/json"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+
+        // Re-issued tokens go in the current cookie
+        let res = app
+            .oneshot(request(
+                &access_token_expired_for(120),
+                &refresh_token(),
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let set_cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(
+            set_cookie.starts_with("bouncarr_session="),
+            "{}",
+            set_cookie
+        );
+    }
+
+    #[tokio::test]
+    async fn test_grace_refresh_for_browser_and_api_clients() {
+        let app = protected_router(grace_config().await);
+        let access = access_token_expired_for(120);
+        let refresh = refresh_token();
+
+        for accept in ["text/html", "application/json"] {
+            let res = app
+                .clone()
+                .oneshot(request(&access, &refresh, accept))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            // A fresh access token cookie is issued
+            let set_cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+            assert!(set_cookie.starts_with("bouncarr_token="));
+
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], b"testuser");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grace_refresh_requires_refresh_token() {
+        let app = protected_router(grace_config().await);
+        let access = access_token_expired_for(120);
+
+        let res = app
+            .oneshot(request(&access, "not-a-token", "application/json"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_expired_beyond_grace_is_rejected() {
+        let app = protected_router(grace_config().await);
+        let access = access_token_expired_for(3600);
+
+        let res = app
+            .clone()
+            .oneshot(request(&access, &refresh_token(), "application/json"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = app
+            .oneshot(request(&access, &refresh_token(), "text/html"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn test_grace_disabled_by_default() {
+        let mut config = grace_config().await;
+        config.security.access_grace_seconds = 0;
+        let app = protected_router(config);
+
+        let res = app
+            .oneshot(request(
+                &access_token_expired_for(120),
+                &refresh_token(),
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    /// Valid access token issued `age` seconds ago with a total lifetime of
+    /// `lifetime` seconds
+    fn access_token_aged(age: i64, lifetime: i64) -> String {
+        let user = test_user_info();
+        let now = chrono::Utc::now().timestamp();
+        sign_claims(&Claims {
+            sub: user.user_id,
+            username: user.username,
+            is_admin: true,
+            exp: now - age + lifetime,
+            iat: now - age,
+            token_type: TokenType::Access,
+            sid: "test-session".to_string(),
+            iss: None,
+            aud: None,
+            groups: Vec::new(),
+            profile: None,
+            jti: None,
+        })
+    }
+
+    fn rolling_router() -> Router {
+        let mut config = test_config();
+        config.security.rolling_session = true;
+        protected_router(config)
+    }
+
+    #[tokio::test]
+    async fn test_rolling_session_reissues_after_halfway() {
+        let res = rolling_router()
+            .oneshot(request(
+                &access_token_aged(3000, 3600),
+                &refresh_token(),
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let set_cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(set_cookie.starts_with("bouncarr_token="));
+        // A full 24h lifetime from now, not the remaining 10 minutes
+        assert!(set_cookie.contains("Max-Age=86400") || set_cookie.contains("Max-Age=86399"));
+    }
+
+    #[tokio::test]
+    async fn test_rolling_session_keeps_token_before_halfway() {
+        let res = rolling_router()
+            .oneshot(request(
+                &access_token_aged(600, 3600),
+                &refresh_token(),
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rolling_session_bounded_by_refresh_token() {
+        let user = test_user_info();
+        let now = chrono::Utc::now().timestamp();
+        let refresh = sign_claims(&Claims {
+            sub: user.user_id,
+            username: user.username,
+            is_admin: true,
+            exp: now + 1800,
+            iat: now - 86400,
+            token_type: TokenType::Refresh,
+            sid: "test-session".to_string(),
+            iss: None,
+            aud: None,
+            groups: Vec::new(),
+            profile: None,
+            jti: None,
+        });
+
+        let res = rolling_router()
+            .oneshot(request(
+                &access_token_aged(3000, 3600),
+                &refresh,
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        let set_cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(set_cookie.contains("Max-Age=1800") || set_cookie.contains("Max-Age=1799"));
+    }
+
+    #[tokio::test]
+    async fn test_rolling_session_disabled_by_default() {
+        let res = protected_router(test_config())
+            .oneshot(request(
+                &access_token_aged(3000, 3600),
+                &refresh_token(),
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    fn query_token_request(token: &str) -> Request<Body> {
+        Request::builder()
+            .uri(format!("/sonarr/api?{}={}", QUERY_TOKEN_PARAM, token))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_query_token_ignored_without_cookies() {
+        let mut config = test_config();
+        config.security.allow_query_token = true;
+        config.security.disable_cookies = true;
+
+        let token = crate::test_support::access_token(true);
+        let res = protected_router(config)
+            .oneshot(query_token_request(&token))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_query_token_accepted_when_enabled() {
+        let mut config = test_config();
+        config.security.allow_query_token = true;
+
+        let (_guard, logs) = crate::test_support::capture_logs();
+        let token = crate::test_support::access_token(true);
+        let res = protected_router(config)
+            .oneshot(query_token_request(&token))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // The token is never logged
+        let logs = logs.contents();
+        assert!(logs.contains("_bouncarr_token=***"), "{}", logs);
+        assert!(!logs.contains(&token));
+    }
+
+    #[tokio::test]
+    async fn test_query_token_ignored_by_default() {
+        let res = protected_router(test_config())
+            .oneshot(query_token_request(&crate::test_support::access_token(
+                true,
+            )))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_idle_session_rejected_while_active_one_continues() {
+        let mut config = test_config();
+        config.security.idle_session_timeout_seconds = Some(600);
+        let state = test_state(config);
+        let app = protected_router_with_state(state.clone());
+
+        let user = test_user_info();
+        let token_for = |sid: &str| state.jwt_manager.create_access_token(&user, sid).unwrap();
+        let idle = state.sessions.create(&user.user_id, None);
+        let active = state.sessions.create(&user.user_id, None);
+        let (idle_token, active_token) = (token_for(&idle), token_for(&active));
+
+        // The active session is used regularly, the idle one not at all
+        for _ in 0..3 {
+            state
+                .sessions
+                .backdate(&active, std::time::Duration::from_secs(400));
+            let res = app
+                .clone()
+                .oneshot(request(&active_token, "", "application/json"))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        state
+            .sessions
+            .backdate(&idle, std::time::Duration::from_secs(1200));
+        let res = app
+            .clone()
+            .oneshot(request(&idle_token, "", "application/json"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // The session stays revoked
+        let res = app
+            .oneshot(request(&idle_token, "", "text/html"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn test_per_app_require_admin() {
+        use crate::test_support::{access_token, spawn_upstream};
+
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream.clone();
+        let mut dashboard = config.arr_apps[0].clone();
+        dashboard.name = "dashboard".to_string();
+        dashboard.require_admin = Some(false);
+        dashboard.host = Some("dashboard.example.com".to_string());
+        config.arr_apps.push(dashboard);
+        config.server.allow_header_routing = true;
+        let state = test_state(config);
+        let app = Router::new()
+            .route("/bouncarr/api/config", get(|| async { "config" }))
+            .route(
+                "/:app/*path",
+                axum::routing::any(crate::proxy::proxy_handler),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            ))
+            .layer(CookieManagerLayer::new())
+            .with_state(state);
+
+        let status_with = |uri: &str, is_admin: bool, header: Option<(&'static str, &str)>| {
+            let mut req = Request::builder().uri(uri);
+            if let Some((name, value)) = header {
+                req = req.header(name, value);
+            }
+            let req = req
+                .header(header::ACCEPT, "application/json")
+                .header(
+                    header::COOKIE,
+                    format!("bouncarr_token={}", access_token(is_admin)),
+                )
+                .body(Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+        let status = |uri: &str, is_admin: bool| status_with(uri, is_admin, None);
+
+        assert_eq!(status("/dashboard/api", false).await, StatusCode::OK);
+        assert_eq!(status("/sonarr/api", false).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            status("/bouncarr/api/config", false).await,
+            StatusCode::FORBIDDEN
+        );
+        for uri in ["/dashboard/api", "/sonarr/api", "/bouncarr/api/config"] {
+            assert_eq!(status(uri, true).await, StatusCode::OK, "{}", uri);
+        }
+
+        // Naming the dashboard by host or header doesn't open Bouncarr's API
+        for header in [
+            ("host", "dashboard.example.com"),
+            ("x-bouncarr-app", "dashboard"),
+        ] {
+            assert_eq!(
+                status_with("/bouncarr/api/config", false, Some(header)).await,
+                StatusCode::FORBIDDEN,
+                "{:?}",
+                header
+            );
+            assert_eq!(
+                status_with("/bouncarr/api/config", true, Some(header)).await,
+                StatusCode::OK,
+                "{:?}",
+                header
+            );
+        }
+    }
+}
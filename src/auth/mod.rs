@@ -1,5 +1,10 @@
+pub mod admin_check;
+pub mod cookies;
 pub mod jwt;
+pub mod login_webhook;
 pub mod middleware;
+pub mod refresh_tokens;
+pub mod sessions;
 
 pub use jwt::JwtManager;
 pub use middleware::auth_middleware;
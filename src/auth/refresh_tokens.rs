@@ -0,0 +1,90 @@
+use crate::auth::jwt::Claims;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Refresh tokens issued by this instance, for `security.track_refresh_tokens`
+///
+/// Tokens are recorded by their `jti` with their session and expiry. A
+/// refresh token missing from the registry is rejected even if its signature
+/// is valid. The registry is not persisted, so a restart ends every session.
+#[derive(Default)]
+pub struct RefreshTokenRegistry {
+    tokens: Mutex<HashMap<String, Issued>>,
+}
+
+struct Issued {
+    sid: String,
+    exp: i64,
+}
+
+impl RefreshTokenRegistry {
+    /// Record a newly issued refresh token
+    pub fn record(&self, jti: &str, sid: &str, exp: i64) {
+        let now = chrono::Utc::now().timestamp();
+        let mut tokens = self.lock();
+        tokens.retain(|_, issued| issued.exp > now);
+        tokens.insert(
+            jti.to_string(),
+            Issued {
+                sid: sid.to_string(),
+                exp,
+            },
+        );
+    }
+
+    /// Whether a refresh token was issued here and not removed since
+    pub fn contains(&self, jti: &str) -> bool {
+        self.lock().contains_key(jti)
+    }
+
+    /// Whether the claims are those of a recorded refresh token
+    pub fn accepts(&self, claims: &Claims) -> bool {
+        claims.jti.as_deref().is_some_and(|jti| self.contains(jti))
+    }
+
+    /// Forget a refresh token, e.g. after it was rotated
+    pub fn remove(&self, jti: &str) {
+        self.lock().remove(jti);
+    }
+
+    /// Forget every refresh token of a session
+    pub fn remove_session(&self, sid: &str) {
+        self.lock().retain(|_, issued| issued.sid != sid);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Issued>> {
+        self.tokens.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_remove() {
+        let registry = RefreshTokenRegistry::default();
+        let exp = chrono::Utc::now().timestamp() + 3600;
+        registry.record("a", "session-1", exp);
+        registry.record("b", "session-1", exp);
+        registry.record("c", "session-2", exp);
+        assert!(registry.contains("a"));
+        assert!(!registry.contains("unknown"));
+
+        registry.remove("a");
+        assert!(!registry.contains("a"));
+        registry.remove_session("session-1");
+        assert!(!registry.contains("b"));
+        assert!(registry.contains("c"));
+    }
+
+    #[test]
+    fn test_expired_tokens_are_pruned() {
+        let registry = RefreshTokenRegistry::default();
+        let now = chrono::Utc::now().timestamp();
+        registry.record("old", "session-1", now - 1);
+        registry.record("new", "session-1", now + 3600);
+        assert!(!registry.contains("old"));
+        assert!(registry.contains("new"));
+    }
+}
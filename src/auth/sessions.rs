@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often expired sessions are dropped, at most
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Session {
+    last_seen: Instant,
+    revoked: bool,
+    /// Jellyfin user id, unknown for sessions adopted after a restart
+    user_id: Option<String>,
+    /// Jellyfin access token obtained at login, for calls on the user's behalf
+    jellyfin_token: Option<String>,
+}
+
+/// In-memory record of login sessions
+///
+/// Every token issued at login carries the session id in its `sid` claim.
+/// The store tracks when each session was last used so idle sessions can be
+/// revoked, and remembers revocations until the session could no longer be
+/// used anyway.
+///
+/// Sessions are not persisted: ones created before a restart are adopted on
+/// first use, without a Jellyfin token. Adoption trusts the session id, so
+/// only ids taken from tokens whose signature was verified may be passed in;
+/// an adopted session expires like any other.
+///
+/// Sessions unused for longer than `max_age` are dropped, checked at most
+/// every `PRUNE_INTERVAL` on any access. The Jellyfin tokens of dropped and
+/// idle-revoked sessions are set aside for `take_expired_jellyfin_tokens`,
+/// so their Jellyfin sessions can be ended too.
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, Session>>,
+    /// How long a session can live at most (the refresh token lifetime)
+    max_age: Duration,
+    last_pruned: Mutex<Instant>,
+    expired_jellyfin_tokens: Mutex<Vec<String>>,
+}
+
+impl SessionStore {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            max_age,
+            last_pruned: Mutex::new(Instant::now()),
+            expired_jellyfin_tokens: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Start a new session, returning its id
+    ///
+    /// The Jellyfin token, if any, is kept until the session is revoked or
+    /// expires.
+    pub fn create(&self, user_id: &str, jellyfin_token: Option<String>) -> String {
+        let sid = generate_session_id();
+        let mut sessions = self.lock();
+        let now = Instant::now();
+        self.prune(&mut sessions, now);
+        sessions.insert(
+            sid.clone(),
+            Session {
+                last_seen: now,
+                revoked: false,
+                user_id: Some(user_id.to_string()),
+                jellyfin_token,
+            },
+        );
+        sid
+    }
+
+    /// Record activity on a session
+    ///
+    /// Returns false if the session was revoked, or has been idle for longer
+    /// than `idle_timeout`, in which case it is revoked now. An unknown `sid`
+    /// is adopted, so it must come from a verified token.
+    pub fn touch(&self, sid: &str, idle_timeout: Option<Duration>) -> bool {
+        self.check(sid, idle_timeout, true)
+    }
+
+    /// Whether a session may still be used, without recording activity
+    pub fn is_active(&self, sid: &str, idle_timeout: Option<Duration>) -> bool {
+        self.check(sid, idle_timeout, false)
+    }
+
+    /// Jellyfin token stored for an active session
+    pub fn jellyfin_token(&self, sid: &str) -> Option<String> {
+        self.lock()
+            .get(sid)
+            .filter(|session| !session.revoked)
+            .and_then(|session| session.jellyfin_token.clone())
+    }
+
+    /// Revoke a session so none of its tokens are accepted anymore
+    ///
+    /// Returns the session's Jellyfin token, which is no longer kept.
+    pub fn revoke(&self, sid: &str) -> Option<String> {
+        if sid.is_empty() {
+            return None;
+        }
+        let mut sessions = self.lock();
+        let session = sessions.entry(sid.to_string()).or_insert(Session {
+            last_seen: Instant::now(),
+            revoked: true,
+            user_id: None,
+            jellyfin_token: None,
+        });
+        session.revoked = true;
+        session.jellyfin_token.take()
+    }
+
+    /// Revoke every session of a user, returning how many were active and
+    /// their Jellyfin tokens, which are no longer kept
+    ///
+    /// Sessions adopted after a restart are not known to belong to anyone
+    /// and are left alone.
+    pub fn revoke_user(&self, user_id: &str) -> (usize, Vec<String>) {
+        let mut revoked = 0;
+        let mut jellyfin_tokens = Vec::new();
+        for session in self.lock().values_mut() {
+            if !session.revoked && session.user_id.as_deref() == Some(user_id) {
+                session.revoked = true;
+                jellyfin_tokens.extend(session.jellyfin_token.take());
+                revoked += 1;
+            }
+        }
+        (revoked, jellyfin_tokens)
+    }
+
+    /// Jellyfin tokens of sessions that expired or were revoked for being
+    /// idle since the last call, after dropping expired sessions if due
+    pub fn take_expired_jellyfin_tokens(&self) -> Vec<String> {
+        self.prune(&mut self.lock(), Instant::now());
+        std::mem::take(&mut *self.expired_tokens())
+    }
+
+    fn check(&self, sid: &str, idle_timeout: Option<Duration>, touch: bool) -> bool {
+        // Tokens issued before sessions were tracked have no session id
+        if sid.is_empty() {
+            return true;
+        }
+
+        let mut sessions = self.lock();
+        let now = Instant::now();
+        self.prune(&mut sessions, now);
+        let session = sessions.entry(sid.to_string()).or_insert(Session {
+            last_seen: now,
+            revoked: false,
+            user_id: None,
+            jellyfin_token: None,
+        });
+
+        if session.revoked {
+            return false;
+        }
+        if let Some(timeout) = idle_timeout
+            && now.duration_since(session.last_seen) > timeout
+        {
+            tracing::debug!("Revoking session idle for over {:?}", timeout);
+            session.revoked = true;
+            self.expired_tokens().extend(session.jellyfin_token.take());
+            return false;
+        }
+
+        if touch {
+            session.last_seen = now;
+        }
+        true
+    }
+
+    /// Drop sessions past `max_age`, if the last pass was a while ago
+    fn prune(&self, sessions: &mut HashMap<String, Session>, now: Instant) {
+        let mut last_pruned = self.last_pruned.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(*last_pruned) < PRUNE_INTERVAL.min(self.max_age) {
+            return;
+        }
+        *last_pruned = now;
+        let mut expired_tokens = self.expired_tokens();
+        sessions.retain(|_, session| {
+            let keep = now.duration_since(session.last_seen) < self.max_age;
+            if !keep {
+                expired_tokens.extend(session.jellyfin_token.take());
+            }
+            keep
+        });
+    }
+
+    fn expired_tokens(&self) -> std::sync::MutexGuard<'_, Vec<String>> {
+        self.expired_jellyfin_tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Pretend a session was last used `idle` ago
+    #[cfg(test)]
+    pub fn backdate(&self, sid: &str, idle: Duration) {
+        if let Some(session) = self.lock().get_mut(sid) {
+            session.last_seen = Instant::now() - idle;
+        }
+    }
+}
+
+fn generate_session_id() -> String {
+    use base64::Engine;
+    use rand::Rng;
+
+    let bytes: [u8; 16] = rand::thread_rng().r#gen();
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> SessionStore {
+        SessionStore::new(Duration::from_secs(86400))
+    }
+
+    #[test]
+    fn test_active_session_stays_alive() {
+        let store = store();
+        let sid = store.create("user-1", None);
+        let timeout = Some(Duration::from_secs(600));
+
+        store.backdate(&sid, Duration::from_secs(300));
+        assert!(store.touch(&sid, timeout));
+        // Touching reset the idle clock
+        store.backdate(&sid, Duration::from_secs(300));
+        assert!(store.touch(&sid, timeout));
+    }
+
+    #[test]
+    fn test_idle_session_is_revoked() {
+        let store = store();
+        let sid = store.create("user-1", None);
+        let timeout = Some(Duration::from_secs(600));
+
+        store.backdate(&sid, Duration::from_secs(601));
+        assert!(!store.touch(&sid, timeout));
+        // Revocation sticks even without an idle timeout
+        assert!(!store.is_active(&sid, None));
+    }
+
+    #[test]
+    fn test_no_idle_timeout_by_default() {
+        let store = store();
+        let sid = store.create("user-1", None);
+        store.backdate(&sid, Duration::from_secs(86399));
+        assert!(store.touch(&sid, None));
+    }
+
+    #[test]
+    fn test_revoke_and_unknown_sessions() {
+        let store = store();
+        let sid = store.create("user-1", None);
+        store.revoke(&sid);
+        assert!(!store.touch(&sid, None));
+
+        // Sessions from before a restart are adopted
+        assert!(store.touch("from-before-restart", None));
+        assert!(store.touch("", None));
+    }
+
+    #[test]
+    fn test_jellyfin_token_cleared_on_revoke() {
+        let store = store();
+        let sid = store.create("user-1", Some("jf-token".to_string()));
+        assert_eq!(store.jellyfin_token(&sid).as_deref(), Some("jf-token"));
+
+        assert_eq!(store.revoke(&sid).as_deref(), Some("jf-token"));
+        assert_eq!(store.jellyfin_token(&sid), None);
+    }
+
+    #[test]
+    fn test_revoke_user_ends_only_their_sessions() {
+        let store = store();
+        let first = store.create("user-1", Some("jf-first".to_string()));
+        let second = store.create("user-1", None);
+        let other = store.create("user-2", None);
+        assert!(store.touch("from-before-restart", None));
+
+        assert_eq!(
+            store.revoke_user("user-1"),
+            (2, vec!["jf-first".to_string()])
+        );
+        assert!(!store.is_active(&first, None));
+        assert!(!store.is_active(&second, None));
+        assert!(store.is_active(&other, None));
+        assert!(store.is_active("from-before-restart", None));
+        assert_eq!(store.revoke_user("user-1"), (0, Vec::new()));
+    }
+
+    #[test]
+    fn test_expired_jellyfin_tokens_set_aside() {
+        let store = SessionStore::new(Duration::from_millis(20));
+        let idle = store.create("user-1", Some("jf-idle".to_string()));
+        store.create("user-1", Some("jf-old".to_string()));
+        let revoked = store.create("user-1", Some("jf-logged-out".to_string()));
+        store.revoke(&revoked);
+
+        store.backdate(&idle, Duration::from_millis(10));
+        assert!(!store.is_active(&idle, Some(Duration::from_millis(5))));
+        assert_eq!(store.take_expired_jellyfin_tokens(), vec!["jf-idle"]);
+        assert!(store.take_expired_jellyfin_tokens().is_empty());
+
+        // Logged out sessions handed their token over already
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(store.take_expired_jellyfin_tokens(), vec!["jf-old"]);
+    }
+
+    #[test]
+    fn test_expired_sessions_pruned_on_use() {
+        let store = SessionStore::new(Duration::from_millis(20));
+        let old = store.create("user-1", None);
+        store.revoke("revoked-before-expiry");
+        std::thread::sleep(Duration::from_millis(30));
+
+        // Checking any session drops the expired ones
+        assert!(store.is_active("another", None));
+        let sessions = store.lock();
+        assert!(!sessions.contains_key(&old));
+        assert!(!sessions.contains_key("revoked-before-expiry"));
+        assert_eq!(sessions.len(), 1);
+    }
+}
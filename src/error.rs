@@ -1,10 +1,15 @@
 use axum::{
     Json,
-    http::StatusCode,
+    http::{HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use serde_json::json;
 
+/// Marks responses generated by Bouncarr itself rather than passed through
+/// from an upstream, so they can be replaced with a custom error page
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratedError;
+
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
     #[error("Configuration error: {0}")]
@@ -31,15 +36,56 @@ pub enum AppError {
     #[error("Proxy error: {0}")]
     ProxyError(String),
 
+    #[error("Upstream timeout: {0}")]
+    UpstreamTimeout(String),
+
+    #[error("Upstream unavailable: {0}")]
+    UpstreamUnavailable(String),
+
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
+    #[error("Method not allowed")]
+    MethodNotAllowed,
+
+    #[error("Unsupported method: {0}")]
+    UnsupportedMethod(String),
+
+    #[error("Under maintenance")]
+    Maintenance,
+
+    #[error("Starting up")]
+    StartingUp,
+
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+
+    /// Over an app's `rate_limit`, with the seconds until a retry may succeed
+    #[error("Rate limit exceeded")]
+    RateLimited(u64),
+
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
+
     #[error("App not found: {0}")]
     AppNotFound(String),
 
+    #[error("Not implemented: {0}")]
+    NotImplemented(String),
+
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
     #[error("Internal server error: {0}")]
     Internal(#[from] anyhow::Error),
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let retry_after = match self {
+            AppError::RateLimited(seconds) => Some(seconds),
+            _ => None,
+        };
         let (status, message) = match self {
             AppError::AuthenticationFailed(msg) => (StatusCode::UNAUTHORIZED, msg),
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
@@ -47,7 +93,31 @@ impl IntoResponse for AppError {
             AppError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token".to_string()),
             AppError::JwtError(e) => (StatusCode::UNAUTHORIZED, e.to_string()),
             AppError::ProxyError(msg) => (StatusCode::BAD_GATEWAY, msg),
+            AppError::UpstreamTimeout(msg) => (StatusCode::GATEWAY_TIMEOUT, msg),
+            AppError::UpstreamUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
+            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::AccessDenied(msg) => (StatusCode::FORBIDDEN, msg),
+            AppError::TooManyRequests(msg) => (StatusCode::TOO_MANY_REQUESTS, msg),
+            AppError::RateLimited(_) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Rate limit exceeded".to_string(),
+            ),
+            AppError::Maintenance => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Bouncarr is under maintenance".to_string(),
+            ),
+            AppError::StartingUp => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Bouncarr is starting up, waiting for Jellyfin".to_string(),
+            ),
+            AppError::MethodNotAllowed => (
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Method not allowed".to_string(),
+            ),
+            AppError::UnsupportedMethod(msg) => (StatusCode::METHOD_NOT_ALLOWED, msg),
             AppError::AppNotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            AppError::NotImplemented(msg) => (StatusCode::NOT_IMPLEMENTED, msg),
+            AppError::PayloadTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, msg),
             AppError::Config(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             AppError::RequestFailed(e) => (StatusCode::BAD_GATEWAY, e.to_string()),
             AppError::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
@@ -57,7 +127,14 @@ impl IntoResponse for AppError {
             "error": message,
         }));
 
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        response.extensions_mut().insert(GeneratedError);
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
     }
 }
 
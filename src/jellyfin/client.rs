@@ -20,7 +20,10 @@ impl JellyfinClient {
     /// # Errors
     ///
     /// Returns error if HTTP client creation fails
-    pub fn new(config: JellyfinConfig, timeout_seconds: i64) -> Result<Self> {
+    pub fn new(mut config: JellyfinConfig, timeout_seconds: i64) -> Result<Self> {
+        // Endpoint paths are appended with a leading slash
+        config.url = config.url.trim_end_matches('/').to_string();
+
         let mut client_builder = reqwest::Client::builder();
         if timeout_seconds > 0 {
             client_builder =
@@ -100,6 +103,12 @@ impl JellyfinClient {
             .send()
             .await?;
 
+        if response.status().is_server_error() {
+            return Err(AppError::UpstreamUnavailable(format!(
+                "Jellyfin returned status {}",
+                response.status()
+            )));
+        }
         if !response.status().is_success() {
             return Err(AppError::AuthenticationFailed(
                 "Failed to fetch user from Jellyfin".to_string(),
@@ -110,6 +119,79 @@ impl JellyfinClient {
         Ok(user.into())
     }
 
+    /// Get the user that owns a Jellyfin access token
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - Jellyfin access token issued to the user
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the token is rejected or network error occurs
+    pub async fn get_me(&self, token: &str) -> Result<UserInfo> {
+        let response = self
+            .user_request(reqwest::Method::GET, "/Users/Me", token)
+            .send()
+            .await?;
+
+        if response.status().is_server_error() {
+            return Err(AppError::UpstreamUnavailable(format!(
+                "Jellyfin returned status {}",
+                response.status()
+            )));
+        }
+        if !response.status().is_success() {
+            return Err(AppError::AuthenticationFailed(format!(
+                "Jellyfin rejected access token with status {}",
+                response.status()
+            )));
+        }
+
+        let user: User = response.json().await?;
+        Ok(user.into())
+    }
+
+    /// Check that Jellyfin is up and answering requests
+    ///
+    /// # Errors
+    ///
+    /// Returns error if Jellyfin answers with an error status or cannot be
+    /// reached
+    pub async fn ping(&self) -> Result<()> {
+        let url = format!("{}/System/Info/Public", self.config.url);
+        self.client.get(&url).send().await?.error_for_status()?;
+
+        Ok(())
+    }
+
+    /// End the Jellyfin session behind a user's access token
+    ///
+    /// # Errors
+    ///
+    /// Returns error if Jellyfin rejects the request or network error occurs
+    pub async fn logout(&self, token: &str) -> Result<()> {
+        self.user_request(reqwest::Method::POST, "/Sessions/Logout", token)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Start a request to Jellyfin authenticated as a user, with the access
+    /// token Jellyfin issued to them
+    pub fn user_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        token: &str,
+    ) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.config.url, path))
+            .header("X-Emby-Authorization", self.build_auth_header())
+            .header("X-MediaBrowser-Token", token)
+    }
+
     fn build_auth_header(&self) -> String {
         format!(
             r#"MediaBrowser Client="Bouncarr", Device="Bouncarr", DeviceId="bouncarr-1", Version="{}""#,
@@ -117,3 +199,71 @@ impl JellyfinClient {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{jellyfin_user, spawn_upstream};
+    use axum::{Json, Router, http::HeaderMap, http::StatusCode, routing::get};
+
+    /// Mock Jellyfin that only accepts "valid-token" on `/Users/Me`
+    async fn spawn_users_me() -> JellyfinClient {
+        let router = Router::new().route(
+            "/Users/Me",
+            get(|headers: HeaderMap| async move {
+                match headers.get("X-MediaBrowser-Token") {
+                    Some(token) if token == "valid-token" => {
+                        Ok(Json(jellyfin_user("user-1", "alice", true)))
+                    }
+                    _ => Err(StatusCode::UNAUTHORIZED),
+                }
+            }),
+        );
+        let config = JellyfinConfig {
+            url: spawn_upstream(router).await,
+            api_key: "test-api-key".to_string(),
+            wait_for_ready: false,
+            ready_timeout_seconds: 120,
+        };
+        JellyfinClient::new(config, -1).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slash_in_url() {
+        for url in ["http://jellyfin:8096", "http://jellyfin:8096/"] {
+            let config = JellyfinConfig {
+                url: url.to_string(),
+                api_key: "test-api-key".to_string(),
+                wait_for_ready: false,
+                ready_timeout_seconds: 120,
+            };
+            let client = JellyfinClient::new(config, -1).unwrap();
+            let request = client
+                .user_request(reqwest::Method::GET, "/Users/Me", "token")
+                .build()
+                .unwrap();
+            assert_eq!(request.url().as_str(), "http://jellyfin:8096/Users/Me");
+        }
+
+        let mut client = spawn_users_me().await;
+        client.config.url.push('/');
+        let client = JellyfinClient::new(client.config, -1).unwrap();
+        assert!(client.get_me("valid-token").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_me_valid_token() {
+        let client = spawn_users_me().await;
+        let user = client.get_me("valid-token").await.unwrap();
+        assert_eq!(user.user_id, "user-1");
+        assert_eq!(user.username, "alice");
+        assert!(user.is_administrator);
+    }
+
+    #[tokio::test]
+    async fn test_get_me_invalid_token() {
+        let client = spawn_users_me().await;
+        let result = client.get_me("wrong-token").await;
+        assert!(matches!(result, Err(AppError::AuthenticationFailed(_))));
+    }
+}
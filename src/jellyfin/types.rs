@@ -24,6 +24,9 @@ pub struct User {
     pub name: String,
     #[serde(rename = "Policy")]
     pub policy: UserPolicy,
+    /// Group or role names, for setups (e.g. via plugins) that report them
+    #[serde(rename = "Groups", default)]
+    pub groups: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +40,17 @@ pub struct UserInfo {
     pub user_id: String,
     pub username: String,
     pub is_administrator: bool,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+impl UserInfo {
+    /// Whether the user is in any of the given groups (case-insensitive)
+    pub fn in_any_group(&self, groups: &[String]) -> bool {
+        self.groups
+            .iter()
+            .any(|group| groups.iter().any(|g| g.eq_ignore_ascii_case(group)))
+    }
 }
 
 impl From<User> for UserInfo {
@@ -45,6 +59,28 @@ impl From<User> for UserInfo {
             user_id: user.id,
             username: user.name,
             is_administrator: user.policy.is_administrator,
+            groups: user.groups,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_groups() {
+        let mut payload = crate::test_support::jellyfin_user("user-1", "alice", true);
+        let user: UserInfo = serde_json::from_value::<User>(payload.clone())
+            .unwrap()
+            .into();
+        assert!(user.groups.is_empty());
+
+        payload["Groups"] = serde_json::json!(["Media-Admins", "family"]);
+        let user: UserInfo = serde_json::from_value::<User>(payload).unwrap().into();
+        assert_eq!(user.groups, ["Media-Admins", "family"]);
+        assert!(user.in_any_group(&["media-admins".to_string()]));
+        assert!(!user.in_any_group(&["guests".to_string()]));
+        assert!(!user.in_any_group(&[]));
+    }
+}
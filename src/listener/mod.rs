@@ -0,0 +1,227 @@
+pub mod proxy_protocol;
+pub mod startup_wait;
+pub mod tls;
+
+use axum::{Router, extract::ConnectInfo};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{
+        conn::auto::Builder,
+        graceful::{GracefulShutdown, Watcher},
+    },
+    service::TowerToHyperService,
+};
+use std::future::Future;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+/// How long a client may take to send its PROXY protocol header
+const PROXY_HEADER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long a client may take to complete the TLS handshake
+const TLS_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Pause after an accept error other than a dropped connection, e.g. running
+/// out of file descriptors, so it isn't retried in a busy loop
+const ACCEPT_ERROR_BACKOFF: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Serve the application on the given listener until `signal` resolves
+///
+/// Each request gets a `ConnectInfo<SocketAddr>` extension with the client
+/// address. When `proxy_protocol` is enabled, every connection must start with
+/// a PROXY protocol (v1 or v2) header and the address it carries is used
+/// instead of the TCP peer address. With a TLS acceptor, connections are
+/// TLS-terminated after the PROXY header.
+///
+/// After the signal fires, no new connections are accepted and in-flight
+/// connections are given the chance to finish before returning.
+pub async fn serve<F>(
+    listener: TcpListener,
+    app: Router,
+    proxy_protocol: bool,
+    tls: Option<TlsAcceptor>,
+    signal: F,
+) where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(signal);
+
+    loop {
+        let (mut stream, peer_addr) = tokio::select! {
+            result = listener.accept() => match result {
+                Ok(conn) => conn,
+                Err(e) if is_connection_error(&e) => continue,
+                Err(e) => {
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                    continue;
+                }
+            },
+            _ = &mut signal => break,
+        };
+
+        let app = app.clone();
+        let tls = tls.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let client_addr = if proxy_protocol {
+                let header = tokio::time::timeout(
+                    PROXY_HEADER_TIMEOUT,
+                    proxy_protocol::read_header(&mut stream),
+                )
+                .await;
+                match header {
+                    Ok(Ok(addr)) => addr.unwrap_or(peer_addr),
+                    Ok(Err(e)) => {
+                        tracing::warn!("Rejecting connection from {}: {}", peer_addr, e);
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::warn!("Timed out waiting for PROXY header from {}", peer_addr);
+                        return;
+                    }
+                }
+            } else {
+                peer_addr
+            };
+
+            let Some(tls) = tls else {
+                serve_connection(stream, client_addr, app, watcher).await;
+                return;
+            };
+            match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls.accept(stream)).await {
+                Ok(Ok(stream)) => serve_connection(stream, client_addr, app, watcher).await,
+                Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", client_addr, e),
+                Err(_) => {
+                    tracing::debug!("Timed out waiting for TLS handshake from {}", client_addr)
+                }
+            }
+        });
+    }
+
+    drop(listener);
+    graceful.shutdown().await;
+}
+
+/// Errors only affecting the connection being accepted, which can be
+/// retried right away
+fn is_connection_error(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::ConnectionReset
+    )
+}
+
+async fn serve_connection<S>(stream: S, client_addr: SocketAddr, app: Router, watcher: Watcher)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = tower::service_fn(move |mut req: axum::http::Request<Incoming>| {
+        req.extensions_mut().insert(ConnectInfo(client_addr));
+        app.clone().oneshot(req)
+    });
+
+    let builder = Builder::new(TokioExecutor::new());
+    let conn = builder
+        .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service));
+
+    if let Err(e) = watcher.watch(conn).await {
+        tracing::debug!("Connection from {} closed with error: {}", client_addr, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn spawn_server(proxy_protocol: bool) -> SocketAddr {
+        let app = Router::new().route(
+            "/ip",
+            get(|ConnectInfo(addr): ConnectInfo<SocketAddr>| async move { addr.to_string() }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(
+            listener,
+            app,
+            proxy_protocol,
+            None,
+            std::future::pending(),
+        ));
+        addr
+    }
+
+    async fn send_raw(addr: SocketAddr, bytes: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(bytes).await.unwrap();
+        // A rejected connection may be reset rather than closed cleanly
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).await;
+        response
+    }
+
+    const REQUEST: &[u8] = b"GET /ip HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n";
+
+    #[tokio::test]
+    async fn test_proxy_protocol_v1_recovers_client_ip() {
+        let addr = spawn_server(true).await;
+        let mut bytes = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 3000\r\n".to_vec();
+        bytes.extend_from_slice(REQUEST);
+
+        let response = send_raw(addr, &bytes).await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("203.0.113.7:51234"));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_protocol_v2_recovers_client_ip() {
+        let addr = spawn_server(true).await;
+        let mut bytes = vec![
+            0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54,
+            0x0A, // signature
+            0x21, 0x11, 0x00, 0x0C, // v2 PROXY, TCP over IPv4, 12 bytes
+            198, 51, 100, 23, 10, 0, 0, 1, 0xC3, 0x50, 0x0B, 0xB8,
+        ];
+        bytes.extend_from_slice(REQUEST);
+
+        let response = send_raw(addr, &bytes).await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("198.51.100.23:50000"));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_protocol_rejects_missing_header() {
+        let addr = spawn_server(true).await;
+        let response = send_raw(addr, REQUEST).await;
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_peer_addr_used_when_disabled() {
+        let addr = spawn_server(false).await;
+        let response = send_raw(addr, REQUEST).await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("127.0.0.1:"));
+    }
+
+    #[test]
+    fn test_accept_errors_backing_off() {
+        use std::io::{Error, ErrorKind};
+
+        assert!(is_connection_error(&Error::from(
+            ErrorKind::ConnectionAborted
+        )));
+        // EMFILE: out of file descriptors
+        assert!(!is_connection_error(&Error::from_raw_os_error(24)));
+    }
+}
@@ -0,0 +1,246 @@
+//! Parser for the HAProxy PROXY protocol (v1 and v2)
+//!
+//! See <https://www.haproxy.org/download/2.8/doc/proxy-protocol.txt> for the
+//! specification. Only the address information is extracted; TLVs in v2
+//! headers are read and discarded.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Signature that starts every v2 header
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+/// Maximum length of a v1 header including the trailing CRLF
+const V1_MAX_LEN: usize = 107;
+
+/// Read a PROXY protocol header from the start of a connection
+///
+/// Consumes exactly the header bytes so the remaining stream can be handed
+/// to the HTTP server untouched.
+///
+/// # Returns
+///
+/// Returns the original client address, or `None` when the header does not
+/// carry one (v1 `UNKNOWN`, v2 `LOCAL` command or unsupported family). In
+/// that case the caller should fall back to the TCP peer address.
+///
+/// # Errors
+///
+/// Returns error if the stream does not start with a valid PROXY header
+pub async fn read_header<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<SocketAddr>> {
+    // Both versions are at least 12 bytes long ("PROXY UNKNOWN\r\n" is 15)
+    let mut prefix = [0u8; 12];
+    reader.read_exact(&mut prefix).await?;
+
+    if prefix == V2_SIGNATURE {
+        read_v2(reader).await
+    } else if prefix.starts_with(b"PROXY ") {
+        read_v1(reader, &prefix).await
+    } else {
+        Err(invalid("missing PROXY protocol header"))
+    }
+}
+
+async fn read_v1<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    prefix: &[u8],
+) -> io::Result<Option<SocketAddr>> {
+    let mut line = prefix.to_vec();
+
+    // Read byte by byte so we never consume data past the header
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Err(invalid("PROXY v1 header too long"));
+        }
+        line.push(reader.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY v1 header is not valid ASCII"))?;
+    parse_v1(line)
+}
+
+/// Parse a v1 header line (without the trailing CRLF)
+fn parse_v1(line: &str) -> io::Result<Option<SocketAddr>> {
+    let mut parts = line.split(' ');
+    if parts.next() != Some("PROXY") {
+        return Err(invalid("PROXY v1 header must start with 'PROXY'"));
+    }
+
+    match parts.next() {
+        Some("UNKNOWN") => Ok(None),
+        Some("TCP4") | Some("TCP6") => {
+            let src_ip: IpAddr = parts
+                .next()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| invalid("PROXY v1 header has invalid source address"))?;
+            // Destination address is required by the spec but unused here
+            parts
+                .next()
+                .and_then(|s| s.parse::<IpAddr>().ok())
+                .ok_or_else(|| invalid("PROXY v1 header has invalid destination address"))?;
+            let src_port: u16 = parts
+                .next()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| invalid("PROXY v1 header has invalid source port"))?;
+
+            Ok(Some(SocketAddr::new(src_ip, src_port)))
+        }
+        _ => Err(invalid("PROXY v1 header has unsupported protocol")),
+    }
+}
+
+async fn read_v2<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut meta = [0u8; 4];
+    reader.read_exact(&mut meta).await?;
+
+    let version = meta[0] >> 4;
+    let command = meta[0] & 0x0F;
+    let family = meta[1];
+    let len = u16::from_be_bytes([meta[2], meta[3]]) as usize;
+
+    if version != 2 {
+        return Err(invalid("PROXY v2 header has unsupported version"));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+
+    match command {
+        // LOCAL: health checks from the proxy itself, no client address
+        0x0 => Ok(None),
+        0x1 => parse_v2_addresses(family, &payload),
+        _ => Err(invalid("PROXY v2 header has unsupported command")),
+    }
+}
+
+fn parse_v2_addresses(family: u8, payload: &[u8]) -> io::Result<Option<SocketAddr>> {
+    // High nibble is the address family, low nibble the transport protocol
+    match family >> 4 {
+        // AF_INET: src addr (4), dst addr (4), src port (2), dst port (2)
+        0x1 => {
+            if payload.len() < 12 {
+                return Err(invalid("PROXY v2 IPv4 address block too short"));
+            }
+            let ip = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+            let port = u16::from_be_bytes([payload[8], payload[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        // AF_INET6: src addr (16), dst addr (16), src port (2), dst port (2)
+        0x2 => {
+            if payload.len() < 36 {
+                return Err(invalid("PROXY v2 IPv6 address block too short"));
+            }
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&payload[0..16]);
+            let port = u16::from_be_bytes([payload[32], payload[33]]);
+            Ok(Some(SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(octets)),
+                port,
+            )))
+        }
+        // AF_UNSPEC / AF_UNIX carry no usable client IP
+        _ => Ok(None),
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v2_header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+        header.extend_from_slice(addresses);
+        header
+    }
+
+    #[tokio::test]
+    async fn test_v1_tcp4() {
+        let mut input: &[u8] = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 3000\r\nGET / HTTP/1.1\r\n";
+        let addr = read_header(&mut input).await.unwrap();
+        assert_eq!(addr, Some("203.0.113.7:51234".parse().unwrap()));
+        // The HTTP request must be left in the stream
+        assert_eq!(input, b"GET / HTTP/1.1\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_v1_tcp6() {
+        let mut input: &[u8] = b"PROXY TCP6 2001:db8::1 2001:db8::2 40000 443\r\n";
+        let addr = read_header(&mut input).await.unwrap();
+        assert_eq!(addr, Some("[2001:db8::1]:40000".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_v1_unknown() {
+        let mut input: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_header(&mut input).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_v1_invalid() {
+        let mut input: &[u8] = b"PROXY TCP4 not-an-ip 10.0.0.1 1 2\r\n";
+        assert!(read_header(&mut input).await.is_err());
+
+        let mut input: &[u8] = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        assert!(read_header(&mut input).await.is_err());
+
+        let long = format!("PROXY TCP4 {}\r\n", "1".repeat(200));
+        let mut input: &[u8] = long.as_bytes();
+        assert!(read_header(&mut input).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_v2_ipv4() {
+        let addresses = [
+            198, 51, 100, 23, // src addr
+            10, 0, 0, 1, // dst addr
+            0xC3, 0x50, // src port 50000
+            0x0B, 0xB8, // dst port 3000
+        ];
+        let mut bytes = v2_header(0x1, 0x11, &addresses);
+        bytes.extend_from_slice(b"GET /");
+        let mut input: &[u8] = &bytes;
+
+        let addr = read_header(&mut input).await.unwrap();
+        assert_eq!(addr, Some("198.51.100.23:50000".parse().unwrap()));
+        assert_eq!(input, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn test_v2_ipv6() {
+        let mut addresses = Vec::new();
+        addresses.extend_from_slice(&"2001:db8::42".parse::<Ipv6Addr>().unwrap().octets());
+        addresses.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        addresses.extend_from_slice(&8080u16.to_be_bytes());
+        addresses.extend_from_slice(&3000u16.to_be_bytes());
+        let bytes = v2_header(0x1, 0x21, &addresses);
+        let mut input: &[u8] = &bytes;
+
+        let addr = read_header(&mut input).await.unwrap();
+        assert_eq!(addr, Some("[2001:db8::42]:8080".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_v2_local_command() {
+        let bytes = v2_header(0x0, 0x00, &[]);
+        let mut input: &[u8] = &bytes;
+        assert_eq!(read_header(&mut input).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_v2_truncated() {
+        let bytes = v2_header(0x1, 0x11, &[1, 2, 3]);
+        let mut input: &[u8] = &bytes;
+        assert!(read_header(&mut input).await.is_err());
+    }
+}
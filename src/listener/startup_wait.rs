@@ -0,0 +1,118 @@
+use crate::config::StartupWait;
+use std::time::Duration;
+
+/// Poll the configured startup conditions every `interval` until all of them
+/// hold or `timeout_seconds` has passed
+///
+/// Returns whether the conditions were met; the caller binds either way.
+pub async fn wait(wait: &StartupWait, jellyfin_url: &str, interval: Duration) -> bool {
+    let max_wait = Duration::from_secs(wait.timeout_seconds);
+    tracing::info!("Waiting up to {:?} for startup dependencies", max_wait);
+
+    let poll = async {
+        loop {
+            match unmet_condition(wait, jellyfin_url).await {
+                None => return,
+                Some(reason) => tracing::info!("Still waiting before binding: {}", reason),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    };
+
+    match tokio::time::timeout(max_wait, poll).await {
+        Ok(()) => {
+            tracing::info!("Startup dependencies are ready");
+            true
+        }
+        Err(_) => {
+            tracing::warn!(
+                "Startup dependencies still not ready after {:?}, binding anyway",
+                max_wait
+            );
+            false
+        }
+    }
+}
+
+/// Describe the first condition that doesn't hold yet
+async fn unmet_condition(wait: &StartupWait, jellyfin_url: &str) -> Option<String> {
+    if wait.jellyfin_dns
+        && let Err(e) = resolve(jellyfin_url).await
+    {
+        return Some(format!("Jellyfin host does not resolve: {}", e));
+    }
+    if let Some(file) = &wait.file
+        && !std::path::Path::new(file).exists()
+    {
+        return Some(format!("{} does not exist", file));
+    }
+    None
+}
+
+/// Resolve the host of a URL to at least one address
+async fn resolve(url: &str) -> Result<(), String> {
+    let url = url::Url::parse(url).map_err(|e| e.to_string())?;
+    let host = url.host_str().ok_or("URL has no host")?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let mut addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| e.to_string())?;
+    match addrs.next() {
+        Some(_) => Ok(()),
+        None => Err("no addresses".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dns_wait(timeout_seconds: u64) -> StartupWait {
+        StartupWait {
+            jellyfin_dns: true,
+            file: None,
+            timeout_seconds,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolvable_host_is_ready() {
+        let ready = wait(
+            &dns_wait(5),
+            "http://localhost:8096",
+            Duration::from_millis(10),
+        )
+        .await;
+        assert!(ready);
+    }
+
+    #[tokio::test]
+    async fn test_unresolvable_host_times_out() {
+        let ready = wait(
+            &dns_wait(1),
+            "http://jellyfin.invalid:8096",
+            Duration::from_millis(100),
+        )
+        .await;
+        assert!(!ready);
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_file() {
+        let dir = crate::test_support::temp_dir();
+        let file = dir.path().join("ready");
+        let startup_wait = StartupWait {
+            jellyfin_dns: false,
+            file: Some(file.to_string_lossy().into_owned()),
+            timeout_seconds: 5,
+        };
+
+        let waiting = tokio::spawn(async move {
+            wait(&startup_wait, "http://localhost", Duration::from_millis(10)).await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        std::fs::write(&file, "").unwrap();
+        assert!(waiting.await.unwrap());
+    }
+}
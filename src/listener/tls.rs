@@ -0,0 +1,207 @@
+use crate::config::TlsConfig;
+use std::io;
+use std::sync::{Arc, RwLock};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::{
+    self,
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+
+/// Certificate and key served on TLS connections, swappable at runtime
+///
+/// New handshakes pick up a reloaded certificate. Established connections
+/// keep the one they were set up with.
+#[derive(Debug)]
+pub struct TlsCertificates {
+    config: TlsConfig,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl TlsCertificates {
+    /// Load the certificate chain and key named in the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns error if either file can't be read or parsed, or the key does
+    /// not belong to the certificate
+    pub fn load(config: &TlsConfig) -> io::Result<Self> {
+        Ok(Self {
+            current: RwLock::new(Arc::new(load_certified_key(config)?)),
+            config: config.clone(),
+        })
+    }
+
+    /// Re-read the certificate and key from disk and use them for new
+    /// connections
+    ///
+    /// The new pair is validated first; on error the current one stays in use.
+    pub fn reload(&self) -> io::Result<()> {
+        let key = load_certified_key(&self.config)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+        Ok(())
+    }
+
+    /// Build an acceptor that serves whatever certificate is current
+    pub fn acceptor(self: &Arc<Self>) -> TlsAcceptor {
+        let mut config =
+            rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .expect("default TLS versions are supported")
+                .with_no_client_auth()
+                .with_cert_resolver(self.clone());
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        TlsAcceptor::from(Arc::new(config))
+    }
+}
+
+impl ResolvesServerCert for TlsCertificates {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.current
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        )
+    }
+}
+
+fn load_certified_key(config: &TlsConfig) -> io::Result<CertifiedKey> {
+    let certs = CertificateDer::pem_file_iter(&config.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(format!("{}: {}", config.cert_path, e)))?;
+    if certs.is_empty() {
+        return Err(invalid(format!(
+            "{}: no certificates found",
+            config.cert_path
+        )));
+    }
+    let key = PrivateKeyDer::from_pem_file(&config.key_path)
+        .map_err(|e| invalid(format!("{}: {}", config.key_path, e)))?;
+
+    CertifiedKey::from_der(certs, key, &ring::default_provider())
+        .map_err(|e| invalid(format!("{}: {}", config.key_path, e)))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listener::serve;
+    use crate::test_support::temp_dir;
+    use axum::{Router, routing::get};
+    use std::path::Path;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::{TlsConnector, client::TlsStream};
+
+    const CA: &str = include_str!("testdata/ca.pem");
+    const OLD_CERT: &str = include_str!("testdata/old.pem");
+    const OLD_KEY: &str = include_str!("testdata/old.key");
+    const NEW_CERT: &str = include_str!("testdata/new.pem");
+    const NEW_KEY: &str = include_str!("testdata/new.key");
+
+    /// Certificate files in `dir`
+    fn write_certs(dir: &Path, cert: &str, key: &str) -> TlsConfig {
+        let config = TlsConfig {
+            cert_path: dir.join("cert.pem").to_string_lossy().into_owned(),
+            key_path: dir.join("key.pem").to_string_lossy().into_owned(),
+        };
+        std::fs::write(&config.cert_path, cert).unwrap();
+        std::fs::write(&config.key_path, key).unwrap();
+        config
+    }
+
+    fn der(pem: &str) -> CertificateDer<'static> {
+        CertificateDer::from_pem_slice(pem.as_bytes()).unwrap()
+    }
+
+    async fn connect(addr: std::net::SocketAddr) -> TlsStream<TcpStream> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(der(CA)).unwrap();
+        let config =
+            rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        TlsConnector::from(Arc::new(config))
+            .connect("localhost".try_into().unwrap(), stream)
+            .await
+            .unwrap()
+    }
+
+    fn served_cert(stream: &TlsStream<TcpStream>) -> CertificateDer<'static> {
+        stream.get_ref().1.peer_certificates().unwrap()[0].clone()
+    }
+
+    async fn send_request(stream: &mut TlsStream<TcpStream>) -> String {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = vec![0; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_reload_swaps_certificate_for_new_connections() {
+        let dir = temp_dir();
+        let config = write_certs(dir.path(), OLD_CERT, OLD_KEY);
+        let certs = Arc::new(TlsCertificates::load(&config).unwrap());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(|| async { "ok" }));
+        tokio::spawn(serve(
+            listener,
+            app,
+            false,
+            Some(certs.acceptor()),
+            std::future::pending(),
+        ));
+
+        let mut existing = connect(addr).await;
+        assert_eq!(served_cert(&existing), der(OLD_CERT));
+        assert!(
+            send_request(&mut existing)
+                .await
+                .starts_with("HTTP/1.1 200")
+        );
+
+        std::fs::write(&config.cert_path, NEW_CERT).unwrap();
+        std::fs::write(&config.key_path, NEW_KEY).unwrap();
+        certs.reload().unwrap();
+
+        let fresh = connect(addr).await;
+        assert_eq!(served_cert(&fresh), der(NEW_CERT));
+        // Connections made before the reload keep working
+        assert!(
+            send_request(&mut existing)
+                .await
+                .starts_with("HTTP/1.1 200")
+        );
+    }
+
+    #[test]
+    fn test_reload_rejects_mismatched_key() {
+        let dir = temp_dir();
+        let config = write_certs(dir.path(), OLD_CERT, OLD_KEY);
+        let certs = TlsCertificates::load(&config).unwrap();
+
+        // A renewed certificate without its new key is refused
+        std::fs::write(&config.cert_path, NEW_CERT).unwrap();
+        assert!(certs.reload().is_err());
+
+        let current = certs.current.read().unwrap().clone();
+        assert_eq!(current.cert[0], der(OLD_CERT));
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+use tower_http::trace::MakeSpan;
+use tracing_subscriber::{
+    EnvFilter, Registry, layer::SubscriberExt, reload, util::SubscriberInitExt,
+};
+
+/// Default filter when `RUST_LOG` is not set
+const DEFAULT_FILTER: &str = "bouncarr=info,tower_http=info";
+
+/// Handle for changing the active log filter at runtime
+#[derive(Clone)]
+pub struct LogFilterHandle {
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogFilterHandle {
+    /// Current filter directive
+    pub fn current(&self) -> String {
+        self.handle
+            .with_current(|filter| filter.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Parse and apply a new filter directive (e.g. `bouncarr=trace`)
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the directive is invalid; the active filter is left
+    /// unchanged in that case
+    pub fn set(&self, directive: &str) -> Result<(), String> {
+        let filter = EnvFilter::builder()
+            .parse(directive)
+            .map_err(|e| format!("Invalid filter directive '{}': {}", directive, e))?;
+        self.handle
+            .reload(filter)
+            .map_err(|e| format!("Failed to apply filter: {}", e))
+    }
+}
+
+/// Build the reloadable filter layer from `RUST_LOG` or the default filter
+fn filter_layer() -> (reload::Layer<EnvFilter, Registry>, LogFilterHandle) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| DEFAULT_FILTER.into());
+    let (layer, handle) = reload::Layer::new(filter);
+    (layer, LogFilterHandle { handle })
+}
+
+/// Install the global tracing subscriber
+pub fn init() -> LogFilterHandle {
+    let (filter, handle) = filter_layer();
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    handle
+}
+
+/// Request span for the HTTP trace layer, like tower-http's default but with
+/// sensitive query parameters redacted from the logged URI
+///
+/// The `user` field is filled in once the request is authenticated.
+#[derive(Clone)]
+pub struct RedactedMakeSpan {
+    params: Arc<[String]>,
+}
+
+impl RedactedMakeSpan {
+    pub fn new(params: &[String]) -> Self {
+        Self {
+            params: params.into(),
+        }
+    }
+}
+
+impl<B> MakeSpan<B> for RedactedMakeSpan {
+    fn make_span(&mut self, request: &http::Request<B>) -> tracing::Span {
+        tracing::debug_span!(
+            "request",
+            method = %request.method(),
+            uri = %crate::config::redact_query(&request.uri().to_string(), &self.params),
+            version = ?request.version(),
+            user = tracing::field::Empty,
+        )
+    }
+}
+
+/// Filter handle that is not attached to the global subscriber
+///
+/// The layer is leaked so the handle stays usable for the whole test.
+#[cfg(test)]
+pub fn detached_handle() -> LogFilterHandle {
+    let (layer, handle) = filter_layer();
+    std::mem::forget(layer);
+    handle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_span_redacts_query() {
+        let (_guard, logs) = crate::test_support::capture_logs();
+        let request = http::Request::get("/sonarr/feed?apikey=secret&page=2")
+            .body(())
+            .unwrap();
+        let span = RedactedMakeSpan::new(&["apikey".to_string()]).make_span(&request);
+        span.in_scope(|| tracing::debug!("inside request"));
+
+        let logs = logs.contents();
+        assert!(logs.contains("/sonarr/feed?apikey=***&page=2"), "{}", logs);
+        assert!(!logs.contains("secret"));
+    }
+
+    #[test]
+    fn test_set_valid_directive() {
+        let (_layer, handle) = filter_layer();
+        handle.set("bouncarr=trace").unwrap();
+        assert_eq!(handle.current(), "bouncarr=trace");
+    }
+
+    #[test]
+    fn test_set_invalid_directive_keeps_filter() {
+        let (_layer, handle) = filter_layer();
+        handle.set("bouncarr=debug").unwrap();
+        assert!(handle.set("bouncarr=notalevel").is_err());
+        assert_eq!(handle.current(), "bouncarr=debug");
+    }
+}
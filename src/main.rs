@@ -2,19 +2,37 @@ mod auth;
 mod config;
 mod error;
 mod jellyfin;
+mod listener;
+mod logging;
+mod metrics;
 mod proxy;
 mod routes;
+#[cfg(test)]
+mod test_support;
 
 use crate::auth::JwtManager;
+use crate::auth::admin_check::LiveAdminChecks;
+use crate::auth::refresh_tokens::RefreshTokenRegistry;
+use crate::auth::sessions::SessionStore;
 use crate::config::Config;
 use crate::jellyfin::JellyfinClient;
+use crate::logging::LogFilterHandle;
+use crate::metrics::Metrics;
+use crate::proxy::app_clients::AppClients;
+use crate::proxy::connection_limit::ConnectionLimiter;
+use crate::proxy::idempotency::IdempotencyCache;
+use crate::proxy::probe::UpstreamProbes;
+use crate::proxy::rate_limit::RateLimiter;
+use crate::proxy::user_agent::UserAgentFilter;
+use crate::routes::ErrorPages;
 use axum::{
     Router, middleware,
-    response::Redirect,
-    routing::{any, get, post},
+    routing::{MethodRouter, any, get, post},
 };
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use tower_cookies::CookieManagerLayer;
+use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 use tracing::info;
 
@@ -22,24 +40,36 @@ pub struct AppState {
     config: Config,
     jellyfin_client: JellyfinClient,
     jwt_manager: JwtManager,
+    sessions: SessionStore,
+    refresh_tokens: RefreshTokenRegistry,
     http_client: reqwest::Client,
+    metrics: Arc<Metrics>,
+    idempotency_cache: IdempotencyCache,
+    log_filter: LogFilterHandle,
+    maintenance: AtomicBool,
+    /// False while waiting for Jellyfin at startup
+    ready: AtomicBool,
+    user_agent_filter: UserAgentFilter,
+    connection_limiter: ConnectionLimiter,
+    rate_limiter: RateLimiter,
+    upstream_probes: UpstreamProbes,
+    error_pages: ErrorPages,
+    /// Clients for apps reached over a Unix socket or with a client certificate
+    app_clients: AppClients,
+    live_admin_checks: LiveAdminChecks,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "bouncarr=info,tower_http=info".into()),
-        )
-        .init();
+    let log_filter = logging::init();
 
     info!("Starting Bouncarr...");
 
     // Load configuration
     let config = Config::load()?;
     info!("Configuration loaded successfully");
+    log_effective_config(&config);
 
     // Create Jellyfin client
     let jellyfin_client = JellyfinClient::new(
@@ -64,33 +94,180 @@ async fn main() -> anyhow::Result<()> {
         info!("HTTP client timeout disabled (no timeout)");
     }
     let http_client = http_client_builder.build()?;
+    let app_clients = AppClients::build(&config).map_err(anyhow::Error::msg)?;
+
+    let user_agent_filter =
+        UserAgentFilter::new(&config.server.blocked_user_agents).map_err(anyhow::Error::msg)?;
+    let error_pages = ErrorPages::load(&config.server.error_pages).map_err(anyhow::Error::msg)?;
 
     // Create shared application state
     let state = Arc::new(AppState {
         config: config.clone(),
         jellyfin_client,
         jwt_manager,
+        sessions: SessionStore::new(config.security.refresh_token_lifetime()),
+        refresh_tokens: RefreshTokenRegistry::default(),
         http_client,
+        metrics: Arc::new(Metrics::default()),
+        idempotency_cache: IdempotencyCache::default(),
+        log_filter,
+        maintenance: AtomicBool::new(config.server.maintenance_mode),
+        ready: AtomicBool::new(!config.jellyfin.wait_for_ready),
+        user_agent_filter,
+        connection_limiter: ConnectionLimiter::default(),
+        rate_limiter: RateLimiter::default(),
+        upstream_probes: UpstreamProbes::default(),
+        error_pages,
+        app_clients,
+        live_admin_checks: LiveAdminChecks::default(),
     });
 
+    if config.server.warm_up_upstreams {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let reached = proxy::warm_up::warm_up(&state).await;
+            info!(
+                "Warmed up {} of {} apps",
+                reached.len(),
+                state.config.arr_apps.len()
+            );
+        });
+    }
+
+    tokio::spawn(routes::end_expired_jellyfin_sessions(
+        state.clone(),
+        auth::sessions::PRUNE_INTERVAL,
+    ));
+
+    if config.jellyfin.wait_for_ready {
+        info!(
+            "Waiting up to {}s for Jellyfin before accepting requests",
+            config.jellyfin.ready_timeout_seconds
+        );
+        tokio::spawn(routes::wait_for_jellyfin(
+            state.clone(),
+            std::time::Duration::from_secs(1),
+        ));
+    }
+
     // Build the application router
     let app = build_router(state.clone());
 
+    if let Some(startup_wait) = &config.server.startup_wait {
+        listener::startup_wait::wait(
+            startup_wait,
+            &config.jellyfin.url,
+            std::time::Duration::from_secs(1),
+        )
+        .await;
+    }
+
     // Start the server
     let addr = format!("{}:{}", config.server.host, config.server.port);
     info!("Listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let admin_listener = match config.server.admin_bind {
+        Some(admin_addr) => {
+            info!("Serving health and metrics on {}", admin_addr);
+            Some(tokio::net::TcpListener::bind(admin_addr).await?)
+        }
+        None => None,
+    };
 
-    // Graceful shutdown handler
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    if config.server.proxy_protocol {
+        info!("PROXY protocol enabled - expecting v1/v2 header on every connection");
+    }
+
+    let tls = match &config.server.tls {
+        Some(tls_config) => {
+            let certs = Arc::new(listener::tls::TlsCertificates::load(tls_config)?);
+            info!("TLS enabled with certificate {}", tls_config.cert_path);
+            reload_certificates_on_sighup(certs.clone());
+            Some(certs.acceptor())
+        }
+        None => None,
+    };
+
+    // Graceful shutdown handler, shared by both listeners
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(());
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        shutdown_tx.send(()).ok();
+    });
+    let shutdown = move || {
+        let mut shutdown_rx = shutdown_rx.clone();
+        async move {
+            shutdown_rx.changed().await.ok();
+        }
+    };
+
+    let admin = async {
+        if let Some(admin_listener) = admin_listener {
+            let admin_app = build_admin_router(state.clone());
+            listener::serve(admin_listener, admin_app, false, None, shutdown()).await;
+        }
+    };
+    let main = listener::serve(listener, app, config.server.proxy_protocol, tls, shutdown());
+    tokio::join!(main, admin);
 
     info!("Server shutdown complete");
     Ok(())
 }
 
+/// Summarize the resolved configuration at info level and dump it in full,
+/// with secrets redacted, at debug level
+fn log_effective_config(config: &Config) {
+    info!("Jellyfin: {}", config::redact_url(&config.jellyfin.url));
+    for app in &config.arr_apps {
+        info!("App '{}' -> {}", app.name, config::redact_url(&app.url));
+    }
+    info!(
+        "Upstream request timeout: {}",
+        match config.server.request_timeout_seconds {
+            t if t > 0 => format!("{}s", t),
+            _ => "disabled".to_string(),
+        }
+    );
+    info!("Resources: {}", config.resource_estimate());
+    if let Some(admin) = &config.security.bootstrap_admin {
+        tracing::warn!(
+            "security.bootstrap_admin is set: '{}' is treated as an administrator \
+            regardless of Jellyfin. Remove it once initial setup is done!",
+            admin
+        );
+    }
+    tracing::debug!("Effective configuration:\n{}", config.redacted());
+}
+
+/// Re-read the TLS certificate and key whenever SIGHUP is received, e.g.
+/// after a renewal
+///
+/// Invalid files are logged and the current certificate kept.
+fn reload_certificates_on_sighup(certs: Arc<listener::tls::TlsCertificates>) {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                tracing::warn!("Failed to install SIGHUP handler: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            match certs.reload() {
+                Ok(()) => info!("Reloaded TLS certificate"),
+                Err(e) => tracing::error!("Keeping current TLS certificate, reload failed: {}", e),
+            }
+        }
+    });
+
+    #[cfg(not(unix))]
+    let _ = certs;
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
@@ -119,24 +296,80 @@ async fn shutdown_signal() {
     }
 }
 
+/// Answer preflights on an auth endpoint and reject unregistered methods
+/// with a JSON 405 carrying an `Allow` header
+fn auth_route(route: MethodRouter<Arc<AppState>>) -> MethodRouter<Arc<AppState>> {
+    route
+        .options(routes::auth_options)
+        .fallback(routes::auth_method_not_allowed)
+}
+
+/// Health, readiness and metrics endpoints, served on the main port or on
+/// `server.admin_bind`
+fn monitoring_routes(state: &AppState) -> Router<Arc<AppState>> {
+    let mut routes = Router::new()
+        .route("/health", get(routes::health_check))
+        .route("/ready", get(routes::ready_check));
+    if state.config.metrics.enabled {
+        routes = routes.route("/metrics", get(routes::metrics));
+    }
+    routes
+}
+
+/// Router for the `server.admin_bind` listener, serving only monitoring
+/// endpoints
+fn build_admin_router(state: Arc<AppState>) -> Router {
+    monitoring_routes(&state)
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
+}
+
 fn build_router(state: Arc<AppState>) -> Router {
     // Public routes (no authentication required)
-    let public_routes = Router::new()
-        .route("/health", get(routes::health_check))
+    let mut public_routes = Router::new()
         .route("/bouncarr/login", get(routes::serve_login_page))
-        .route("/bouncarr/api/auth/login", post(routes::login))
-        .route("/bouncarr/api/auth/refresh", post(routes::refresh))
-        .route("/bouncarr/api/auth/logout", post(routes::logout));
+        .route("/bouncarr/api/auth/login", auth_route(post(routes::login)))
+        .route(
+            "/bouncarr/api/auth/jellyfin-token",
+            auth_route(post(routes::jellyfin_token_login)),
+        )
+        .route(
+            "/bouncarr/api/auth/refresh",
+            auth_route(post(routes::refresh)),
+        )
+        .route(
+            "/bouncarr/api/auth/logout",
+            auth_route(post(routes::logout)),
+        );
+    if state.config.security.no_store_auth_responses {
+        public_routes = public_routes.layer(middleware::map_response(routes::no_store));
+    }
+
+    if state.config.server.admin_bind.is_none() {
+        public_routes = public_routes.merge(monitoring_routes(&state));
+    }
+
+    if let Some(dir) = &state.config.server.static_dir {
+        public_routes =
+            public_routes.nest_service(&state.config.server.static_path, ServeDir::new(dir));
+    }
 
     // Protected routes (authentication required)
     let protected_routes = Router::new()
+        .route(
+            "/bouncarr/api/loglevel",
+            get(routes::get_log_level).post(routes::set_log_level),
+        )
+        .route("/bouncarr/api/config", get(routes::get_config))
+        .route("/bouncarr/api/route-debug", get(routes::route_debug))
+        .route(
+            "/bouncarr/api/maintenance",
+            get(routes::get_maintenance).post(routes::set_maintenance),
+        )
         .route("/:app/*path", any(proxy::proxy_handler))
         .route("/:app/", any(proxy::proxy_handler))
         .route("/:app", any(proxy::proxy_handler))
-        .route(
-            "/",
-            get(|| async { Redirect::permanent("/bouncarr/login") }),
-        )
+        .route("/", any(proxy::handler::root_handler))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth::auth_middleware,
@@ -146,7 +379,207 @@ fn build_router(state: Arc<AppState>) -> Router {
     Router::new()
         .merge(public_routes)
         .merge(protected_routes)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            routes::maintenance_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            routes::readiness_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            proxy::connection_limit::connection_limit_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            routes::error_pages_middleware,
+        ))
         .layer(CookieManagerLayer::new())
-        .layer(TraceLayer::new_for_http())
+        .layer(
+            TraceLayer::new_for_http().make_span_with(logging::RedactedMakeSpan::new(
+                &state.config.server.redact_query_params,
+            )),
+        )
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_config, test_state};
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode, header},
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_admin_bind_moves_monitoring_endpoints() {
+        let request = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let mut config = test_config();
+        config.metrics.enabled = true;
+
+        let app = build_router(test_state(config.clone()));
+        for uri in ["/health", "/ready", "/metrics"] {
+            let res = app.clone().oneshot(request(uri)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+        }
+
+        config.server.admin_bind = Some("127.0.0.1:0".parse().unwrap());
+        let state = test_state(config);
+        let app = build_router(state.clone());
+        let admin = build_admin_router(state);
+        for uri in ["/health", "/ready", "/metrics"] {
+            let res = admin.clone().oneshot(request(uri)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+            // On the main port these are now just unauthenticated app paths
+            let res = app.clone().oneshot(request(uri)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+
+        // The admin port serves nothing else
+        for uri in ["/bouncarr/login", "/sonarr/api"] {
+            let res = admin.clone().oneshot(request(uri)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+        let res = app.oneshot(request("/bouncarr/login")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_auth_responses_are_not_cached() {
+        let request = |method: Method, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"username":"alice","password":"wrong"}"#))
+                .unwrap()
+        };
+        let app = build_router(test_state(test_config()));
+        for (method, uri) in [
+            (Method::GET, "/bouncarr/login"),
+            (Method::POST, "/bouncarr/api/auth/login"),
+            (Method::POST, "/bouncarr/api/auth/jellyfin-token"),
+            (Method::POST, "/bouncarr/api/auth/refresh"),
+            (Method::POST, "/bouncarr/api/auth/logout"),
+            (Method::GET, "/bouncarr/api/auth/login"),
+        ] {
+            let res = app.clone().oneshot(request(method, uri)).await.unwrap();
+            assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store", "{}", uri);
+            assert_eq!(res.headers()[header::PRAGMA], "no-cache", "{}", uri);
+            assert_eq!(res.headers()[header::VARY], "Cookie", "{}", uri);
+        }
+        let res = app.oneshot(request(Method::GET, "/health")).await.unwrap();
+        assert!(!res.headers().contains_key(header::CACHE_CONTROL));
+
+        let mut config = test_config();
+        config.security.no_store_auth_responses = false;
+        let app = build_router(test_state(config));
+        let res = app
+            .oneshot(request(Method::GET, "/bouncarr/login"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(header::CACHE_CONTROL));
+    }
+
+    #[tokio::test]
+    async fn test_static_dir() {
+        let root = crate::test_support::temp_dir();
+        let dir = root.path().join("public");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("logo.svg"), "<svg/>").unwrap();
+        std::fs::write(root.path().join("secret.txt"), "top secret").unwrap();
+
+        let mut config = test_config();
+        config.server.static_dir = Some(dir.to_string_lossy().into_owned());
+        let app = build_router(test_state(config));
+        let request = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let res = app
+            .clone()
+            .oneshot(request("/bouncarr/static/logo.svg"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"<svg/>");
+
+        for uri in [
+            "/bouncarr/static/../secret.txt",
+            "/bouncarr/static/..%2Fsecret.txt",
+            "/bouncarr/static/%2e%2e/secret.txt",
+            "/bouncarr/static/..%5Csecret.txt",
+        ] {
+            let res = app.clone().oneshot(request(uri)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", uri);
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(!String::from_utf8_lossy(&body).contains("top secret"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_endpoint_options() {
+        let app = build_router(test_state(test_config()));
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/bouncarr/api/auth/login")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[header::ALLOW], "POST, OPTIONS");
+    }
+
+    #[tokio::test]
+    async fn test_auth_endpoint_disallowed_method() {
+        let app = build_router(test_state(test_config()));
+
+        for method in [Method::GET, Method::PUT, Method::DELETE] {
+            let req = Request::builder()
+                .method(method)
+                .uri("/bouncarr/api/auth/login")
+                .body(Body::empty())
+                .unwrap();
+
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(res.headers()[header::ALLOW], "POST, OPTIONS");
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], br#"{"error":"Method not allowed"}"#);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_preflight_skips_authentication() {
+        let mut config = test_config();
+        let app = build_router(test_state(config.clone()));
+        let preflight = || {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/sonarr/api/v3/series")
+                .header(header::ORIGIN, "https://dashboard.example.com")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // Forwarded preflights still require authentication
+        let res = app.oneshot(preflight()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        config.arr_apps[0].preflight = crate::config::PreflightMode::Local;
+        let app = build_router(test_state(config));
+        let res = app.oneshot(preflight()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+}
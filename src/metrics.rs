@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+type LabelSet = Vec<(String, String)>;
+
+/// In-process counter registry, rendered in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<String, BTreeMap<LabelSet, u64>>>,
+}
+
+impl Metrics {
+    /// Add `value` to the counter identified by `name` and `labels`
+    pub fn add(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        *counters
+            .entry(name.to_string())
+            .or_default()
+            .entry(Self::label_set(labels))
+            .or_default() += value;
+    }
+
+    /// Current value of a counter (0 if it was never incremented)
+    #[cfg(test)]
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        counters
+            .get(name)
+            .and_then(|series| series.get(&Self::label_set(labels)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Render all counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let mut output = String::new();
+
+        for (name, series) in counters.iter() {
+            output.push_str(&format!("# TYPE {} counter\n", name));
+            for (labels, value) in series {
+                if labels.is_empty() {
+                    output.push_str(&format!("{} {}\n", name, value));
+                } else {
+                    let labels: Vec<_> = labels
+                        .iter()
+                        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+                        .collect();
+                    output.push_str(&format!("{}{{{}}} {}\n", name, labels.join(","), value));
+                }
+            }
+        }
+
+        output
+    }
+
+    fn label_set(labels: &[(&str, &str)]) -> LabelSet {
+        let mut set: LabelSet = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        set.sort();
+        set
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_accumulate() {
+        let metrics = Metrics::default();
+        metrics.add("requests_total", &[("app", "sonarr")], 1);
+        metrics.add("requests_total", &[("app", "sonarr")], 2);
+        metrics.add("requests_total", &[("app", "radarr")], 1);
+
+        assert_eq!(metrics.get("requests_total", &[("app", "sonarr")]), 3);
+        assert_eq!(metrics.get("requests_total", &[("app", "radarr")]), 1);
+        assert_eq!(metrics.get("requests_total", &[("app", "lidarr")]), 0);
+    }
+
+    #[test]
+    fn test_render_prometheus_format() {
+        let metrics = Metrics::default();
+        metrics.add("requests_total", &[("b", "2"), ("a", "1")], 5);
+        metrics.add("uptime_total", &[], 7);
+        metrics.add("quoted_total", &[("v", "say \"hi\"")], 1);
+
+        let output = metrics.render();
+        assert!(output.contains("# TYPE requests_total counter\n"));
+        assert!(output.contains("requests_total{a=\"1\",b=\"2\"} 5\n"));
+        assert!(output.contains("uptime_total 7\n"));
+        assert!(output.contains("quoted_total{v=\"say \\\"hi\\\"\"} 1\n"));
+    }
+}
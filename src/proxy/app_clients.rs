@@ -0,0 +1,292 @@
+use crate::config::{ArrApp, Config};
+use std::collections::HashMap;
+
+/// HTTP clients for apps that can't share the default client, one per app
+///
+/// A reqwest client sends every request over the socket it was built with
+/// and presents the same client certificate on every TLS connection, so apps
+/// reached over a Unix socket or with a `client_cert_path` get their own.
+#[derive(Default)]
+pub struct AppClients {
+    clients: HashMap<String, reqwest::Client>,
+}
+
+impl AppClients {
+    /// Build a client for every app with a `unix:` URL or a client certificate
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a certificate or key can't be loaded, or a client
+    /// can't be built
+    pub fn build(config: &Config) -> Result<Self, String> {
+        Self::build_with(config, reqwest::Client::builder)
+    }
+
+    /// Like [`build`](Self::build), starting each client from `new_builder`
+    fn build_with(
+        config: &Config,
+        new_builder: impl Fn() -> reqwest::ClientBuilder,
+    ) -> Result<Self, String> {
+        let mut clients = HashMap::new();
+        for app in &config.arr_apps {
+            let socket = app.unix_socket().map(|(socket, _)| socket);
+            let identity = client_identity(app)?;
+            if socket.is_none() && identity.is_none() {
+                continue;
+            }
+
+            let mut builder = new_builder();
+            #[cfg(unix)]
+            if let Some(socket) = socket {
+                builder = builder.unix_socket(socket);
+            }
+            if let Some(identity) = identity {
+                builder = builder.identity(identity);
+            }
+            if config.server.request_timeout_seconds > 0 {
+                builder = builder.timeout(std::time::Duration::from_secs(
+                    config.server.request_timeout_seconds as u64,
+                ));
+            }
+            let client = builder
+                .build()
+                .map_err(|e| format!("Arr app '{}': failed to build client: {}", app.name, e))?;
+            clients.insert(app.name.clone(), client);
+        }
+        Ok(Self { clients })
+    }
+
+    /// The client for an app, or `default` for apps using the shared client
+    pub fn client_for<'a>(
+        &'a self,
+        app: &ArrApp,
+        default: &'a reqwest::Client,
+    ) -> &'a reqwest::Client {
+        self.clients.get(&app.name).unwrap_or(default)
+    }
+}
+
+/// Load the app's TLS client certificate and key, if it has one
+fn client_identity(app: &ArrApp) -> Result<Option<reqwest::Identity>, String> {
+    let (Some(cert_path), Some(key_path)) = (&app.client_cert_path, &app.client_key_path) else {
+        return Ok(None);
+    };
+    let read = |path: &str| {
+        std::fs::read(path).map_err(|e| {
+            format!(
+                "Arr app '{}': failed to read client certificate file {}: {}",
+                app.name, path, e
+            )
+        })
+    };
+    let (cert, key) = (read(cert_path)?, read(key_path)?);
+    reqwest::Identity::from_pkcs8_pem(&cert, &key)
+        .map(Some)
+        .map_err(|e| {
+            format!(
+                "Arr app '{}': invalid client certificate {} or key {}: {}",
+                app.name, cert_path, key_path, e
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_router;
+    use crate::test_support::{access_token, temp_dir, test_config, test_state};
+    use axum::{
+        Router,
+        body::Body,
+        extract::Request,
+        http::{StatusCode, header},
+        routing::get,
+    };
+    use std::sync::Arc;
+    use tokio_rustls::rustls::{
+        self,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        server::WebPkiClientVerifier,
+    };
+    use tower::ServiceExt;
+
+    const CA: &str = include_str!("../listener/testdata/ca.pem");
+    const SERVER_CERT: &str = include_str!("../listener/testdata/new.pem");
+    const SERVER_KEY: &str = include_str!("../listener/testdata/new.key");
+    const CLIENT_CERT: &str = include_str!("../listener/testdata/old.pem");
+    const CLIENT_KEY: &str = include_str!("../listener/testdata/old.key");
+
+    /// Serve a router on a Unix socket in `dir`, returning its path
+    #[cfg(unix)]
+    fn spawn_unix_upstream(dir: &std::path::Path, router: Router) -> String {
+        use hyper_util::{
+            rt::{TokioExecutor, TokioIo},
+            server::conn::auto::Builder,
+            service::TowerToHyperService,
+        };
+
+        let path = dir.join("upstream.sock");
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let service = TowerToHyperService::new(router.clone());
+                tokio::spawn(async move {
+                    Builder::new(TokioExecutor::new())
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                        .ok();
+                });
+            }
+        });
+        path.to_string_lossy().into_owned()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_proxies_to_unix_socket() {
+        let dir = temp_dir();
+        let socket = spawn_unix_upstream(
+            dir.path(),
+            Router::new().fallback(get(|req: Request| async move {
+                format!(
+                    "{} {}",
+                    req.uri(),
+                    req.headers()[header::HOST].to_str().unwrap()
+                )
+            })),
+        );
+
+        let mut config = test_config();
+        config.arr_apps[0].url = format!("unix:{}:/base", socket);
+        let app = build_router(test_state(config));
+
+        let res = app
+            .oneshot(
+                Request::builder()
+                    .uri("/sonarr/api/v3/series?page=2")
+                    .header(
+                        header::AUTHORIZATION,
+                        format!("Bearer {}", access_token(true)),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"/base/api/v3/series?page=2 localhost");
+    }
+
+    /// Serve "mtls ok" over TLS to clients with a certificate from the test CA
+    async fn spawn_mtls_upstream() -> String {
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_slice(CA.as_bytes()).unwrap())
+            .unwrap();
+        let provider = Arc::new(ring::default_provider());
+        let verifier =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .unwrap();
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(
+                vec![CertificateDer::from_pem_slice(SERVER_CERT.as_bytes()).unwrap()],
+                PrivateKeyDer::from_pem_slice(SERVER_KEY.as_bytes()).unwrap(),
+            )
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(crate::listener::serve(
+            listener,
+            Router::new().fallback(get(|| async { "mtls ok" })),
+            false,
+            Some(tokio_rustls::TlsAcceptor::from(Arc::new(config))),
+            std::future::pending(),
+        ));
+        format!("https://localhost:{}", port)
+    }
+
+    /// Client certificate files in `dir`
+    fn write_client_cert(dir: &std::path::Path, cert: &str, key: &str) -> (String, String) {
+        let (cert_path, key_path) = (dir.join("client.pem"), dir.join("client.key"));
+        std::fs::write(&cert_path, cert).unwrap();
+        std::fs::write(&key_path, key).unwrap();
+        (
+            cert_path.to_string_lossy().into_owned(),
+            key_path.to_string_lossy().into_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_mtls_upstream_requires_client_certificate() {
+        let upstream = spawn_mtls_upstream().await;
+        let trusting_ca = || {
+            reqwest::Client::builder()
+                .add_root_certificate(reqwest::Certificate::from_pem(CA.as_bytes()).unwrap())
+        };
+
+        let dir = temp_dir();
+        for with_cert in [false, true] {
+            let mut config = test_config();
+            config.arr_apps[0].url = upstream.clone();
+            if with_cert {
+                let (cert, key) = write_client_cert(dir.path(), CLIENT_CERT, CLIENT_KEY);
+                config.arr_apps[0].client_cert_path = Some(cert);
+                config.arr_apps[0].client_key_path = Some(key);
+            }
+            let mut state = test_state(config.clone());
+            let shared = Arc::get_mut(&mut state).unwrap();
+            shared.http_client = trusting_ca().build().unwrap();
+            shared.app_clients = AppClients::build_with(&config, trusting_ca).unwrap();
+
+            let res = build_router(state)
+                .oneshot(
+                    Request::builder()
+                        .uri("/sonarr/api/v3/series")
+                        .header(
+                            header::AUTHORIZATION,
+                            format!("Bearer {}", access_token(true)),
+                        )
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            if with_cert {
+                assert_eq!(res.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                assert_eq!(&body[..], b"mtls ok");
+            } else {
+                assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unloadable_client_certificate_fails() {
+        let mut config = test_config();
+        config.arr_apps[0].client_cert_path = Some("/nonexistent/client.pem".to_string());
+        config.arr_apps[0].client_key_path = Some("/nonexistent/client.key".to_string());
+        let err = AppClients::build(&config).err().unwrap();
+        assert!(err.contains("Arr app 'sonarr'"), "{}", err);
+        assert!(err.contains("/nonexistent/client.pem"), "{}", err);
+
+        // A certificate in place of the key is not a PKCS#8 key
+        let dir = temp_dir();
+        let (cert, key) = write_client_cert(dir.path(), CLIENT_CERT, CLIENT_CERT);
+        config.arr_apps[0].client_cert_path = Some(cert);
+        config.arr_apps[0].client_key_path = Some(key);
+        let err = AppClients::build(&config).err().unwrap();
+        assert!(err.contains("invalid client certificate"), "{}", err);
+    }
+}
@@ -0,0 +1,509 @@
+use crate::config::OversizedBodyPolicy;
+use crate::error::{AppError, Result};
+use axum::body::{Body, Bytes};
+use futures_util::{Stream, StreamExt};
+use http_body_util::BodyExt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::time::Instant;
+
+/// Minimum rate at which an upstream must deliver a response body
+#[derive(Debug, Clone, Copy)]
+pub struct MinThroughput {
+    pub bytes_per_second: u64,
+    /// Period over which the rate is measured, so short pauses in an
+    /// otherwise steady stream are tolerated
+    pub window: Duration,
+}
+
+/// Largest response body passed on to the client
+#[derive(Debug, Clone, Copy)]
+pub struct MaxSize {
+    pub bytes: u64,
+    pub policy: OversizedBodyPolicy,
+}
+
+/// Stream an upstream response body to the client
+///
+/// The first chunk is read before the response is handed back, so an upstream
+/// that resets or stalls before sending any body still turns into a 502 or
+/// 504. A failure after that point is logged and ends the body with an error,
+/// which makes the server abort the response instead of presenting a
+/// truncated body as complete.
+///
+/// With `min_throughput` set, the body is aborted as soon as a measurement
+/// window delivers fewer bytes than the configured rate.
+///
+/// With `max_size` set, bodies growing past it are failed (a 502 if the
+/// declared length is already too large) or truncated, per its policy.
+///
+/// # Errors
+///
+/// Returns error if the upstream fails or stalls before the first body chunk,
+/// or declares a body larger than `max_size` allows
+pub async fn stream_response(
+    response: reqwest::Response,
+    logged_url: &str,
+    min_throughput: Option<MinThroughput>,
+    max_size: Option<MaxSize>,
+) -> Result<Body> {
+    if let Some(max) = max_size
+        && max.policy == OversizedBodyPolicy::Reject
+        && response.content_length().is_some_and(|len| len > max.bytes)
+    {
+        tracing::error!(
+            "Upstream {} sent a {} byte response (limit {})",
+            logged_url,
+            response.content_length().unwrap_or_default(),
+            max.bytes
+        );
+        return Err(AppError::ProxyError(format!(
+            "Upstream response exceeds the maximum size of {} bytes",
+            max.bytes
+        )));
+    }
+
+    let upstream = response
+        .bytes_stream()
+        .map(|chunk| chunk.map_err(|e| io::Error::other(e.without_url())));
+    let mut stream = match min_throughput {
+        Some(min) => enforce_throughput(upstream, min).boxed(),
+        None => upstream.boxed(),
+    };
+    if let Some(max) = max_size {
+        stream = limit_size(stream, max).boxed();
+    }
+
+    let first = match stream.next().await {
+        Some(Ok(chunk)) => Some(chunk),
+        Some(Err(e)) => {
+            tracing::error!("Failed to read response body from {}: {}", logged_url, e);
+            if e.kind() == io::ErrorKind::TimedOut {
+                return Err(AppError::UpstreamTimeout(format!(
+                    "Upstream response stalled: {}",
+                    e
+                )));
+            }
+            return Err(AppError::ProxyError(format!(
+                "Failed to read response body: {}",
+                e
+            )));
+        }
+        None => None,
+    };
+
+    let logged_url = logged_url.to_string();
+    let mut received = first.as_ref().map_or(0, Bytes::len);
+    let rest = stream.inspect(move |chunk| match chunk {
+        Ok(chunk) => received += chunk.len(),
+        Err(e) => tracing::warn!(
+            "Upstream {} failed after {} bytes, truncating response: {}",
+            logged_url,
+            received,
+            e
+        ),
+    });
+
+    Ok(Body::from_stream(
+        futures_util::stream::iter(first.map(Ok)).chain(rest),
+    ))
+}
+
+/// Read a whole upstream response body before answering
+///
+/// Unlike a streamed body, an upstream failing partway through turns into a
+/// 502 (or 504 on timeout) instead of a truncated response.
+///
+/// # Errors
+///
+/// Returns error if the body cannot be read completely
+pub async fn buffer_response(response: reqwest::Response, logged_url: &str) -> Result<Body> {
+    match response.bytes().await {
+        Ok(bytes) => Ok(Body::from(bytes)),
+        Err(e) => {
+            let e = e.without_url();
+            tracing::error!("Failed to read response body from {}: {}", logged_url, e);
+            if e.is_timeout() {
+                return Err(AppError::UpstreamTimeout(format!(
+                    "Upstream response stalled: {}",
+                    e
+                )));
+            }
+            Err(AppError::ProxyError(format!(
+                "Failed to read response body: {}",
+                e
+            )))
+        }
+    }
+}
+
+/// Fail or cut off a body stream once it grows past `max.bytes`
+fn limit_size<S>(stream: S, max: MaxSize) -> impl Stream<Item = io::Result<Bytes>> + Send
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + Unpin,
+{
+    futures_util::stream::unfold(
+        (stream, 0u64, false),
+        move |(mut stream, received, done)| async move {
+            if done {
+                return None;
+            }
+
+            match stream.next().await? {
+                Ok(mut chunk) => {
+                    let total = received + chunk.len() as u64;
+                    if total <= max.bytes {
+                        return Some((Ok(chunk), (stream, total, false)));
+                    }
+                    match max.policy {
+                        OversizedBodyPolicy::Reject => {
+                            let error = io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("response exceeds the maximum size of {} bytes", max.bytes),
+                            );
+                            Some((Err(error), (stream, total, true)))
+                        }
+                        OversizedBodyPolicy::Truncate => {
+                            tracing::warn!(
+                                "Truncating upstream response at the maximum size of {} bytes",
+                                max.bytes
+                            );
+                            chunk.truncate((max.bytes - received) as usize);
+                            Some((Ok(chunk), (stream, max.bytes, true)))
+                        }
+                    }
+                }
+                Err(e) => Some((Err(e), (stream, received, true))),
+            }
+        },
+    )
+}
+
+/// Fail a body stream with `TimedOut` once a window delivers too few bytes
+fn enforce_throughput<S>(
+    stream: S,
+    min: MinThroughput,
+) -> impl Stream<Item = io::Result<Bytes>> + Send
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + Unpin,
+{
+    let required = min
+        .bytes_per_second
+        .saturating_mul(min.window.as_secs().max(1));
+    let state = (stream, Instant::now() + min.window, 0u64, false);
+
+    futures_util::stream::unfold(
+        state,
+        move |(mut stream, mut deadline, mut window_bytes, failed)| async move {
+            if failed {
+                return None;
+            }
+
+            loop {
+                let item = tokio::time::timeout_at(deadline, stream.next()).await;
+                let window_over = Instant::now() >= deadline;
+
+                if let Ok(Some(Ok(chunk))) = &item {
+                    window_bytes += chunk.len() as u64;
+                }
+                if window_over {
+                    if window_bytes < required {
+                        let error = io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!(
+                                "received {} bytes in {}s, below the minimum of {} bytes/s",
+                                window_bytes,
+                                min.window.as_secs(),
+                                min.bytes_per_second
+                            ),
+                        );
+                        return Some((Err(error), (stream, deadline, window_bytes, true)));
+                    }
+                    deadline = Instant::now() + min.window;
+                    window_bytes = 0;
+                }
+
+                match item {
+                    Ok(Some(Ok(chunk))) => {
+                        return Some((Ok(chunk), (stream, deadline, window_bytes, false)));
+                    }
+                    Ok(Some(Err(e))) => {
+                        return Some((Err(e), (stream, deadline, window_bytes, true)));
+                    }
+                    Ok(None) => return None,
+                    // Window passed with enough data; keep waiting
+                    Err(_) => continue,
+                }
+            }
+        },
+    )
+}
+
+/// A fully received request body, held in memory or spilled to a temp file
+pub enum BufferedBody {
+    /// Body small enough to stay in memory
+    Memory(Bytes),
+    /// Body larger than the spill threshold, written to disk
+    File(SpillFile),
+}
+
+impl BufferedBody {
+    /// Receive a request body, spilling to `spill_dir` once it grows past
+    /// `threshold` bytes
+    ///
+    /// With no threshold the whole body is kept in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if reading the body or writing the temp file fails
+    pub async fn receive(body: Body, threshold: Option<usize>, spill_dir: &Path) -> Result<Self> {
+        let mut body = body;
+        let mut buffer = Vec::new();
+        let mut spill: Option<(SpillFile, tokio::fs::File)> = None;
+
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(|e| {
+                tracing::error!("Failed to read request body: {}", e);
+                AppError::ProxyError(format!("Failed to read request body: {}", e))
+            })?;
+            let Ok(data) = frame.into_data() else {
+                continue;
+            };
+
+            if let Some((spill_file, file)) = spill.as_mut() {
+                file.write_all(&data).await.map_err(spill_error)?;
+                spill_file.len += data.len() as u64;
+                continue;
+            }
+
+            buffer.extend_from_slice(&data);
+            if threshold.is_some_and(|limit| buffer.len() > limit) {
+                let (mut spill_file, mut file) =
+                    SpillFile::create(spill_dir).await.map_err(spill_error)?;
+                tracing::debug!(
+                    "Request body exceeds {} bytes, spilling to {}",
+                    threshold.unwrap_or_default(),
+                    spill_file.path.display()
+                );
+                file.write_all(&buffer).await.map_err(spill_error)?;
+                spill_file.len = buffer.len() as u64;
+                buffer = Vec::new();
+                spill = Some((spill_file, file));
+            }
+        }
+
+        match spill {
+            Some((spill_file, mut file)) => {
+                file.flush().await.map_err(spill_error)?;
+                Ok(BufferedBody::File(spill_file))
+            }
+            None => Ok(BufferedBody::Memory(Bytes::from(buffer))),
+        }
+    }
+
+    /// Build a body for the upstream request
+    ///
+    /// Can be called repeatedly, e.g. to retry a request with the same body.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a spilled body can no longer be opened
+    pub async fn to_reqwest_body(&self) -> Result<reqwest::Body> {
+        match self {
+            BufferedBody::Memory(bytes) => Ok(reqwest::Body::from(bytes.clone())),
+            BufferedBody::File(spill_file) => {
+                let file = tokio::fs::File::open(&spill_file.path)
+                    .await
+                    .map_err(spill_error)?;
+                Ok(reqwest::Body::from(file))
+            }
+        }
+    }
+}
+
+/// Temp file holding a spilled request body, removed when dropped
+pub struct SpillFile {
+    path: PathBuf,
+    len: u64,
+}
+
+impl SpillFile {
+    /// Create a new file with a random name, readable only by Bouncarr
+    ///
+    /// The file must not exist yet, so a file or symlink planted in a shared
+    /// temp dir is never written to (or removed).
+    async fn create(dir: &Path) -> io::Result<(Self, tokio::fs::File)> {
+        use rand::Rng;
+
+        let suffix: u128 = rand::thread_rng().r#gen();
+        let path = dir.join(format!("bouncarr-body-{:032x}.tmp", suffix));
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let file = options.open(&path).await?;
+        Ok((Self { path, len: 0 }, file))
+    }
+
+    /// Number of bytes written to the file
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!(
+                "Failed to remove request body temp file {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Whether a gzip body decodes to more than `limit` bytes
+///
+/// The decoded output is counted and discarded as it is produced, and
+/// decoding stops right after the limit, so a small compressed body can't
+/// make Bouncarr inflate gigabytes.
+///
+/// # Errors
+///
+/// Returns error if the body is not valid gzip or the spill file can't be read
+pub async fn gzip_exceeds(body: &BufferedBody, limit: u64) -> io::Result<bool> {
+    fn decoded_len(compressed: impl std::io::Read, limit: u64) -> io::Result<u64> {
+        use std::io::Read;
+        let decoder = flate2::read::MultiGzDecoder::new(compressed);
+        std::io::copy(&mut decoder.take(limit + 1), &mut std::io::sink())
+    }
+
+    let decoded = match body {
+        BufferedBody::Memory(bytes) => {
+            let bytes = bytes.clone();
+            tokio::task::spawn_blocking(move || decoded_len(&bytes[..], limit)).await
+        }
+        BufferedBody::File(spill_file) => {
+            let path = spill_file.path.clone();
+            tokio::task::spawn_blocking(move || {
+                let file = std::fs::File::open(path)?;
+                decoded_len(std::io::BufReader::new(file), limit)
+            })
+            .await
+        }
+    }
+    .map_err(io::Error::other)??;
+    Ok(decoded > limit)
+}
+
+fn spill_error(e: std::io::Error) -> AppError {
+    tracing::error!("Failed to spill request body to disk: {}", e);
+    AppError::ProxyError(format!("Failed to buffer request body: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn dir_entries(dir: &Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    async fn collect(body: reqwest::Body) -> Vec<u8> {
+        http_body_util::BodyExt::collect(body)
+            .await
+            .unwrap()
+            .to_bytes()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_gzip_exceeds_spilled_body() {
+        use std::io::Write;
+
+        let dir = temp_dir();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&[0u8; 100_000]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let body = BufferedBody::receive(Body::from(compressed), Some(16), dir.path())
+            .await
+            .unwrap();
+        assert!(matches!(body, BufferedBody::File(_)));
+
+        assert!(gzip_exceeds(&body, 99_999).await.unwrap());
+        assert!(!gzip_exceeds(&body, 100_000).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_body_below_threshold_stays_in_memory() {
+        let dir = temp_dir();
+        let body = BufferedBody::receive(Body::from("small body"), Some(1024), dir.path())
+            .await
+            .unwrap();
+
+        assert!(matches!(body, BufferedBody::Memory(_)));
+        assert_eq!(dir_entries(dir.path()), 0);
+        assert_eq!(
+            collect(body.to_reqwest_body().await.unwrap()).await,
+            b"small body"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_body_above_threshold_spills_and_cleans_up() {
+        let dir = temp_dir();
+        let payload = "x".repeat(4096);
+        let body = BufferedBody::receive(Body::from(payload.clone()), Some(1024), dir.path())
+            .await
+            .unwrap();
+
+        match &body {
+            BufferedBody::File(spill_file) => assert_eq!(spill_file.len(), 4096),
+            BufferedBody::Memory(_) => panic!("body should have been spilled to disk"),
+        }
+        assert_eq!(dir_entries(dir.path()), 1);
+
+        // Body can be replayed more than once
+        for _ in 0..2 {
+            let bytes = collect(body.to_reqwest_body().await.unwrap()).await;
+            assert_eq!(bytes, payload.as_bytes());
+        }
+
+        drop(body);
+        assert_eq!(dir_entries(dir.path()), 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_spill_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir();
+        let (spill_file, _file) = SpillFile::create(dir.path()).await.unwrap();
+        let mode = std::fs::metadata(&spill_file.path)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let (other, _file) = SpillFile::create(dir.path()).await.unwrap();
+        assert_ne!(other.path, spill_file.path);
+    }
+
+    #[tokio::test]
+    async fn test_no_threshold_keeps_body_in_memory() {
+        let dir = temp_dir();
+        let body = BufferedBody::receive(Body::from("y".repeat(8192)), None, dir.path())
+            .await
+            .unwrap();
+
+        assert!(matches!(body, BufferedBody::Memory(_)));
+        assert_eq!(dir_entries(dir.path()), 0);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::AppState;
+use crate::error::AppError;
+use crate::proxy::forwarded;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::BodyExt;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+/// Count of in-flight requests and WebSocket connections per client IP
+#[derive(Default)]
+pub struct ConnectionLimiter {
+    active: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+/// Slot held by one request or WebSocket connection, released on drop
+pub struct ConnectionGuard {
+    ip: IpAddr,
+    active: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl ConnectionLimiter {
+    /// Take a slot for `ip`, or `None` if it already holds `max`
+    pub fn acquire(&self, ip: IpAddr, max: usize) -> Option<ConnectionGuard> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        let count = active.entry(ip).or_insert(0);
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+
+        Some(ConnectionGuard {
+            ip,
+            active: self.active.clone(),
+        })
+    }
+
+    /// Slots currently held by `ip`
+    #[cfg(test)]
+    pub fn active(&self, ip: IpAddr) -> usize {
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        active.get(&ip).copied().unwrap_or(0)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = active.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Reject clients holding `server.max_connections_per_ip` requests or
+/// WebSocket connections with a 429
+///
+/// Clients behind a peer in `server.trusted_proxies` are told apart by
+/// `X-Forwarded-For`, so they don't share the proxy's slots.
+///
+/// A slot is held until the response body has been sent. WebSocket upgrades
+/// find the guard in the request extensions and keep it for the lifetime of
+/// the connection.
+pub async fn connection_limit_middleware(
+    State(state): State<Arc<AppState>>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let server = &state.config.server;
+    let Some(max) = server.max_connections_per_ip else {
+        return next.run(req).await;
+    };
+    let Some(ConnectInfo(addr)) = req.extensions().get::<ConnectInfo<SocketAddr>>().copied() else {
+        return next.run(req).await;
+    };
+    let Some(ip) = forwarded::client_address(req.headers(), Some(addr.ip()), server) else {
+        return next.run(req).await;
+    };
+    if server.connection_limit_exempt_ips.contains(&ip) {
+        return next.run(req).await;
+    }
+
+    let Some(guard) = state.connection_limiter.acquire(ip, max) else {
+        tracing::warn!("Client {} exceeded {} concurrent connections", ip, max);
+        return AppError::TooManyRequests("Too many concurrent connections".to_string())
+            .into_response();
+    };
+    let guard = Arc::new(guard);
+    req.extensions_mut().insert(guard.clone());
+
+    // Hold the slot until the response body is done with
+    let (parts, body) = next.run(req).await.into_parts();
+    let body = body.map_frame(move |frame| {
+        let _ = &guard;
+        frame
+    });
+    Response::from_parts(parts, Body::new(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_config, test_state};
+    use axum::{Router, http::StatusCode, middleware, routing::get};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_limiter_releases_on_drop() {
+        let limiter = ConnectionLimiter::default();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+        let first = limiter.acquire(ip, 2).unwrap();
+        let _second = limiter.acquire(ip, 2).unwrap();
+        assert!(limiter.acquire(ip, 2).is_none());
+        // Other clients are unaffected
+        assert!(limiter.acquire("203.0.113.8".parse().unwrap(), 2).is_some());
+
+        drop(first);
+        assert!(limiter.acquire(ip, 2).is_some());
+    }
+
+    fn request(ip: &str) -> Request<Body> {
+        let mut req = Request::builder().uri("/slow").body(Body::empty()).unwrap();
+        let addr: SocketAddr = format!("{}:40000", ip).parse().unwrap();
+        req.extensions_mut().insert(ConnectInfo(addr));
+        req
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_connections_limited_per_ip() {
+        let mut config = test_config();
+        config.server.max_connections_per_ip = Some(2);
+        config.server.connection_limit_exempt_ips = vec!["10.0.0.1".parse().unwrap()];
+        let state = test_state(config);
+
+        let release = Arc::new(tokio::sync::Semaphore::new(0));
+        let wait = release.clone();
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(move || async move {
+                    let _permit = wait.acquire().await;
+                    "done"
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                connection_limit_middleware,
+            ));
+
+        // Hold two requests open from the same client
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let in_flight: Vec<_> = (0..2)
+            .map(|_| tokio::spawn(app.clone().oneshot(request("203.0.113.7"))))
+            .collect();
+        while state.connection_limiter.active(ip) < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        let res = app.clone().oneshot(request("203.0.113.7")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Exempt addresses are never limited
+        let exempt = tokio::spawn(app.clone().oneshot(request("10.0.0.1")));
+        tokio::task::yield_now().await;
+        assert_eq!(
+            state.connection_limiter.active("10.0.0.1".parse().unwrap()),
+            0
+        );
+
+        release.add_permits(3);
+        for handle in in_flight {
+            let res = handle.await.unwrap().unwrap();
+            axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+        }
+        exempt.abort();
+        assert_eq!(state.connection_limiter.active(ip), 0);
+    }
+
+    #[tokio::test]
+    async fn test_clients_behind_trusted_proxy_limited_separately() {
+        let mut config = test_config();
+        config.server.max_connections_per_ip = Some(1);
+        config.server.trusted_proxies = vec!["10.0.0.2".parse().unwrap()];
+        let state = test_state(config);
+
+        let release = Arc::new(tokio::sync::Semaphore::new(0));
+        let wait = release.clone();
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(move || async move {
+                    let _permit = wait.acquire().await;
+                    "done"
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                connection_limit_middleware,
+            ));
+        let via_proxy = |client: &str| {
+            let mut req = request("10.0.0.2");
+            req.headers_mut()
+                .insert("x-forwarded-for", client.parse().unwrap());
+            req
+        };
+
+        let first: IpAddr = "203.0.113.7".parse().unwrap();
+        let held = tokio::spawn(app.clone().oneshot(via_proxy("203.0.113.7")));
+        while state.connection_limiter.active(first) < 1 {
+            tokio::task::yield_now().await;
+        }
+        let res = app.clone().oneshot(via_proxy("203.0.113.7")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Another client behind the same proxy has its own slot
+        let other: IpAddr = "203.0.113.8".parse().unwrap();
+        let second = tokio::spawn(app.clone().oneshot(via_proxy("203.0.113.8")));
+        while state.connection_limiter.active(other) < 1 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            state.connection_limiter.active("10.0.0.2".parse().unwrap()),
+            0
+        );
+
+        release.add_permits(2);
+        for handle in [held, second] {
+            let res = handle.await.unwrap().unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+        }
+    }
+}
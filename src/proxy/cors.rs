@@ -0,0 +1,225 @@
+use crate::config::{ArrApp, Config, PreflightMode};
+use crate::proxy::handler::resolve_route;
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, Method, Request, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+/// Whether a request is a CORS preflight (`OPTIONS` with
+/// `Access-Control-Request-Method`)
+pub fn is_preflight(req: &Request<Body>) -> bool {
+    req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// Whether a request is a CORS preflight that Bouncarr answers itself
+///
+/// Browsers never attach credentials to preflights, so these requests must be
+/// let through authentication. They are answered locally and never reach the
+/// upstream.
+pub fn is_local_preflight(config: &Config, req: &Request<Body>) -> bool {
+    is_preflight(req)
+        && resolve_route(config, req)
+            .is_some_and(|route| route.app.preflight == PreflightMode::Local)
+}
+
+/// Answer a CORS preflight from the app's CORS policy
+///
+/// Origins that are not allowed get a bare 204 without any
+/// `Access-Control-Allow-*` headers, which makes the browser block the request.
+pub fn preflight_response(app: &ArrApp, req: &Request<Body>) -> Response {
+    let mut headers = HeaderMap::new();
+
+    if let Some(origin) = allowed_origin(app, req.headers()) {
+        let policy = &app.cors;
+        insert_allow_origin(&mut headers, origin);
+        if let Ok(methods) = HeaderValue::from_str(&policy.allowed_methods.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        if let Ok(allowed) = HeaderValue::from_str(&policy.allowed_headers.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed);
+        }
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from(policy.max_age_seconds),
+        );
+    } else {
+        tracing::debug!(
+            "Rejecting CORS preflight for '{}' from disallowed origin",
+            app.name
+        );
+    }
+    headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+
+    (StatusCode::NO_CONTENT, headers).into_response()
+}
+
+/// Add CORS headers to a proxied response when the app answers preflights
+/// locally and the request origin is allowed
+pub fn apply_response_headers(app: &ArrApp, request_headers: &HeaderMap, response: &mut Response) {
+    if app.preflight != PreflightMode::Local {
+        return;
+    }
+    if let Some(origin) = allowed_origin(app, request_headers) {
+        let headers = response.headers_mut();
+        insert_allow_origin(headers, origin);
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    }
+}
+
+/// An origin allowed by the app's CORS policy
+enum AllowedOrigin {
+    /// Listed by name: reflected, with credentials
+    Listed(HeaderValue),
+    /// Only allowed by `*`: answered with a literal `*` and no credentials, so
+    /// other sites can't make cookie-authenticated calls
+    Any,
+}
+
+fn allowed_origin(app: &ArrApp, headers: &HeaderMap) -> Option<AllowedOrigin> {
+    let origin = headers.get(header::ORIGIN)?;
+    let origin_str = origin.to_str().ok()?;
+    let allowed = &app.cors.allowed_origins;
+    if allowed
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(origin_str))
+    {
+        Some(AllowedOrigin::Listed(origin.clone()))
+    } else {
+        allowed
+            .iter()
+            .any(|allowed| allowed == "*")
+            .then_some(AllowedOrigin::Any)
+    }
+}
+
+fn insert_allow_origin(headers: &mut HeaderMap, origin: AllowedOrigin) {
+    match origin {
+        AllowedOrigin::Listed(origin) => {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        AllowedOrigin::Any => {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static("*"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_config;
+
+    fn local_app() -> ArrApp {
+        let mut config = test_config();
+        let mut app = config.arr_apps.remove(0);
+        app.preflight = PreflightMode::Local;
+        app.cors.allowed_origins = vec!["https://dashboard.example.com".to_string()];
+        app
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/sonarr/api/v3/series")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_preflight_allowed_origin() {
+        let res = preflight_response(&local_app(), &preflight("https://dashboard.example.com"));
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://dashboard.example.com"
+        );
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+        assert!(
+            res.headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_METHODS)
+        );
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_MAX_AGE], "600");
+    }
+
+    #[test]
+    fn test_preflight_disallowed_origin() {
+        let res = preflight_response(&local_app(), &preflight("https://evil.example.com"));
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(
+            !res.headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+
+    #[test]
+    fn test_wildcard_origin_gets_no_credentials() {
+        let mut app = local_app();
+        app.cors.allowed_origins.push("*".to_string());
+
+        let res = preflight_response(&app, &preflight("https://evil.example.com"));
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(
+            !res.headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+        );
+
+        let mut response = Response::new(Body::empty());
+        let request = preflight("https://evil.example.com");
+        apply_response_headers(&app, request.headers(), &mut response);
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+        );
+
+        // Listed origins still get credentials
+        let res = preflight_response(&app, &preflight("https://dashboard.example.com"));
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://dashboard.example.com"
+        );
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_is_local_preflight() {
+        let mut config = test_config();
+        assert!(!is_local_preflight(
+            &config,
+            &preflight("https://a.example.com")
+        ));
+
+        config.arr_apps[0].preflight = PreflightMode::Local;
+        assert!(is_local_preflight(
+            &config,
+            &preflight("https://a.example.com")
+        ));
+
+        // Plain OPTIONS without Access-Control-Request-Method is not a preflight
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/sonarr/")
+            .body(Body::empty())
+            .unwrap();
+        assert!(!is_local_preflight(&config, &req));
+    }
+}
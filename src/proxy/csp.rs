@@ -0,0 +1,128 @@
+use crate::config::CspRewrite;
+use axum::http::{HeaderMap, HeaderValue, header};
+
+/// Apply an app's CSP rewrite to the upstream response headers
+///
+/// Responses without a `Content-Security-Policy` header are left alone,
+/// except with `replace`, which sets the policy regardless.
+pub fn apply(rewrite: &CspRewrite, headers: &mut HeaderMap) {
+    let policy = match &rewrite.replace {
+        Some(policy) => policy.clone(),
+        None => {
+            let Some(upstream) = headers
+                .get(header::CONTENT_SECURITY_POLICY)
+                .and_then(|v| v.to_str().ok())
+            else {
+                return;
+            };
+            rewrite_policy(upstream, rewrite)
+        }
+    };
+
+    match HeaderValue::from_str(&policy) {
+        Ok(value) => {
+            headers.insert(header::CONTENT_SECURITY_POLICY, value);
+        }
+        Err(_) => tracing::warn!("Rewritten Content-Security-Policy is not a valid header value"),
+    }
+}
+
+/// Set and remove directives in a policy, keeping the upstream order
+///
+/// Directives that are set but not in the upstream policy are appended.
+fn rewrite_policy(policy: &str, rewrite: &CspRewrite) -> String {
+    let mut directives: Vec<String> = Vec::new();
+    let mut seen = Vec::new();
+
+    for directive in policy.split(';').map(str::trim).filter(|d| !d.is_empty()) {
+        let name = directive
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if rewrite.remove.iter().any(|r| r.eq_ignore_ascii_case(&name)) {
+            continue;
+        }
+        match rewrite
+            .set
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(&name))
+        {
+            Some((n, value)) => directives.push(format!("{} {}", n, value)),
+            None => directives.push(directive.to_string()),
+        }
+        seen.push(name);
+    }
+
+    for (name, value) in &rewrite.set {
+        if !seen.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            directives.push(format!("{} {}", name, value));
+        }
+    }
+
+    directives.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPSTREAM: &str = "default-src 'self'; connect-src 'self'; frame-ancestors 'none'";
+
+    fn headers(csp: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(csp) = csp {
+            headers.insert(header::CONTENT_SECURITY_POLICY, csp.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_set_and_remove_directives() {
+        let rewrite = CspRewrite {
+            set: [
+                (
+                    "frame-ancestors".to_string(),
+                    "'self' https://dashboard.example.com".to_string(),
+                ),
+                ("img-src".to_string(), "* data:".to_string()),
+            ]
+            .into(),
+            remove: vec!["Connect-Src".to_string()],
+            ..Default::default()
+        };
+        let mut headers = headers(Some(UPSTREAM));
+        apply(&rewrite, &mut headers);
+
+        assert_eq!(
+            headers[header::CONTENT_SECURITY_POLICY],
+            "default-src 'self'; frame-ancestors 'self' https://dashboard.example.com; \
+            img-src * data:"
+        );
+    }
+
+    #[test]
+    fn test_replace_policy() {
+        let rewrite = CspRewrite {
+            replace: Some("default-src *".to_string()),
+            ..Default::default()
+        };
+
+        for upstream in [Some(UPSTREAM), None] {
+            let mut headers = headers(upstream);
+            apply(&rewrite, &mut headers);
+            assert_eq!(headers[header::CONTENT_SECURITY_POLICY], "default-src *");
+        }
+    }
+
+    #[test]
+    fn test_missing_policy_left_alone() {
+        let rewrite = CspRewrite {
+            remove: vec!["connect-src".to_string()],
+            ..Default::default()
+        };
+        let mut headers = headers(None);
+        apply(&rewrite, &mut headers);
+        assert!(headers.get(header::CONTENT_SECURITY_POLICY).is_none());
+    }
+}
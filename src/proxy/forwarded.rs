@@ -0,0 +1,186 @@
+use crate::config::ServerConfig;
+use axum::http::HeaderMap;
+use std::net::IpAddr;
+
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// `X-Forwarded-For` value to send upstream
+///
+/// The client address is appended to the chain the request arrived with, but
+/// only peers listed in `trusted_proxies` may supply that chain; anyone else
+/// could put arbitrary addresses in it, so it is replaced by the peer alone.
+/// With `max_forwarded_for_entries` the oldest entries are dropped. Without a
+/// known peer address no header is sent.
+pub fn forwarded_for(
+    incoming: &HeaderMap,
+    peer: Option<IpAddr>,
+    config: &ServerConfig,
+) -> Option<String> {
+    let peer = peer?.to_canonical();
+
+    let mut chain: Vec<&str> = Vec::new();
+    if is_trusted(peer, config) {
+        chain.extend(incoming_chain(incoming));
+    }
+    let peer = peer.to_string();
+    chain.push(&peer);
+
+    if let Some(max) = config.max_forwarded_for_entries
+        && chain.len() > max
+    {
+        chain.drain(..chain.len() - max);
+    }
+    Some(chain.join(", "))
+}
+
+/// Address of the client behind any trusted proxies
+///
+/// From a peer in `trusted_proxies` this is the last `X-Forwarded-For` entry
+/// that is not itself a trusted proxy; from anyone else it is the peer.
+pub fn client_address(
+    incoming: &HeaderMap,
+    peer: Option<IpAddr>,
+    config: &ServerConfig,
+) -> Option<IpAddr> {
+    let mut client = peer?.to_canonical();
+    if !is_trusted(client, config) {
+        return Some(client);
+    }
+    let chain: Vec<&str> = incoming_chain(incoming).collect();
+    for entry in chain.into_iter().rev() {
+        let Ok(addr) = entry.parse::<IpAddr>() else {
+            break;
+        };
+        client = addr.to_canonical();
+        if !is_trusted(client, config) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+fn is_trusted(peer: IpAddr, config: &ServerConfig) -> bool {
+    config
+        .trusted_proxies
+        .iter()
+        .any(|trusted| trusted.to_canonical() == peer)
+}
+
+fn incoming_chain(incoming: &HeaderMap) -> impl Iterator<Item = &str> {
+    incoming
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const PROXY: [u8; 4] = [10, 0, 0, 2];
+
+    fn config() -> ServerConfig {
+        let mut config = crate::test_support::test_config().server;
+        config.trusted_proxies = vec![IpAddr::from(PROXY)];
+        config
+    }
+
+    fn incoming(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(X_FORWARDED_FOR, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_trusted_peer_chain_is_extended() {
+        let peer = Some(IpAddr::from(PROXY));
+        let config = config();
+
+        let cases: [(&[&str], &str); 4] = [
+            (&[], "10.0.0.2"),
+            (&["203.0.113.7"], "203.0.113.7, 10.0.0.2"),
+            (
+                &["203.0.113.7, 198.51.100.1"],
+                "203.0.113.7, 198.51.100.1, 10.0.0.2",
+            ),
+            (
+                &["203.0.113.7", "198.51.100.1 ,"],
+                "203.0.113.7, 198.51.100.1, 10.0.0.2",
+            ),
+        ];
+        for (values, expected) in cases {
+            let value = forwarded_for(&incoming(values), peer, &config);
+            assert_eq!(value.as_deref(), Some(expected), "{:?}", values);
+        }
+    }
+
+    #[test]
+    fn test_untrusted_peer_chain_is_reset() {
+        let peer = Some(IpAddr::from([203, 0, 113, 7]));
+        let config = config();
+
+        for values in [&[][..], &["1.2.3.4"], &["1.2.3.4, 10.0.0.2", "5.6.7.8"]] {
+            let value = forwarded_for(&incoming(values), peer, &config);
+            assert_eq!(value.as_deref(), Some("203.0.113.7"), "{:?}", values);
+        }
+    }
+
+    #[test]
+    fn test_chain_length_cap() {
+        let mut config = config();
+        config.max_forwarded_for_entries = Some(2);
+        let value = forwarded_for(
+            &incoming(&["203.0.113.7, 198.51.100.1"]),
+            Some(IpAddr::from(PROXY)),
+            &config,
+        );
+        assert_eq!(value.as_deref(), Some("198.51.100.1, 10.0.0.2"));
+    }
+
+    #[test]
+    fn test_mapped_ipv6_peer_and_unknown_peer() {
+        let config = config();
+        let mapped = "::ffff:10.0.0.2".parse().ok();
+        let value = forwarded_for(&incoming(&["203.0.113.7"]), mapped, &config);
+        assert_eq!(value.as_deref(), Some("203.0.113.7, 10.0.0.2"));
+
+        assert_eq!(
+            forwarded_for(&incoming(&["203.0.113.7"]), None, &config),
+            None
+        );
+    }
+
+    #[test]
+    fn test_client_address() {
+        let mut config = config();
+        config.trusted_proxies.push(IpAddr::from([10, 0, 0, 3]));
+        let client = |values: &[&str], peer: [u8; 4]| {
+            client_address(&incoming(values), Some(IpAddr::from(peer)), &config)
+                .map(|addr| addr.to_string())
+        };
+
+        let cases: [(&[&str], [u8; 4], &str); 6] = [
+            (&[], PROXY, "10.0.0.2"),
+            (&["203.0.113.7"], PROXY, "203.0.113.7"),
+            (&["1.2.3.4, 203.0.113.7, 10.0.0.3"], PROXY, "203.0.113.7"),
+            (&["10.0.0.3"], PROXY, "10.0.0.3"),
+            (&["1.2.3.4, not-an-ip"], PROXY, "10.0.0.2"),
+            // Untrusted peers can't pick their address
+            (&["1.2.3.4"], [203, 0, 113, 9], "203.0.113.9"),
+        ];
+        for (values, peer, expected) in cases {
+            assert_eq!(
+                client(values, peer).as_deref(),
+                Some(expected),
+                "{:?}",
+                values
+            );
+        }
+    }
+}
@@ -1,220 +1,3309 @@
 use crate::AppState;
-use crate::error::{AppError, Result};
-use axum::{body::Body, extract::State, http::Request, response::Response};
-use http_body_util::BodyExt;
+use crate::auth::middleware::QUERY_TOKEN_PARAM;
+use crate::config::{
+    self, ArrApp, Config, OversizedBodyPolicy, OversizedHeaderPolicy, PreflightMode,
+    RoutePrecedence, ServerConfig,
+};
+use crate::error::{AppError, GeneratedError, Result};
+use crate::jellyfin::types::UserInfo;
+use crate::proxy::body::{self, BufferedBody};
+use crate::proxy::connection_limit::ConnectionGuard;
+use crate::proxy::forwarded::{self, X_FORWARDED_FOR};
+use crate::proxy::{cors, csp, idempotency, set_cookie};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderValue, Method, Request, Version, header},
+    response::{IntoResponse, Response},
+};
+use std::net::SocketAddr;
 use std::sync::Arc;
 
+/// Header telling the upstream which path prefix it is served under
+const X_FORWARDED_PREFIX: &str = "x-forwarded-prefix";
+
+/// Header naming the app a request is for, with `allow_header_routing`
+pub const X_BOUNCARR_APP: &str = "x-bouncarr-app";
+
+/// Header reporting upstream latency to browser dev tools
+const SERVER_TIMING: &str = "server-timing";
+
+/// Header carrying a client-assigned request ID, used in heartbeat logs
+const X_REQUEST_ID: &str = "x-request-id";
+
 pub async fn proxy_handler(
     State(state): State<Arc<AppState>>,
     req: Request<Body>,
 ) -> Result<Response> {
+    // HTTP/1.0 clients get a response delimited by closing the connection
+    // (never chunked), so tell them explicitly instead of leaving keep-alive
+    // to guesswork
+    let http_10 = req.version() == Version::HTTP_10;
+    if state.config.server.log_user
+        && let Some(user) = req.extensions().get::<UserInfo>()
+    {
+        tracing::Span::current().record("user", tracing::field::display(&user.username));
+    }
+    // Requests for an app that are refused or fail are counted as errors;
+    // successful ones are counted by traffic class once forwarded
+    let counted_app = state
+        .config
+        .metrics
+        .enabled
+        .then(|| resolve_route(&state.config, &req).map(|route| route.app.name.clone()))
+        .flatten();
+    let metrics = state.metrics.clone();
+    let mut response = route_request(state, req).await.inspect_err(|_| {
+        if let Some(app) = &counted_app {
+            metrics.add(
+                "bouncarr_requests_total",
+                &[("app", app), ("class", "error")],
+                1,
+            );
+        }
+    })?;
+    if http_10 {
+        response
+            .headers_mut()
+            .insert(header::CONNECTION, HeaderValue::from_static("close"));
+    }
+    Ok(response)
+}
+
+async fn route_request(state: Arc<AppState>, req: Request<Body>) -> Result<Response> {
+    // Tunnelling and request echoing make no sense for a reverse proxy
+    if req.method() == Method::CONNECT || req.method() == Method::TRACE {
+        return Err(AppError::UnsupportedMethod(format!(
+            "{} is not supported: Bouncarr is a reverse proxy, not a forward proxy",
+            req.method()
+        )));
+    }
+
     // Extract app name from the first path segment
     let path = req.uri().path();
-    let app_name = path
-        .trim_start_matches('/')
-        .split('/')
-        .next()
-        .unwrap_or("")
-        .to_string();
+    let app_name = app_name_from_path(path).to_string();
 
-    // Check if this is a WebSocket upgrade request by looking at headers
-    let is_websocket = req
+    // Reject blocked clients before contacting any upstream
+    let user_agent = req
         .headers()
-        .get("upgrade")
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok());
+    if state.user_agent_filter.is_blocked(user_agent) {
+        tracing::info!(
+            "Blocked request to {} from user agent {:?}",
+            path,
+            user_agent
+        );
+        return Err(AppError::AccessDenied("Client not allowed".to_string()));
+    }
+
+    // Check if this is a WebSocket upgrade request by looking at headers.
+    // Other upgrades (h2c, TLS, ...) can't be proxied and are refused rather
+    // than forwarded as a plain request without their upgrade semantics.
+    let upgrade = req.headers().get(header::UPGRADE);
+    let is_websocket = upgrade
         .and_then(|v| v.to_str().ok())
         .map(|v| v.eq_ignore_ascii_case("websocket"))
         .unwrap_or(false);
+    if let Some(upgrade) = upgrade
+        && !is_websocket
+    {
+        let protocol = String::from_utf8_lossy(upgrade.as_bytes()).into_owned();
+        tracing::debug!("Refusing upgrade to '{}' for {}", protocol, path);
+        return Err(AppError::NotImplemented(format!(
+            "Upgrade to '{}' is not supported, only websocket",
+            protocol
+        )));
+    }
+
+    // Find the arr app configuration, by Host or by path prefix
+    let route = resolve_route(&state.config, &req);
+
+    // Requests without a user were let through unauthenticated by the
+    // middleware, e.g. CORS preflights
+    if let Some(route) = route
+        && state.config.require_admin(route.app)
+        && let Some(user) = req.extensions().get::<UserInfo>()
+        && !user.is_administrator
+    {
+        tracing::info!(
+            "Denied {} access to admin-only app '{}'",
+            user.username,
+            route.app.name
+        );
+        return Err(AppError::Forbidden);
+    }
+
+    if let Some(route) = route
+        && route.app.verify_admin_live
+        && state.config.require_admin(route.app)
+    {
+        let user = req.extensions().get::<UserInfo>().cloned();
+        verify_live_admin(&state, user).await?;
+    }
+
+    if let Some(route) = route
+        && !route.app.required_groups.is_empty()
+    {
+        let user = req.extensions().get::<UserInfo>();
+        if !user.is_some_and(|user| user.in_any_group(&route.app.required_groups)) {
+            tracing::info!(
+                "Denied {} access to app '{}': not in a required group",
+                user.map_or("unknown user", |user| user.username.as_str()),
+                route.app.name
+            );
+            return Err(AppError::Forbidden);
+        }
+    }
+
+    if let Some(route) = route {
+        let path_and_query = upstream_path(&state.config, route, req.uri());
+        let path = path_and_query.split('?').next().unwrap_or_default();
+        if !route.app.is_path_allowed(path) {
+            tracing::info!("Refused request to {} on app '{}'", path, route.app.name);
+            return Err(AppError::AccessDenied(format!(
+                "Path '{}' is not allowed",
+                path
+            )));
+        }
+    }
+
+    if let Some(route) = route
+        && let Some(limit) = &route.app.rate_limit
+    {
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let client = forwarded::client_address(req.headers(), peer, &state.config.server);
+        if let Some(wait) = state.rate_limiter.check(&route.app.name, client, limit) {
+            tracing::debug!("Rate limited request to app '{}'", route.app.name);
+            return Err(AppError::RateLimited(wait.as_secs_f64().ceil() as u64));
+        }
+    }
 
     if is_websocket {
         tracing::debug!("WebSocket upgrade request detected for {}", path);
+        let app_name = route.map_or(app_name, |route| route.app.name.clone());
         return handle_websocket_upgrade_raw(state, app_name, req).await;
     }
-    // Find the arr app configuration
-    let arr_app = state
-        .config
-        .arr_apps
-        .iter()
-        .find(|app| app.name == app_name)
-        .ok_or_else(|| {
-            let available_apps: Vec<_> = state.config.arr_apps.iter().map(|a| &a.name).collect();
-            // Filter out noise from browser requests (.well-known, favicon, etc.)
-            if !app_name.starts_with('.') && app_name != "favicon.ico" {
-                tracing::warn!(
-                    "Request for unknown app '{}'. Available apps: {:?}",
-                    app_name,
-                    available_apps
-                );
-            }
-            AppError::AppNotFound(format!(
-                "App '{}' not found. Available apps: {:?}. \
+    let route = route.ok_or_else(|| {
+        let available_apps: Vec<_> = state.config.arr_apps.iter().map(|a| &a.name).collect();
+        // Filter out noise from browser requests (.well-known, favicon, etc.)
+        if !app_name.starts_with('.') && app_name != "favicon.ico" {
+            tracing::warn!(
+                "Request for unknown app '{}'. Available apps: {:?}",
+                app_name,
+                available_apps
+            );
+        }
+        AppError::AppNotFound(format!(
+            "App '{}' not found. Available apps: {:?}. \
                 Hint: Configure URL Base to '/{app_name}' in your *arr app settings.",
-                app_name, available_apps
-            ))
+            app_name, available_apps
+        ))
+    })?;
+    let arr_app = route.app;
+
+    // Answer CORS preflights locally if configured
+    if arr_app.preflight == PreflightMode::Local && cors::is_preflight(&req) {
+        return Ok(cors::preflight_response(arr_app, &req));
+    }
+
+    // Build target URL, removing the app prefix from path-routed requests
+    let path_and_query = upstream_path(&state.config, route, req.uri());
+    let target_url = format!("{}{}", arr_app.base_url(), path_and_query);
+    let upstream_path = path_and_query.split('?').next().unwrap_or_default();
+    let sensitive = arr_app.is_sensitive_path(upstream_path);
+
+    // Fail fast instead of sending a possibly large body to a dead upstream
+    if let Some(probe) = &arr_app.probe
+        && !req.method().is_idempotent()
+        && !state
+            .upstream_probes
+            .is_alive(
+                state.app_clients.client_for(arr_app, &state.http_client),
+                arr_app,
+                probe,
+            )
+            .await
+    {
+        return Err(AppError::UpstreamUnavailable(format!(
+            "App '{}' is not responding",
+            arr_app.name
+        )));
+    }
+
+    // Replay duplicate mutations sharing an Idempotency-Key if enabled.
+    // Responses for sensitive paths are never stored.
+    let response = if !sensitive && let Some(cache_key) = idempotency_cache_key(arr_app, &req) {
+        let ttl = std::time::Duration::from_secs(arr_app.idempotency_ttl_seconds.unwrap_or(0));
+        state
+            .idempotency_cache
+            .get_or_forward(cache_key, ttl, || {
+                forward_request(&state, arr_app, target_url, false, req)
+            })
+            .await?
+    } else {
+        forward_request(&state, arr_app, target_url, sensitive, req).await?
+    };
+
+    if state.config.metrics.enabled {
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        state.metrics.add(
+            "bouncarr_requests_total",
+            &[
+                ("app", &arr_app.name),
+                ("class", arr_app.traffic_class(upstream_path, content_type)),
+            ],
+            1,
+        );
+    }
+    Ok(response)
+}
+
+/// Check with Jellyfin that the requesting user is still an administrator
+async fn verify_live_admin(state: &AppState, user: Option<UserInfo>) -> Result<()> {
+    let user = user.ok_or(AppError::Forbidden)?;
+    let max_age = std::time::Duration::from_secs(state.config.security.live_admin_cache_seconds);
+    let is_admin = state
+        .live_admin_checks
+        .is_admin(&state.jellyfin_client, &user.user_id, max_age)
+        .await
+        .map_err(|e| {
+            tracing::warn!("Live admin check for {} failed: {}", user.username, e);
+            AppError::UpstreamUnavailable("Could not verify admin status".to_string())
         })?;
 
-    // Build target URL by removing app prefix from path
-    let path_and_query = build_upstream_path(req.uri(), &app_name);
-    let target_url = format!("{}{}", arr_app.url, path_and_query);
+    if !is_admin && state.config.security.is_bootstrap_admin(&user.username) {
+        tracing::warn!(
+            "Allowing non-admin '{}' because security.bootstrap_admin is set",
+            user.username
+        );
+        return Ok(());
+    }
+    if !is_admin {
+        tracing::info!(
+            "Denied {}: no longer a Jellyfin administrator",
+            user.username
+        );
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+/// Build the idempotency cache key for a request, if it should be deduplicated
+///
+/// Only mutations carrying an `Idempotency-Key` header to apps with
+/// idempotency enabled are deduplicated. The key is scoped to the app, user,
+/// method and path so unrelated requests can never share a response.
+fn idempotency_cache_key(arr_app: &ArrApp, req: &Request<Body>) -> Option<String> {
+    arr_app.idempotency_ttl_seconds.filter(|ttl| *ttl > 0)?;
+    if req.method().is_safe() {
+        return None;
+    }
+    let key = req
+        .headers()
+        .get(idempotency::IDEMPOTENCY_KEY_HEADER)?
+        .to_str()
+        .ok()?;
+    let user = req
+        .extensions()
+        .get::<UserInfo>()
+        .map(|u| u.user_id.as_str())
+        .unwrap_or("");
+    // Upstream bodies are passed through encoded, so a response compressed
+    // for one client must not be replayed to a client that can't decode it
+    let accept_encoding = req
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    Some(format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        arr_app.name,
+        user,
+        req.method(),
+        req.uri().path(),
+        accept_encoding,
+        key
+    ))
+}
+
+/// App a proxied request is routed to
+#[derive(Clone, Copy)]
+pub struct Route<'a> {
+    pub app: &'a ArrApp,
+    /// How the app was picked
+    pub rule: RouteRule,
+}
+
+/// What picked the app for a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteRule {
+    /// The `/<name>` path prefix, stripped before forwarding
+    Path,
+    /// The app's `host`
+    Host,
+    /// The `X-Bouncarr-App` header
+    Header,
+}
+
+impl RouteRule {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RouteRule::Path => "path",
+            RouteRule::Host => "host",
+            RouteRule::Header => "header",
+        }
+    }
+}
+
+impl Route<'_> {
+    /// Whether the app's path prefix is removed before forwarding
+    pub fn strip_prefix(&self) -> bool {
+        self.rule == RouteRule::Path
+    }
+}
+
+/// Find the app a request is for
+///
+/// Apps with a `host` match on the request's `Host`, and get the path as is.
+/// Otherwise the first path segment names the app. When both match different
+/// apps, `server.route_precedence` decides. With `allow_header_routing`, an
+/// `X-Bouncarr-App` header overrides both and also keeps the path as is.
+pub fn resolve_route<'a>(config: &'a Config, req: &Request<Body>) -> Option<Route<'a>> {
+    if config.server.allow_header_routing
+        && let Some(name) = req.headers().get(X_BOUNCARR_APP)
+    {
+        return name
+            .to_str()
+            .ok()
+            .and_then(|name| config.find_app(name.trim()))
+            .map(|app| Route {
+                app,
+                rule: RouteRule::Header,
+            });
+    }
+
+    let by_host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| req.uri().host())
+        .and_then(|host| config.find_app_by_host(host))
+        .map(|app| Route {
+            app,
+            rule: RouteRule::Host,
+        });
+    let by_path = config
+        .find_app(app_name_from_path(req.uri().path()))
+        .map(|app| Route {
+            app,
+            rule: RouteRule::Path,
+        });
+
+    match config.server.route_precedence {
+        RoutePrecedence::HostFirst => by_host.or(by_path),
+        RoutePrecedence::PathFirst => by_path.or(by_host),
+    }
+}
+
+/// Path and query a routed request is forwarded to, relative to the app's
+/// base URL
+pub fn upstream_path(config: &Config, route: Route<'_>, uri: &axum::http::Uri) -> String {
+    let mut path_and_query =
+        build_upstream_path(uri, route.strip_prefix().then_some(route.app.name.as_str()));
+    if route.strip_prefix() && config.server.collapse_duplicate_app_prefix {
+        let rest = strip_repeated_app_prefix(&path_and_query, &route.app.name);
+        if rest.len() < path_and_query.len() {
+            path_and_query = match rest {
+                "" => "/".to_string(),
+                rest if rest.starts_with('?') => format!("/{}", rest),
+                rest => rest.to_string(),
+            };
+        }
+    }
+    if config.server.normalize_slashes {
+        collapse_slashes(&path_and_query)
+    } else {
+        path_and_query
+    }
+}
+
+/// Requests for `/`: proxied if they are for an app's host, otherwise sent to
+/// the login page
+pub async fn root_handler(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+) -> Result<Response> {
+    if resolve_route(&state.config, &req).is_some_and(|route| !route.strip_prefix()) {
+        return proxy_handler(State(state), req).await;
+    }
+    Ok(axum::response::Redirect::permanent("/bouncarr/login").into_response())
+}
 
-    // Forward the request
-    forward_request(&state, target_url, req).await
+/// Extract the app name from the first segment of a request path
+///
+/// Only literal slashes separate segments: `/sonarr%2Fapi` names an app
+/// called `sonarr%2Fapi`, which never matches a configured app.
+pub fn app_name_from_path(path: &str) -> &str {
+    path.trim_start_matches('/').split('/').next().unwrap_or("")
 }
 
+/// Send a request upstream and stream back its response
+///
+/// Responses for `sensitive` paths get `Cache-Control: no-store` and their
+/// bodies are never logged.
 async fn forward_request(
     state: &AppState,
+    arr_app: &ArrApp,
     target_url: String,
+    sensitive: bool,
     req: Request<Body>,
 ) -> Result<Response> {
     let method = req.method().clone();
     let headers = req.headers().clone();
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
 
-    // Collect the body
-    let body_bytes = req
-        .into_body()
-        .collect()
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to read request body: {}", e);
-            AppError::ProxyError(format!("Failed to read request body: {}", e))
-        })?
-        .to_bytes();
+    // Heartbeat log description, built before the request is consumed
+    let heartbeat = state
+        .config
+        .server
+        .slow_request_heartbeat_seconds
+        .filter(|secs| *secs > 0)
+        .map(std::time::Duration::from_secs);
+    let in_flight = match heartbeat {
+        Some(_) => format!(
+            "{} ({} {} to '{}')",
+            request_id(&headers),
+            method,
+            req.uri().path(),
+            arr_app.name
+        ),
+        None => String::new(),
+    };
+
+    let logged_url = config::redact_query(&target_url, &state.config.server.redact_query_params);
+    if arr_app.log_requests {
+        tracing::debug!("Proxying {} {} to {}", method, req.uri().path(), logged_url);
+    }
+
+    // Collect the body, spilling large uploads to disk if configured
+    let spill_dir = state
+        .config
+        .server
+        .body_spill_dir
+        .clone()
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let body = BufferedBody::receive(
+        req.into_body(),
+        state.config.server.body_spill_threshold_bytes,
+        &spill_dir,
+    )
+    .await?;
+
+    if let Some(limit) = state.config.server.max_decompressed_request_bytes
+        && is_gzip_encoded(&headers)
+    {
+        match body::gzip_exceeds(&body, limit).await {
+            Ok(false) => {}
+            Ok(true) => {
+                tracing::warn!(
+                    "Rejected gzip request body to {} decompressing past {} bytes",
+                    logged_url,
+                    limit
+                );
+                return Err(AppError::PayloadTooLarge(format!(
+                    "Decompressed request body exceeds {} bytes",
+                    limit
+                )));
+            }
+            Err(e) => {
+                tracing::debug!("Invalid gzip request body to {}: {}", logged_url, e);
+                return Err(AppError::BadRequest(
+                    "Invalid gzip request body".to_string(),
+                ));
+            }
+        }
+    }
 
     // Build the proxied request
     let mut proxy_req = state
-        .http_client
+        .app_clients
+        .client_for(arr_app, &state.http_client)
         .request(method.clone(), &target_url)
-        .body(body_bytes.to_vec());
+        .body(body.to_reqwest_body().await?);
+    if let BufferedBody::File(spill_file) = &body {
+        proxy_req = proxy_req.header(axum::http::header::CONTENT_LENGTH, spill_file.len());
+    }
 
     // Forward relevant headers (skip host, connection, etc.)
     for (name, value) in headers.iter() {
         let name_str = name.as_str().to_lowercase();
-        if !should_skip_header(&name_str) {
-            proxy_req = proxy_req.header(name, value);
+        if should_skip_header(&name_str) {
+            continue;
+        }
+
+        // Injected headers replace whatever the client sent
+        if arr_app
+            .request_headers
+            .keys()
+            .any(|injected| injected.eq_ignore_ascii_case(&name_str))
+        {
+            continue;
+        }
+
+        // The configured user agent replaces the client's
+        if arr_app.upstream_user_agent.is_some() && name_str == "user-agent" {
+            continue;
+        }
+
+        // Routing is our business, not the app's
+        if state.config.server.allow_header_routing && name_str == X_BOUNCARR_APP {
+            continue;
+        }
+
+        // Never let clients pick the prefix we announce
+        if arr_app.forwarded_prefix && name_str == X_FORWARDED_PREFIX {
+            continue;
+        }
+
+        // Rebuilt below from trusted entries only
+        if name_str == X_FORWARDED_FOR {
+            continue;
+        }
+
+        // Make Referer/Origin look like they came from the upstream itself
+        if arr_app.rewrite_referer && (name == header::REFERER || name == header::ORIGIN) {
+            match rewrite_referer(
+                value,
+                &arr_app.base_url(),
+                &arr_app.name,
+                name == header::ORIGIN,
+            ) {
+                Some(rewritten) => proxy_req = proxy_req.header(name, rewritten),
+                None => tracing::debug!("Dropping unparseable {} header", name),
+            }
+            continue;
         }
+
+        proxy_req = proxy_req.header(name, value);
+    }
+
+    if arr_app.forwarded_prefix {
+        proxy_req = proxy_req.header(X_FORWARDED_PREFIX, format!("/{}", arr_app.name));
+    }
+    if !arr_app
+        .request_headers
+        .keys()
+        .any(|injected| injected.eq_ignore_ascii_case(X_FORWARDED_FOR))
+        && let Some(value) = forwarded::forwarded_for(&headers, peer, &state.config.server)
+    {
+        proxy_req = proxy_req.header(X_FORWARDED_FOR, value);
+    }
+    if let Some(user_agent) = &arr_app.upstream_user_agent {
+        proxy_req = proxy_req.header(header::USER_AGENT, user_agent.as_str());
+    }
+    for (name, value) in &arr_app.request_headers {
+        proxy_req = proxy_req.header(name.as_str(), value.as_str());
     }
 
     // Send the request
-    let response = proxy_req.send().await.map_err(|e| {
-        tracing::error!("Failed to proxy {} to {}: {}", method, target_url, e);
-        AppError::ProxyError(format!("Failed to proxy request to {}: {}", target_url, e))
+    let started = std::time::Instant::now();
+    let response = with_heartbeat(proxy_req.send(), heartbeat, |elapsed| {
+        tracing::info!(
+            "Request {} still waiting for upstream after {}s",
+            in_flight,
+            elapsed.as_secs()
+        )
+    })
+    .await
+    .map_err(|e| {
+        let e = e.without_url();
+        tracing::error!("Failed to proxy {} to {}: {}", method, logged_url, e);
+        AppError::ProxyError(format!("Failed to proxy request to {}: {}", logged_url, e))
     })?;
+    let server_timing = state
+        .config
+        .server
+        .emit_server_timing
+        .then(|| server_timing(started.elapsed()));
 
     let status = response.status();
-    tracing::debug!("Upstream response status: {}", status);
+    if arr_app.log_requests {
+        tracing::debug!("Upstream response status: {}", status);
+    }
+
+    if state.config.server.sanitize_upstream_errors && status.is_server_error() {
+        let mut response = sanitized_error_response(arr_app, &headers, response, sensitive).await;
+        cors::apply_response_headers(arr_app, &headers, &mut response);
+        if sensitive {
+            response
+                .headers_mut()
+                .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        }
+        if let Some(value) = server_timing {
+            response.headers_mut().append(SERVER_TIMING, value);
+        }
+        return Ok(response);
+    }
 
     // Convert reqwest::Response to axum::Response
     let mut builder = Response::builder().status(status);
 
     // Copy headers from the response
-    for (name, value) in response.headers().iter() {
-        let name_str = name.as_str().to_lowercase();
-        if !should_skip_header(&name_str) {
-            builder = builder.header(name, value);
+    if let Some(response_headers) = builder.headers_mut() {
+        copy_response_headers(response.headers(), response_headers, &state.config.server)?;
+
+        for name in &state.config.server.strip_response_headers {
+            response_headers.remove(name.to_lowercase().as_str());
+        }
+        if let Some(rewrite) = &arr_app.csp {
+            csp::apply(rewrite, response_headers);
+        }
+        if let Some(rewrite) = &arr_app.cookies {
+            set_cookie::apply(rewrite, response_headers);
+        }
+        if sensitive {
+            response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        }
+        if let Some(content_type) = state.config.default_content_type(arr_app)
+            && !response_headers.contains_key(header::CONTENT_TYPE)
+            && let Ok(value) = HeaderValue::try_from(content_type)
+        {
+            response_headers.insert(header::CONTENT_TYPE, value);
+        }
+        vary_on_encoding(response_headers);
+        if let Some(value) = server_timing {
+            response_headers.append(SERVER_TIMING, value);
+        }
+        // A truncated body won't match the declared length
+        if let Some(max_bytes) = state.config.server.max_response_bytes
+            && state.config.server.oversized_response_body == OversizedBodyPolicy::Truncate
+            && response.content_length().is_some_and(|len| len > max_bytes)
+        {
+            response_headers.remove(header::CONTENT_LENGTH);
         }
     }
 
-    let body_bytes = response.bytes().await.map_err(|e| {
-        tracing::error!("Failed to read response body: {}", e);
-        AppError::ProxyError(format!("Failed to read response body: {}", e))
-    })?;
+    let min_throughput =
+        state
+            .config
+            .server
+            .min_response_bytes_per_second
+            .map(|bytes_per_second| body::MinThroughput {
+                bytes_per_second,
+                window: std::time::Duration::from_secs(
+                    state.config.server.response_throughput_window_seconds,
+                ),
+            });
+    let max_size = state
+        .config
+        .server
+        .max_response_bytes
+        .map(|bytes| body::MaxSize {
+            bytes,
+            policy: state.config.server.oversized_response_body,
+        });
+    // Small bodies of known length are buffered, anything else is streamed.
+    // Bodies over max_response_bytes are left to the streaming limits.
+    let buffer = response.content_length().is_some_and(|len| {
+        state
+            .config
+            .server
+            .stream_threshold_bytes
+            .is_some_and(|threshold| len < threshold)
+            && max_size.is_none_or(|max| len <= max.bytes)
+    });
+    let response_body = if buffer {
+        body::buffer_response(response, &logged_url).await?
+    } else {
+        body::stream_response(response, &logged_url, min_throughput, max_size).await?
+    };
 
-    builder.body(Body::from(body_bytes)).map_err(|e| {
+    let mut response = builder.body(response_body).map_err(|e| {
         tracing::error!("Failed to build response: {}", e);
         AppError::ProxyError(format!("Failed to build response: {}", e))
-    })
+    })?;
+    cors::apply_response_headers(arr_app, &headers, &mut response);
+
+    Ok(response)
+}
+
+/// Replace an upstream error response with a generic one of the same status
+///
+/// Browsers get a small HTML page, other clients the usual JSON error. The
+/// original body is only logged at debug level, and not at all for
+/// sensitive paths.
+async fn sanitized_error_response(
+    arr_app: &ArrApp,
+    request_headers: &HeaderMap,
+    upstream: reqwest::Response,
+    sensitive: bool,
+) -> Response {
+    let status = upstream.status();
+    if sensitive {
+        tracing::debug!(
+            "Sanitized {} response from {} (sensitive path, body not logged)",
+            status,
+            arr_app.name
+        );
+    } else {
+        match upstream.text().await {
+            Ok(body) => tracing::debug!(
+                "Sanitized {} response from {}: {}",
+                status,
+                arr_app.name,
+                body
+            ),
+            Err(e) => tracing::debug!(
+                "Sanitized {} response from {} (body unreadable: {})",
+                status,
+                arr_app.name,
+                e
+            ),
+        }
+    }
+
+    let wants_html = request_headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"));
+
+    let mut response = if wants_html {
+        axum::response::Html(format!(
+            "<!DOCTYPE html><html><head><title>Bouncarr - Error</title></head>\
+            <body><h1>{}</h1><p>The application returned an error.</p></body></html>",
+            status
+        ))
+        .into_response()
+    } else {
+        axum::Json(serde_json::json!({ "error": "Upstream application error" })).into_response()
+    };
+    *response.status_mut() = status;
+    response.extensions_mut().insert(GeneratedError);
+    response
+}
+
+/// Copy upstream response headers, enforcing the configured header size limit
+///
+/// Oversized headers are either skipped or turned into a 502 naming the
+/// header, depending on `server.oversized_response_headers`.
+fn copy_response_headers(
+    upstream: &HeaderMap,
+    target: &mut HeaderMap,
+    server: &ServerConfig,
+) -> Result<()> {
+    for (name, value) in upstream.iter() {
+        let name_str = name.as_str().to_lowercase();
+        if should_skip_header(&name_str) {
+            continue;
+        }
+
+        let size = name.as_str().len() + value.len();
+        if size > server.max_response_header_bytes {
+            match server.oversized_response_headers {
+                OversizedHeaderPolicy::Skip => {
+                    tracing::warn!(
+                        "Skipping upstream response header '{}' ({} bytes exceeds limit of {})",
+                        name,
+                        size,
+                        server.max_response_header_bytes
+                    );
+                    continue;
+                }
+                OversizedHeaderPolicy::Reject => {
+                    tracing::error!(
+                        "Upstream response header '{}' is {} bytes (limit {})",
+                        name,
+                        size,
+                        server.max_response_header_bytes
+                    );
+                    return Err(AppError::ProxyError(format!(
+                        "Upstream response header '{}' exceeds the maximum size of {} bytes",
+                        name, server.max_response_header_bytes
+                    )));
+                }
+            }
+        }
+
+        target.append(name, value.clone());
+    }
+
+    Ok(())
+}
+
+/// Rewrite a client `Referer` or `Origin` header to point at the upstream
+///
+/// Origins become the upstream origin. Referers keep their path and query,
+/// with the `/{app_name}` prefix stripped like the request path itself.
+///
+/// For example, with upstream `http://sonarr:8989`:
+/// - `https://bouncarr.example.com/sonarr/series` -> `http://sonarr:8989/series`
+/// - `https://bouncarr.example.com` (Origin) -> `http://sonarr:8989`
+fn rewrite_referer(
+    value: &HeaderValue,
+    upstream_url: &str,
+    app_name: &str,
+    is_origin: bool,
+) -> Option<HeaderValue> {
+    let upstream = url::Url::parse(upstream_url).ok()?;
+    let upstream_origin = upstream.origin().ascii_serialization();
+
+    if is_origin {
+        return HeaderValue::from_str(&upstream_origin).ok();
+    }
+
+    let referer = url::Url::parse(value.to_str().ok()?).ok()?;
+    let uri: axum::http::Uri = match referer.query() {
+        Some(query) => format!("{}?{}", referer.path(), query),
+        None => referer.path().to_string(),
+    }
+    .parse()
+    .ok()?;
+
+    let base = upstream_url.trim_end_matches('/');
+    HeaderValue::from_str(&format!(
+        "{}{}",
+        base,
+        build_upstream_path(&uri, Some(app_name))
+    ))
+    .ok()
+}
+
+/// `Server-Timing` value reporting how long the upstream took to respond
+fn server_timing(elapsed: std::time::Duration) -> HeaderValue {
+    let millis = elapsed.as_secs_f64() * 1000.0;
+    HeaderValue::try_from(format!("upstream;dur={:.1}", millis))
+        .expect("formatted duration is a valid header value")
+}
+
+/// Add `Accept-Encoding` to `Vary` on encoded responses that don't list it,
+/// so caches between us and the client never serve a compressed body to a
+/// client that didn't ask for it
+fn vary_on_encoding(headers: &mut HeaderMap) {
+    if !headers.contains_key(header::CONTENT_ENCODING) {
+        return;
+    }
+    let varies = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|name| {
+            let name = name.trim();
+            name == "*" || name.eq_ignore_ascii_case("accept-encoding")
+        });
+    if !varies {
+        headers.append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
 }
 
 fn should_skip_header(name: &str) -> bool {
     matches!(
         name,
-        "host" | "connection" | "transfer-encoding" | "content-length"
+        "host"
+            | "connection"
+            | "keep-alive"
+            | "proxy-connection"
+            | "transfer-encoding"
+            | "content-length"
     )
 }
 
+/// Whether a request body is gzip-encoded, and nothing else
+fn is_gzip_encoded(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            let v = v.trim();
+            v.eq_ignore_ascii_case("gzip") || v.eq_ignore_ascii_case("x-gzip")
+        })
+}
+
 /// Build the upstream path by removing the app name prefix from the request URI
 ///
 /// For example:
 /// - `/sonarr/api/v3/series` -> `/api/v3/series`
 /// - `/radarr` -> `/`
 /// - `/radarr/` -> `/`
-fn build_upstream_path(uri: &axum::http::Uri, app_name: &str) -> String {
+fn build_upstream_path(uri: &axum::http::Uri, app_name: Option<&str>) -> String {
     uri.path_and_query()
         .map(|pq| {
             // Remove the /app_name prefix from the path
             let path = pq.path();
-            let prefix = format!("/{}", app_name);
-            let new_path = path.strip_prefix(&prefix).unwrap_or(path);
+            let new_path = match app_name {
+                Some(app_name) => strip_app_prefix(path, app_name).unwrap_or(path),
+                None => path,
+            };
 
             // If path is empty after stripping, default to "/"
             let new_path = if new_path.is_empty() { "/" } else { new_path };
 
-            // Append query string if present
-            if let Some(query) = pq.query() {
-                format!("{}?{}", new_path, query)
-            } else {
-                new_path.to_string()
+            // Append query string if present, minus any Bouncarr token
+            match pq.query().map(strip_query_token) {
+                Some(query) if !query.is_empty() => format!("{}?{}", new_path, query),
+                _ => new_path.to_string(),
             }
         })
         .unwrap_or_else(|| "/".to_string())
 }
 
+/// Remove a leading `/app_name` from a path, ignoring case
+fn strip_app_prefix<'a>(path: &'a str, app_name: &str) -> Option<&'a str> {
+    let prefix = path.get(..app_name.len() + 1)?;
+    let rest = path.get(app_name.len() + 1..)?;
+    (prefix.starts_with('/') && prefix[1..].eq_ignore_ascii_case(app_name)).then_some(rest)
+}
+
+/// The client's `X-Request-Id`, or a random ID if it sent none
+fn request_id(headers: &HeaderMap) -> String {
+    use rand::Rng;
+
+    headers
+        .get(X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+        .unwrap_or_else(|| format!("{:08x}", rand::thread_rng().r#gen::<u32>()))
+}
+
+/// Run `fut`, calling `log` with the elapsed time every `every` until it
+/// completes. `None` runs it without heartbeats.
+async fn with_heartbeat<F: std::future::Future>(
+    fut: F,
+    every: Option<std::time::Duration>,
+    log: impl Fn(std::time::Duration),
+) -> F::Output {
+    let Some(every) = every else {
+        return fut.await;
+    };
+    let started = tokio::time::Instant::now();
+    let mut ticks = tokio::time::interval_at(started + every, every);
+    tokio::pin!(fut);
+    loop {
+        tokio::select! {
+            output = &mut fut => return output,
+            _ = ticks.tick() => log(started.elapsed()),
+        }
+    }
+}
+
+/// Remove every leading `/app_name` segment, ignoring case
+///
+/// For example, `/sonarr/sonarr/api` -> `/api` for `sonarr`. `/sonarrx` is
+/// left alone.
+fn strip_repeated_app_prefix<'a>(path: &'a str, app_name: &str) -> &'a str {
+    let mut path = path;
+    while let Some(rest) = strip_app_prefix(path, app_name)
+        && (rest.is_empty() || rest.starts_with('/') || rest.starts_with('?'))
+    {
+        path = rest;
+    }
+    path
+}
+
+/// Collapse consecutive slashes in the path part of a path and query
+///
+/// For example, `/api/v3//series?path=//x` -> `/api/v3/series?path=//x`
+fn collapse_slashes(path_and_query: &str) -> String {
+    let (path, query) = match path_and_query.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path_and_query, None),
+    };
+
+    let mut collapsed = String::with_capacity(path_and_query.len());
+    for c in path.chars() {
+        if c == '/' && collapsed.ends_with('/') {
+            continue;
+        }
+        collapsed.push(c);
+    }
+    if let Some(query) = query {
+        collapsed.push('?');
+        collapsed.push_str(query);
+    }
+    collapsed
+}
+
+/// Remove the deep-link token parameter so it never reaches the upstream
+fn strip_query_token(query: &str) -> String {
+    query
+        .split('&')
+        .filter(|pair| pair.split('=').next() != Some(QUERY_TOKEN_PARAM))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// WebSocket URL of an app's base URL (`http` -> `ws`, `https` -> `wss`),
+/// without a trailing slash
+fn websocket_base_url(base_url: &str) -> Result<String> {
+    let mut target_url = url::Url::parse(base_url)
+        .map_err(|e| AppError::ProxyError(format!("Invalid app URL: {}", e)))?;
+
+    let scheme = match target_url.scheme() {
+        "http" => "ws",
+        "https" => "wss",
+        scheme => {
+            return Err(AppError::ProxyError(format!(
+                "Invalid URL scheme '{}', expected http or https",
+                scheme
+            )));
+        }
+    };
+    target_url
+        .set_scheme(scheme)
+        .map_err(|_| AppError::ProxyError("Failed to set WebSocket scheme".to_string()))?;
+
+    Ok(target_url.as_str().trim_end_matches('/').to_string())
+}
+
 async fn handle_websocket_upgrade_raw(
     state: Arc<AppState>,
     app_name: String,
     req: Request<Body>,
 ) -> Result<Response> {
-    use crate::proxy::websocket::proxy_websocket_connection;
+    use crate::proxy::websocket::{WebSocketOptions, proxy_websocket_connection};
 
     // Find the arr app configuration
-    let arr_app = state
-        .config
-        .arr_apps
-        .iter()
-        .find(|app| app.name == app_name)
-        .ok_or_else(|| {
-            let available_apps: Vec<_> = state.config.arr_apps.iter().map(|a| &a.name).collect();
-            AppError::AppNotFound(format!(
-                "App '{}' not found for WebSocket connection. Available apps: {:?}",
-                app_name, available_apps
-            ))
-        })?;
+    let arr_app = state.config.find_app(&app_name).ok_or_else(|| {
+        let available_apps: Vec<_> = state.config.arr_apps.iter().map(|a| &a.name).collect();
+        AppError::AppNotFound(format!(
+            "App '{}' not found for WebSocket connection. Available apps: {:?}",
+            app_name, available_apps
+        ))
+    })?;
+
+    if !arr_app.allow_websocket {
+        tracing::debug!("Refusing WebSocket upgrade to app '{}'", arr_app.name);
+        return Err(AppError::AccessDenied(format!(
+            "WebSocket connections to app '{}' are disabled",
+            arr_app.name
+        )));
+    }
 
     // Build the WebSocket URL
     // IMPORTANT: Keep the full path INCLUDING the app name prefix!
     // The *arr app is configured with URL Base matching our prefix,
     // so it expects WebSocket connections at /{app_name}/signalr/...
-    let path = req.uri().path();
-    let query = req
-        .uri()
-        .query()
-        .map(|q| format!("?{}", q))
-        .unwrap_or_default();
-
-    // Convert HTTP URL to WebSocket URL
-    let mut target_url = url::Url::parse(&arr_app.url)
-        .map_err(|e| AppError::ProxyError(format!("Invalid app URL: {}", e)))?;
+    let mut path = if state.config.server.normalize_slashes {
+        collapse_slashes(req.uri().path())
+    } else {
+        req.uri().path().to_string()
+    };
+    // The app expects its configured name, whatever case the client used
+    if let Some(rest) = strip_app_prefix(&path, &arr_app.name)
+        && (rest.is_empty() || rest.starts_with('/'))
+    {
+        let rest = if state.config.server.collapse_duplicate_app_prefix {
+            strip_repeated_app_prefix(rest, &arr_app.name)
+        } else {
+            rest
+        };
+        path = format!("/{}{}", arr_app.name, rest);
+    }
+    // Like proxied HTTP requests, never pass the Bouncarr token upstream
+    let query = match req.uri().query().map(strip_query_token) {
+        Some(query) if !query.is_empty() => format!("?{}", query),
+        _ => String::new(),
+    };
 
-    match target_url.scheme() {
-        "http" => target_url
-            .set_scheme("ws")
-            .map_err(|_| AppError::ProxyError("Failed to set WebSocket scheme".to_string()))?,
-        "https" => target_url
-            .set_scheme("wss")
-            .map_err(|_| AppError::ProxyError("Failed to set WebSocket scheme".to_string()))?,
-        scheme => {
-            return Err(AppError::ProxyError(format!(
-                "Invalid URL scheme '{}', expected http or https",
-                scheme
-            )));
+    let full_ws_url = format!(
+        "{}{}{}",
+        websocket_base_url(&arr_app.base_url())?,
+        path,
+        query
+    );
+
+    tracing::debug!("Proxying WebSocket connection to upstream");
+
+    let options = WebSocketOptions {
+        app_name: app_name.clone(),
+        frame_metrics: state
+            .config
+            .websocket
+            .frame_counters
+            .then(|| state.metrics.clone()),
+        connection_guard: req.extensions().get::<Arc<ConnectionGuard>>().cloned(),
+        idle_timeout: state.config.websocket_idle_timeout(arr_app),
+        max_lifetime: state.config.websocket_max_lifetime(arr_app),
+        unix_socket: arr_app
+            .unix_socket()
+            .map(|(socket, _)| std::path::PathBuf::from(socket)),
+        origin: arr_app
+            .rewrite_websocket_origin
+            .then(|| app_origin(arr_app))
+            .flatten(),
+    };
+
+    proxy_websocket_connection(req, full_ws_url, options).await
+}
+
+/// Origin (`scheme://host[:port]`) of an app's base URL
+fn app_origin(arr_app: &ArrApp) -> Option<HeaderValue> {
+    let url = url::Url::parse(&arr_app.base_url()).ok()?;
+    HeaderValue::try_from(url.origin().ascii_serialization()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{spawn_upstream, test_config, test_state};
+    use axum::{
+        Router,
+        http::{StatusCode, header},
+        routing::{options, post},
+    };
+
+    #[tokio::test]
+    async fn test_forward_spilled_body() {
+        let temp = crate::test_support::temp_dir();
+        let spill_dir = temp.path().to_path_buf();
+
+        // Upstream echoes the body and reports how many temp files exist while
+        // the request is in flight
+        let dir = spill_dir.clone();
+        let upstream = spawn_upstream(Router::new().route(
+            "/api/upload",
+            post(move |body: axum::body::Bytes| async move {
+                let spilled = std::fs::read_dir(&dir).unwrap().count();
+                format!("{}:{}", spilled, body.len())
+            }),
+        ))
+        .await;
+
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.body_spill_threshold_bytes = Some(1024);
+        config.server.body_spill_dir = Some(spill_dir.to_string_lossy().into_owned());
+        let state = test_state(config);
+
+        for (size, expected) in [(100, "0:100"), (5000, "1:5000")] {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/sonarr/api/upload")
+                .body(Body::from("z".repeat(size)))
+                .unwrap();
+            let res = proxy_handler(State(state.clone()), req).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes());
         }
+
+        // Temp file is cleaned up once the request completes
+        assert_eq!(std::fs::read_dir(&spill_dir).unwrap().count(), 0);
     }
 
-    // Build full WebSocket URL, keeping the app prefix
-    // Remove trailing slash from base URL to avoid double slashes
-    let base_url = target_url.as_str().trim_end_matches('/');
-    let full_ws_url = format!("{}{}{}", base_url, path, query);
+    #[test]
+    fn test_websocket_base_url() {
+        let cases = [
+            ("http://sonarr:8989", "ws://sonarr:8989"),
+            (
+                "https://sonarr.example.com/sonarr/",
+                "wss://sonarr.example.com/sonarr",
+            ),
+            ("HTTP://Sonarr:8989/base", "ws://sonarr:8989/base"),
+            ("HTTPS://sonarr.example.com", "wss://sonarr.example.com"),
+            (
+                "http://http-sonarr.local:8989",
+                "ws://http-sonarr.local:8989",
+            ),
+            ("https://httpsonarr/https://x", "wss://httpsonarr/https://x"),
+        ];
+        for (base_url, expected) in cases {
+            assert_eq!(
+                websocket_base_url(base_url).unwrap(),
+                expected,
+                "{}",
+                base_url
+            );
+        }
 
-    tracing::debug!("Proxying WebSocket connection to upstream");
+        assert!(websocket_base_url("ftp://sonarr").is_err());
+    }
 
-    proxy_websocket_connection(req, full_ws_url).await
+    #[tokio::test]
+    async fn test_websocket_refused_for_disabled_app() {
+        use axum::extract::ws::WebSocketUpgrade;
+        use tokio_tungstenite::{connect_async, tungstenite};
+
+        let upstream = spawn_upstream(
+            Router::new().fallback(|ws: WebSocketUpgrade| async { ws.on_upgrade(|_| async {}) }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        let mut radarr = config.arr_apps[0].clone();
+        radarr.name = "radarr".to_string();
+        radarr.allow_websocket = false;
+        config.arr_apps.push(radarr);
+        let proxy = spawn_upstream(
+            Router::new()
+                .fallback(proxy_handler)
+                .with_state(test_state(config)),
+        )
+        .await
+        .replacen("http://", "ws://", 1);
+
+        connect_async(format!("{}/sonarr/signalr", proxy))
+            .await
+            .expect("allowed app should upgrade");
+        match connect_async(format!("{}/radarr/signalr", proxy)).await {
+            Err(tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            }
+            other => panic!("expected refused upgrade, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket_query_token_not_forwarded() {
+        use axum::extract::ws::WebSocketUpgrade;
+        use futures_util::StreamExt;
+        use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+        // Upstream sends back the query string of the handshake
+        let upstream = spawn_upstream(Router::new().fallback(
+            |uri: axum::http::Uri, ws: WebSocketUpgrade| async move {
+                let query = uri.query().unwrap_or_default().to_string();
+                ws.on_upgrade(|mut socket| async move {
+                    let _ = socket.send(axum::extract::ws::Message::Text(query)).await;
+                })
+            },
+        ))
+        .await;
+
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        let proxy = spawn_upstream(
+            Router::new()
+                .fallback(proxy_handler)
+                .with_state(test_state(config)),
+        )
+        .await
+        .replacen("http://", "ws://", 1);
+
+        for (query, expected) in [
+            ("_bouncarr_token=secret-jwt", ""),
+            ("id=1&_bouncarr_token=secret-jwt&v=2", "id=1&v=2"),
+        ] {
+            let (mut client, _) = connect_async(format!("{}/sonarr/signalr?{}", proxy, query))
+                .await
+                .unwrap();
+            let reply = client.next().await.unwrap().unwrap();
+            assert_eq!(reply, Message::Text(expected.to_string()), "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket_origin_rewrite() {
+        use axum::extract::ws::WebSocketUpgrade;
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::{
+            connect_async,
+            tungstenite::{Message, client::IntoClientRequest},
+        };
+
+        // Upstream only accepts handshakes from its own origin
+        let upstream = spawn_upstream(Router::new().fallback(
+            |headers: HeaderMap, ws: WebSocketUpgrade| async move {
+                let expected = format!("http://{}", headers[header::HOST].to_str().unwrap());
+                if headers
+                    .get(header::ORIGIN)
+                    .is_none_or(|origin| origin != &expected)
+                {
+                    return StatusCode::FORBIDDEN.into_response();
+                }
+                ws.on_upgrade(|mut socket| async move {
+                    while let Some(Ok(msg)) = socket.recv().await {
+                        if socket.send(msg).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            },
+        ))
+        .await;
+
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        for rewrite in [false, true] {
+            config.arr_apps[0].rewrite_websocket_origin = rewrite;
+            let proxy = spawn_upstream(
+                Router::new()
+                    .fallback(proxy_handler)
+                    .with_state(test_state(config.clone())),
+            )
+            .await
+            .replacen("http://", "ws://", 1);
+
+            let mut request = format!("{}/sonarr/signalr", proxy)
+                .into_client_request()
+                .unwrap();
+            request.headers_mut().insert(
+                header::ORIGIN,
+                HeaderValue::from_static("https://bouncarr.example.com"),
+            );
+            let (mut client, _) = connect_async(request).await.unwrap();
+            client
+                .send(Message::Text("ping".to_string()))
+                .await
+                .unwrap();
+            let reply = client.next().await.unwrap().unwrap();
+            if rewrite {
+                assert_eq!(reply, Message::Text("ping".to_string()));
+            } else {
+                assert!(matches!(reply, Message::Close(_)), "{:?}", reply);
+            }
+        }
+    }
+
+    fn preflight_request() -> Request<Body> {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/sonarr/api/v3/series")
+            .header(header::ORIGIN, "https://dashboard.example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn spawn_options_upstream() -> String {
+        spawn_upstream(Router::new().route(
+            "/api/v3/series",
+            options(|| async { (StatusCode::OK, "from upstream") }),
+        ))
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_preflight_forwarded_by_default() {
+        let mut config = test_config();
+        config.arr_apps[0].url = spawn_options_upstream().await;
+        let state = test_state(config);
+
+        let res = proxy_handler(State(state), preflight_request())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"from upstream");
+    }
+
+    #[tokio::test]
+    async fn test_preflight_answered_locally() {
+        let mut config = test_config();
+        config.arr_apps[0].url = spawn_options_upstream().await;
+        config.arr_apps[0].preflight = PreflightMode::Local;
+        config.arr_apps[0].cors.allowed_origins = vec!["https://dashboard.example.com".to_string()];
+        let state = test_state(config);
+
+        let res = proxy_handler(State(state), preflight_request())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://dashboard.example.com"
+        );
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+    }
+
+    async fn spawn_large_header_upstream() -> String {
+        spawn_upstream(Router::new().route(
+            "/api/v3/system/status",
+            axum::routing::get(|| async {
+                (
+                    [
+                        ("x-huge", "a".repeat(100 * 1024)),
+                        ("x-small", "ok".to_string()),
+                    ],
+                    "status",
+                )
+            }),
+        ))
+        .await
+    }
+
+    fn status_request() -> Request<Body> {
+        Request::builder()
+            .uri("/sonarr/api/v3/system/status")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_header_skipped() {
+        let mut config = test_config();
+        config.arr_apps[0].url = spawn_large_header_upstream().await;
+        let state = test_state(config);
+
+        let res = proxy_handler(State(state), status_request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get("x-huge").is_none());
+        assert_eq!(res.headers()["x-small"], "ok");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_header_rejected() {
+        let mut config = test_config();
+        config.arr_apps[0].url = spawn_large_header_upstream().await;
+        config.server.oversized_response_headers = OversizedHeaderPolicy::Reject;
+        let state = test_state(config);
+
+        let err = proxy_handler(State(state), status_request())
+            .await
+            .unwrap_err();
+        match err {
+            AppError::ProxyError(msg) => assert!(msg.contains("'x-huge'")),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_deduplicates_posts() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let upstream = spawn_upstream(Router::new().route(
+            "/api/v3/series",
+            post(move || {
+                let counter = counter.clone();
+                async move {
+                    let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    (StatusCode::CREATED, format!("created #{}", n))
+                }
+            }),
+        ))
+        .await;
+
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].idempotency_ttl_seconds = Some(60);
+        let state = test_state(config);
+
+        let request = |key: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/sonarr/api/v3/series")
+                .header("Idempotency-Key", key)
+                .body(Body::from(r#"{"title":"Show"}"#))
+                .unwrap()
+        };
+
+        let first = proxy_handler(State(state.clone()), request("abc"))
+            .await
+            .unwrap();
+        let second = proxy_handler(State(state.clone()), request("abc"))
+            .await
+            .unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert_eq!(second.status(), StatusCode::CREATED);
+        assert!(first.headers().get("idempotent-replayed").is_none());
+        assert_eq!(second.headers()["idempotent-replayed"], "true");
+        let first = axum::body::to_bytes(first.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let second = axum::body::to_bytes(second.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+
+        // A different key is forwarded again
+        proxy_handler(State(state), request("def")).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_replay_respects_accept_encoding() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let upstream = spawn_upstream(Router::new().route(
+            "/api/v3/series",
+            post(move |headers: HeaderMap| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let gzip = headers
+                        .get(header::ACCEPT_ENCODING)
+                        .is_some_and(|v| v.to_str().unwrap().contains("gzip"));
+                    if gzip {
+                        ([(header::CONTENT_ENCODING, "gzip")], "gzipped").into_response()
+                    } else {
+                        "plain".into_response()
+                    }
+                }
+            }),
+        ))
+        .await;
+
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].idempotency_ttl_seconds = Some(60);
+        let state = test_state(config);
+
+        let send = |accept_encoding: Option<&'static str>| {
+            let state = state.clone();
+            async move {
+                let mut req = Request::builder()
+                    .method("POST")
+                    .uri("/sonarr/api/v3/series")
+                    .header("Idempotency-Key", "abc");
+                if let Some(accept_encoding) = accept_encoding {
+                    req = req.header(header::ACCEPT_ENCODING, accept_encoding);
+                }
+                let res = proxy_handler(State(state), req.body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let vary = res.headers().get(header::VARY).cloned();
+                let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (body, vary)
+            }
+        };
+
+        let (body, vary) = send(Some("gzip, br")).await;
+        assert_eq!(&body[..], b"gzipped");
+        assert_eq!(vary.unwrap(), "Accept-Encoding");
+        let (body, vary) = send(None).await;
+        assert_eq!(&body[..], b"plain");
+        assert!(vary.is_none());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // Each variant is replayed to matching clients only
+        let (body, _) = send(Some("gzip, br")).await;
+        assert_eq!(&body[..], b"gzipped");
+        let (body, _) = send(None).await;
+        assert_eq!(&body[..], b"plain");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_disabled_by_default() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let upstream = spawn_upstream(Router::new().route(
+            "/api/v3/series",
+            post(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { StatusCode::CREATED }
+            }),
+        ))
+        .await;
+
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        let state = test_state(config);
+
+        for _ in 0..2 {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/sonarr/api/v3/series")
+                .header("Idempotency-Key", "abc")
+                .body(Body::empty())
+                .unwrap();
+            proxy_handler(State(state.clone()), req).await.unwrap();
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_request_headers_injected() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/series",
+            axum::routing::get(|headers: HeaderMap| async move {
+                let values: Vec<_> = headers
+                    .get_all("x-api-key")
+                    .iter()
+                    .map(|v| v.to_str().unwrap().to_string())
+                    .collect();
+                format!(
+                    "{}|{}",
+                    values.join(","),
+                    headers["x-source"].to_str().unwrap()
+                )
+            }),
+        ))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].request_headers = std::collections::HashMap::from([
+            ("X-Api-Key".to_string(), "shared-secret".to_string()),
+            ("X-Source".to_string(), "bouncarr".to_string()),
+        ]);
+        let state = test_state(config);
+
+        let req = Request::builder()
+            .uri("/sonarr/series")
+            .header("x-api-key", "client-value")
+            .body(Body::empty())
+            .unwrap();
+        let res = proxy_handler(State(state), req).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"shared-secret|bouncarr");
+    }
+
+    #[tokio::test]
+    async fn test_upstream_user_agent_override() {
+        let upstream = spawn_upstream(Router::new().fallback(|headers: HeaderMap| async move {
+            headers
+                .get_all(header::USER_AGENT)
+                .iter()
+                .map(|v| v.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+
+        for (user_agent, expected) in [(None, "Mozilla/5.0"), (Some("Bouncarr"), "Bouncarr")] {
+            config.arr_apps[0].upstream_user_agent = user_agent.map(str::to_string);
+            let state = test_state(config.clone());
+            let req = Request::builder()
+                .uri("/sonarr/api/v3/series")
+                .header(header::USER_AGENT, "Mozilla/5.0")
+                .body(Body::empty())
+                .unwrap();
+            let res = proxy_handler(State(state), req).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes());
+        }
+    }
+
+    async fn spawn_referer_echo_upstream() -> String {
+        spawn_upstream(Router::new().route(
+            "/series",
+            axum::routing::get(|headers: HeaderMap| async move {
+                let get = |name| {
+                    headers
+                        .get(name)
+                        .map(|v: &HeaderValue| v.to_str().unwrap().to_string())
+                        .unwrap_or_default()
+                };
+                format!("{}|{}", get(header::REFERER), get(header::ORIGIN))
+            }),
+        ))
+        .await
+    }
+
+    fn referer_request() -> Request<Body> {
+        Request::builder()
+            .uri("/sonarr/series")
+            .header(
+                header::REFERER,
+                "https://bouncarr.example.com/sonarr/series/abc?page=2",
+            )
+            .header(header::ORIGIN, "https://bouncarr.example.com")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_referer_and_origin_rewritten() {
+        let upstream = spawn_referer_echo_upstream().await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream.clone();
+        config.arr_apps[0].rewrite_referer = true;
+        let state = test_state(config);
+
+        let res = proxy_handler(State(state), referer_request())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            format!("{}/series/abc?page=2|{}", upstream, upstream)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_referer_and_origin_passthrough_by_default() {
+        let mut config = test_config();
+        config.arr_apps[0].url = spawn_referer_echo_upstream().await;
+        let state = test_state(config);
+
+        let res = proxy_handler(State(state), referer_request())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            &body[..],
+            b"https://bouncarr.example.com/sonarr/series/abc?page=2|https://bouncarr.example.com"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_referer_with_upstream_base_path() {
+        let value = HeaderValue::from_static("https://proxy.example.com/radarr/movie/1");
+        let rewritten = rewrite_referer(&value, "http://radarr:7878/", "radarr", false).unwrap();
+        assert_eq!(rewritten, "http://radarr:7878/movie/1");
+
+        let value = HeaderValue::from_static("https://proxy.example.com");
+        let rewritten =
+            rewrite_referer(&value, "https://radarr.lan:7878/", "radarr", true).unwrap();
+        assert_eq!(rewritten, "https://radarr.lan:7878");
+    }
+
+    fn user_agent_request(user_agent: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/sonarr/api/v3/series");
+        if let Some(user_agent) = user_agent {
+            builder = builder.header(header::USER_AGENT, user_agent);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_blocked_user_agent_rejected_before_upstream() {
+        let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = hits.clone();
+        let upstream = spawn_upstream(Router::new().fallback(move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async { "ok" }
+        }))
+        .await;
+
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.blocked_user_agents = vec!["masscan".to_string(), r"/^curl\//".to_string()];
+        let state = test_state(config);
+
+        for user_agent in ["MASSCAN/1.3", "curl/8.5.0"] {
+            let err = proxy_handler(State(state.clone()), user_agent_request(Some(user_agent)))
+                .await
+                .unwrap_err();
+            assert!(matches!(err, AppError::AccessDenied(_)));
+        }
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        // Browsers and clients without a User-Agent still get through
+        for user_agent in [Some("Mozilla/5.0 (X11; Linux x86_64) Firefox/130.0"), None] {
+            let res = proxy_handler(State(state.clone()), user_agent_request(user_agent))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    /// Upstream that sends the given raw response bytes and then drops the
+    /// connection
+    async fn spawn_resetting_upstream(raw: &'static [u8]) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(raw).await;
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_upstream_reset_before_body_is_bad_gateway() {
+        let mut config = test_config();
+        config.arr_apps[0].url =
+            spawn_resetting_upstream(b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n").await;
+        let state = test_state(config);
+
+        let req = Request::builder()
+            .uri("/sonarr/api/v3/series")
+            .body(Body::empty())
+            .unwrap();
+        let err = proxy_handler(State(state), req).await.unwrap_err();
+        assert!(matches!(err, AppError::ProxyError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_upstream_reset_mid_body_terminates_stream() {
+        let mut config = test_config();
+        config.arr_apps[0].url = spawn_resetting_upstream(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\npartial body",
+        )
+        .await;
+        let state = test_state(config);
+
+        let req = Request::builder()
+            .uri("/sonarr/api/v3/series")
+            .body(Body::empty())
+            .unwrap();
+        let res = proxy_handler(State(state), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // The body ends with an error instead of hanging or looking complete
+        let body = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            axum::body::to_bytes(res.into_body(), usize::MAX),
+        )
+        .await
+        .expect("truncated body should not hang");
+        assert!(body.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_threshold() {
+        let upstream = spawn_resetting_upstream(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\npartial body",
+        )
+        .await;
+        let request = || {
+            Request::builder()
+                .uri("/sonarr/api/v3/series")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // Below the threshold the body is read before answering, so the
+        // failure is a 502 instead of a truncated response
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream.clone();
+        config.server.stream_threshold_bytes = Some(1001);
+        let err = proxy_handler(State(test_state(config)), request())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::ProxyError(_)));
+
+        // At or above it the response is already streaming
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.stream_threshold_bytes = Some(1000);
+        let res = proxy_handler(State(test_state(config)), request())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(
+            axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_heartbeat() {
+        let upstream = spawn_upstream(Router::new().fallback(|uri: axum::http::Uri| async move {
+            if uri.path() == "/api/v1/indexer/sync" {
+                tokio::time::sleep(std::time::Duration::from_millis(2300)).await;
+            }
+            "ok"
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.slow_request_heartbeat_seconds = Some(1);
+        let state = test_state(config);
+
+        let (_guard, logs) = crate::test_support::capture_logs();
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/sonarr/api/v1/indexer/sync")
+            .header(X_REQUEST_ID, "sync-42")
+            .body(Body::empty())
+            .unwrap();
+        let res = proxy_handler(State(state.clone()), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let logs = logs.contents();
+        let heartbeats: Vec<_> = logs
+            .lines()
+            .filter(|line| line.contains("still waiting for upstream"))
+            .collect();
+        assert_eq!(heartbeats.len(), 2, "{}", logs);
+        assert!(heartbeats[0].contains(
+            "Request sync-42 (POST /sonarr/api/v1/indexer/sync to 'sonarr') \
+            still waiting for upstream after 1s"
+        ));
+        assert!(heartbeats[1].contains("after 2s"));
+
+        // Fast requests log nothing
+        let (_guard, logs) = crate::test_support::capture_logs();
+        let req = Request::builder()
+            .uri("/sonarr/api/v3/series")
+            .body(Body::empty())
+            .unwrap();
+        proxy_handler(State(state), req).await.unwrap();
+        assert!(!logs.contents().contains("still waiting"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_counted_by_traffic_class() {
+        let upstream = spawn_upstream(
+            Router::new()
+                .route("/api/v3/series", axum::routing::get(|| async { "[]" }))
+                .fallback(|| async { axum::response::Html("<html></html>") }),
+        )
+        .await;
+        let mut config = test_config();
+        config.metrics.enabled = true;
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].traffic_classes = vec![
+            crate::config::TrafficClass {
+                name: "api".to_string(),
+                paths: vec!["/api/*".to_string()],
+                content_types: vec![],
+            },
+            crate::config::TrafficClass {
+                name: "ui".to_string(),
+                paths: vec![],
+                content_types: vec!["text/html".to_string()],
+            },
+        ];
+        config.arr_apps[0].deny_paths = vec!["/api/v3/system/*".to_string()];
+        let mut down = config.arr_apps[0].clone();
+        down.name = "radarr".to_string();
+        down.url = "http://127.0.0.1:1".to_string();
+        config.arr_apps.push(down);
+        let state = test_state(config);
+
+        for (path, ok) in [
+            ("/sonarr/api/v3/series", true),
+            ("/sonarr/api/v3/series?page=2", true),
+            ("/sonarr/calendar", true),
+            ("/sonarr/api/v3/system/status", false),
+            ("/radarr/api/v3/movie", false),
+        ] {
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            let res = proxy_handler(State(state.clone()), req).await;
+            assert_eq!(res.is_ok(), ok, "{}", path);
+        }
+
+        let count = |app: &str, class: &str| {
+            state
+                .metrics
+                .get("bouncarr_requests_total", &[("app", app), ("class", class)])
+        };
+        assert_eq!(count("sonarr", "api"), 2);
+        assert_eq!(count("sonarr", "ui"), 1);
+        assert_eq!(count("sonarr", "other"), 0);
+        // Refused and failed requests are errors, whatever their class
+        assert_eq!(count("sonarr", "error"), 1);
+        assert_eq!(count("radarr", "error"), 1);
+        assert_eq!(count("radarr", "api"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_log_requests_toggle() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream.clone();
+        let mut quiet = config.arr_apps[0].clone();
+        quiet.name = "radarr".to_string();
+        quiet.log_requests = false;
+        config.arr_apps.push(quiet);
+        let state = test_state(config);
+
+        let (_guard, logs) = crate::test_support::capture_logs();
+        for path in ["/sonarr/api/v3/series", "/radarr/api/v3/movie"] {
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            let res = proxy_handler(State(state.clone()), req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let logs = logs.contents();
+        assert!(logs.contains("Proxying GET /sonarr/api/v3/series"));
+        assert!(!logs.contains("Proxying GET /radarr"));
+        assert_eq!(logs.matches("Upstream response status").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_log_user() {
+        use tower_http::trace::MakeSpan;
+        use tracing::Instrument;
+
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let logged = |log_user: bool, user: Option<&str>| {
+            let mut config = test_config();
+            config.arr_apps[0].url = upstream.clone();
+            config.server.log_user = log_user;
+            let state = test_state(config);
+            let mut req = Request::builder()
+                .uri("/sonarr/api/v3/series")
+                .body(Body::empty())
+                .unwrap();
+            if let Some(user) = user {
+                req.extensions_mut().insert(UserInfo {
+                    username: user.to_string(),
+                    ..crate::test_support::test_user_info()
+                });
+            }
+            async move {
+                let (_guard, logs) = crate::test_support::capture_logs();
+                let span = crate::logging::RedactedMakeSpan::new(&[]).make_span(&req);
+                let res = proxy_handler(State(state), req)
+                    .instrument(span)
+                    .await
+                    .unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+                logs.contents()
+            }
+        };
+
+        let logs = logged(true, Some("alice")).await;
+        assert!(logs.contains("user=alice"), "{}", logs);
+        assert!(logs.contains("Proxying GET /sonarr/api/v3/series"));
+
+        let logs = logged(true, None).await;
+        assert!(!logs.contains("user="), "{}", logs);
+        let logs = logged(false, Some("alice")).await;
+        assert!(!logs.contains("alice"), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn test_logged_url_redacts_sensitive_query_params() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        let state = test_state(config);
+
+        let (_guard, logs) = crate::test_support::capture_logs();
+        let req = Request::builder()
+            .uri("/sonarr/feed/calendar.ics?apikey=s3cret&token=t0ken&unmonitored=true")
+            .body(Body::empty())
+            .unwrap();
+        let res = proxy_handler(State(state), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let logs = logs.contents();
+        assert!(
+            logs.contains("/feed/calendar.ics?apikey=***&token=***&unmonitored=true"),
+            "{}",
+            logs
+        );
+        assert!(!logs.contains("s3cret"));
+        assert!(!logs.contains("t0ken"));
+    }
+
+    async fn error_response(sanitize: bool, accept: &str) -> (StatusCode, String) {
+        let upstream = spawn_upstream(Router::new().fallback(|| async {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "System.NullReferenceException at Sonarr.Api.V3 (db.internal.lan)",
+            )
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.sanitize_upstream_errors = sanitize;
+        let state = test_state(config);
+
+        let req = Request::builder()
+            .uri("/sonarr/api/v3/series")
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap();
+        let res = proxy_handler(State(state), req).await.unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_upstream_errors_verbatim_by_default() {
+        let (status, body) = error_response(false, "application/json").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body.contains("NullReferenceException"));
+    }
+
+    #[tokio::test]
+    async fn test_upstream_errors_sanitized() {
+        let (status, body) = error_response(true, "application/json").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body, r#"{"error":"Upstream application error"}"#);
+
+        let (status, body) = error_response(true, "text/html").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body.contains("500 Internal Server Error"));
+        assert!(!body.contains("db.internal.lan"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_and_trace_rejected() {
+        let app = crate::build_router(test_state(test_config()));
+        let token = crate::test_support::access_token(true);
+
+        for method in [Method::CONNECT, Method::TRACE] {
+            let req = Request::builder()
+                .method(method.clone())
+                .uri("/sonarr/api/v3/series")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap();
+            let res = tower::ServiceExt::oneshot(app.clone(), req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED, "{}", method);
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(String::from_utf8_lossy(&body).contains("reverse proxy"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sensitive_path_never_cached() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let upstream = spawn_upstream(Router::new().fallback(move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                "apiKey=abc123"
+            }
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].idempotency_ttl_seconds = Some(60);
+        config.arr_apps[0].sensitive_paths = vec!["/api/v3/config/*".to_string()];
+        let state = test_state(config);
+
+        for _ in 0..2 {
+            let req = Request::builder()
+                .method("PUT")
+                .uri("/sonarr/api/v3/config/host")
+                .header("Idempotency-Key", "abc")
+                .body(Body::empty())
+                .unwrap();
+            let res = proxy_handler(State(state.clone()), req).await.unwrap();
+            assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+            assert!(res.headers().get("idempotent-replayed").is_none());
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sensitive_path_body_never_logged() {
+        let upstream = spawn_upstream(
+            Router::new()
+                .fallback(|| async { (StatusCode::INTERNAL_SERVER_ERROR, "backup key: hunter2") }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].sensitive_paths = vec!["/api/*/system/backup*".to_string()];
+        config.server.sanitize_upstream_errors = true;
+        let state = test_state(config);
+
+        let (_guard, logs) = crate::test_support::capture_logs();
+        for path in ["/sonarr/api/v3/system/backup", "/sonarr/api/v3/series"] {
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            let res = proxy_handler(State(state.clone()), req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+
+        // Only the non-sensitive request had its body logged
+        let logs = logs.contents();
+        assert_eq!(logs.matches("hunter2").count(), 1);
+        assert!(logs.contains("sensitive path, body not logged"));
+    }
+
+    #[tokio::test]
+    async fn test_demoted_admin_blocked_on_live_verify_app() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let jellyfin = crate::test_support::spawn_jellyfin(crate::test_support::jellyfin_user(
+            "user-1", "alice", false,
+        ))
+        .await;
+
+        let mut config = test_config();
+        config.jellyfin.url = jellyfin;
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].verify_admin_live = true;
+        let mut radarr = config.arr_apps[0].clone();
+        radarr.name = "radarr".to_string();
+        radarr.verify_admin_live = false;
+        config.arr_apps.push(radarr);
+        let state = test_state(config);
+
+        // The token still says admin, Jellyfin no longer does
+        let request = |path: &str| {
+            let mut req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            req.extensions_mut().insert(UserInfo {
+                user_id: "user-1".to_string(),
+                username: "alice".to_string(),
+                is_administrator: true,
+                groups: Vec::new(),
+            });
+            req
+        };
+
+        let err = proxy_handler(State(state.clone()), request("/sonarr/api"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::Forbidden));
+
+        let res = proxy_handler(State(state), request("/radarr/api"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_non_websocket_upgrade_not_implemented() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        let state = test_state(config);
+
+        for protocol in ["h2c", "TLS/1.2", "custom-app-protocol"] {
+            let req = Request::builder()
+                .uri("/sonarr/api")
+                .header(header::CONNECTION, "Upgrade")
+                .header(header::UPGRADE, protocol)
+                .body(Body::empty())
+                .unwrap();
+            let res = proxy_handler(State(state.clone()), req)
+                .await
+                .unwrap_err()
+                .into_response();
+            assert_eq!(res.status(), StatusCode::NOT_IMPLEMENTED, "{}", protocol);
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let expected = format!(
+                r#"{{"error":"Upgrade to '{}' is not supported, only websocket"}}"#,
+                protocol
+            );
+            assert_eq!(String::from_utf8_lossy(&body), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_required_groups() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].required_groups = vec!["Media-Admins".to_string()];
+        let mut radarr = config.arr_apps[0].clone();
+        radarr.name = "radarr".to_string();
+        radarr.required_groups = Vec::new();
+        config.arr_apps.push(radarr);
+        let state = test_state(config);
+
+        let request = |path: &str, groups: &[&str]| {
+            let mut req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            req.extensions_mut().insert(UserInfo {
+                groups: groups.iter().map(|g| g.to_string()).collect(),
+                ..crate::test_support::test_user_info()
+            });
+            req
+        };
+
+        for groups in [&[][..], &["family"]] {
+            let err = proxy_handler(State(state.clone()), request("/sonarr/api", groups))
+                .await
+                .unwrap_err();
+            assert!(matches!(err, AppError::Forbidden));
+        }
+
+        let res = proxy_handler(
+            State(state.clone()),
+            request("/sonarr/api", &["family", "media-admins"]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Apps without required groups keep the existing rules
+        let res = proxy_handler(State(state), request("/radarr/api", &[]))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_http_10_client_gets_close_delimited_response() {
+        use futures_util::StreamExt;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Streamed upstream body of unknown length, which an HTTP/1.1 client
+        // would get chunked
+        let upstream = spawn_upstream(Router::new().fallback(|| async {
+            let chunks = futures_util::stream::iter(["hello ", "from ", "sonarr"])
+                .map(|chunk| Ok::<_, std::io::Error>(axum::body::Bytes::from(chunk)));
+            ([("keep-alive", "timeout=5")], Body::from_stream(chunks))
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        let app = crate::build_router(test_state(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(crate::listener::serve(
+            listener,
+            app,
+            false,
+            None,
+            std::future::pending(),
+        ));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET /sonarr/api HTTP/1.0\r\nAuthorization: Bearer {}\r\n\r\n",
+            crate::test_support::access_token(true)
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            stream.read_to_string(&mut response),
+        )
+        .await
+        .expect("server should close the connection after the response")
+        .unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let head = head.to_ascii_lowercase();
+        assert!(head.starts_with("http/1.0 200"), "{}", head);
+        assert!(!head.contains("transfer-encoding"), "{}", head);
+        assert!(!head.contains("keep-alive"), "{}", head);
+        assert!(head.contains("connection: close"), "{}", head);
+        assert_eq!(body, "hello from sonarr");
+    }
+
+    #[tokio::test]
+    async fn test_app_name_matched_case_insensitively() {
+        let upstream = spawn_upstream(
+            Router::new().fallback(|uri: axum::http::Uri| async move { uri.to_string() }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        let state = test_state(config.clone());
+
+        let req = Request::builder()
+            .uri("/Sonarr/api/v3/series")
+            .body(Body::empty())
+            .unwrap();
+        let res = proxy_handler(State(state), req).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"/api/v3/series");
+
+        config.server.case_insensitive_apps = false;
+        let req = Request::builder()
+            .uri("/Sonarr/api/v3/series")
+            .body(Body::empty())
+            .unwrap();
+        let err = proxy_handler(State(test_state(config)), req)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::AppNotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn test_query_token_stripped_before_forwarding() {
+        let upstream = spawn_upstream(
+            Router::new().fallback(|uri: axum::http::Uri| async move { uri.to_string() }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        let state = test_state(config);
+
+        for (path, expected) in [
+            (
+                "/sonarr/calendar?_bouncarr_token=abc&week=2",
+                "/calendar?week=2",
+            ),
+            ("/sonarr/calendar?_bouncarr_token=abc", "/calendar"),
+        ] {
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            let res = proxy_handler(State(state.clone()), req).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes());
+        }
+    }
+
+    /// Upstream whose body is produced by sending `chunk` every `interval`,
+    /// `count` times, after an initial `delay`
+    async fn spawn_trickling_upstream(
+        delay: std::time::Duration,
+        chunk: &'static [u8],
+        interval: std::time::Duration,
+        count: usize,
+    ) -> String {
+        spawn_upstream(Router::new().fallback(move || async move {
+            let stream = futures_util::stream::unfold(0, move |sent| async move {
+                if sent == count {
+                    return None;
+                }
+                let wait = if sent == 0 { delay } else { interval };
+                tokio::time::sleep(wait).await;
+                Some((
+                    Ok::<_, std::io::Error>(axum::body::Bytes::from_static(chunk)),
+                    sent + 1,
+                ))
+            });
+            Body::from_stream(stream)
+        }))
+        .await
+    }
+
+    fn throughput_state(upstream: String) -> Arc<AppState> {
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.min_response_bytes_per_second = Some(100);
+        config.server.response_throughput_window_seconds = 1;
+        test_state(config)
+    }
+
+    fn series_request() -> Request<Body> {
+        Request::builder()
+            .uri("/sonarr/api/v3/series")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_trickling_upstream_body_is_aborted() {
+        let ms = std::time::Duration::from_millis;
+        let upstream = spawn_trickling_upstream(ms(0), b"x", ms(200), 50).await;
+        let state = throughput_state(upstream);
+
+        let res = proxy_handler(State(state), series_request()).await.unwrap();
+        let body = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            axum::body::to_bytes(res.into_body(), usize::MAX),
+        )
+        .await
+        .expect("stalled body should be aborted");
+        assert!(body.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stall_before_first_chunk_is_gateway_timeout() {
+        let ms = std::time::Duration::from_millis;
+        let upstream = spawn_trickling_upstream(ms(3000), b"x", ms(0), 1).await;
+        let state = throughput_state(upstream);
+
+        let err = proxy_handler(State(state), series_request())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::UpstreamTimeout(_)));
+        assert_eq!(err.into_response().status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_steady_slow_stream_is_not_aborted() {
+        let ms = std::time::Duration::from_millis;
+        let chunk: &'static [u8] = &[b'x'; 64];
+        // 64 bytes every 200ms is ~320 bytes/s for 2 seconds
+        let upstream = spawn_trickling_upstream(ms(0), chunk, ms(200), 10).await;
+        let state = throughput_state(upstream);
+
+        let res = proxy_handler(State(state), series_request()).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.len(), 640);
+    }
+
+    #[tokio::test]
+    async fn test_strip_response_headers() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async {
+            (
+                [
+                    ("server", "Kestrel"),
+                    ("x-powered-by", "ASP.NET"),
+                    ("x-custom", "kept"),
+                ],
+                "ok",
+            )
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.strip_response_headers =
+            vec!["Server".to_string(), "X-POWERED-BY".to_string()];
+        let state = test_state(config);
+
+        let res = proxy_handler(State(state), series_request()).await.unwrap();
+        assert!(res.headers().get("server").is_none());
+        assert!(res.headers().get("x-powered-by").is_none());
+        assert_eq!(res.headers()["x-custom"], "kept");
+    }
+
+    #[tokio::test]
+    async fn test_server_timing_header() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            "ok"
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+
+        let request = || {
+            Request::builder()
+                .uri("/sonarr/api")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let res = proxy_handler(State(test_state(config.clone())), request())
+            .await
+            .unwrap();
+        assert!(res.headers().get(SERVER_TIMING).is_none());
+
+        config.server.emit_server_timing = true;
+        let res = proxy_handler(State(test_state(config)), request())
+            .await
+            .unwrap();
+        let value = res.headers()[SERVER_TIMING].to_str().unwrap();
+        let millis: f64 = value
+            .strip_prefix("upstream;dur=")
+            .expect("upstream metric")
+            .parse()
+            .unwrap();
+        assert!((50.0..5000.0).contains(&millis), "{}", value);
+    }
+
+    #[tokio::test]
+    async fn test_default_content_type() {
+        let upstream = spawn_upstream(Router::new().fallback(|uri: axum::http::Uri| async move {
+            let mut res = Response::new(Body::from("data"));
+            if uri.path() == "/typed" {
+                res.headers_mut()
+                    .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+            }
+            res
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+
+        let content_type = |config: Config, path: &str| {
+            let req = Request::builder()
+                .uri(format!("/sonarr{}", path))
+                .body(Body::empty())
+                .unwrap();
+            async move {
+                let res = proxy_handler(State(test_state(config)), req).await.unwrap();
+                res.headers()
+                    .get(header::CONTENT_TYPE)
+                    .map(|v| v.to_str().unwrap().to_string())
+            }
+        };
+
+        assert_eq!(content_type(config.clone(), "/untyped").await, None);
+
+        config.server.default_content_type = Some("application/octet-stream".to_string());
+        assert_eq!(
+            content_type(config.clone(), "/untyped").await.as_deref(),
+            Some("application/octet-stream")
+        );
+        assert_eq!(
+            content_type(config.clone(), "/typed").await.as_deref(),
+            Some("text/plain")
+        );
+
+        config.arr_apps[0].default_content_type = Some("application/json".to_string());
+        assert_eq!(
+            content_type(config, "/untyped").await.as_deref(),
+            Some("application/json")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encoded_slashes_preserved() {
+        let upstream = spawn_upstream(
+            Router::new().fallback(|uri: axum::http::Uri| async move { uri.to_string() }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        let state = test_state(config);
+
+        for (path, expected) in [
+            ("/sonarr/api%2Fv3/series", "/api%2Fv3/series"),
+            (
+                "/sonarr/api/v3/series/a%2fb?path=%2Ftv",
+                "/api/v3/series/a%2fb?path=%2Ftv",
+            ),
+            ("/sonarr/%2F%2Fapi", "/%2F%2Fapi"),
+        ] {
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            let res = proxy_handler(State(state.clone()), req).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes(), "{}", path);
+        }
+
+        // An encoded slash doesn't end the app name
+        assert_eq!(app_name_from_path("/sonarr%2Fapi/v3"), "sonarr%2Fapi");
+        let req = Request::builder()
+            .uri("/sonarr%2Fapi/v3")
+            .body(Body::empty())
+            .unwrap();
+        let err = proxy_handler(State(state), req).await.unwrap_err();
+        assert!(matches!(err, AppError::AppNotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn test_app_url_trailing_slash() {
+        let upstream = spawn_upstream(
+            Router::new().fallback(|uri: axum::http::Uri| async move { uri.to_string() }),
+        )
+        .await;
+
+        for url in [upstream.clone(), format!("{}/", upstream)] {
+            let mut config = test_config();
+            config.arr_apps[0].url = url.clone();
+            let state = test_state(config);
+            let req = Request::builder()
+                .uri("/sonarr/api/v3/series")
+                .body(Body::empty())
+                .unwrap();
+            let res = proxy_handler(State(state), req).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], b"/api/v3/series", "{}", url);
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_gzip_request_decompressed_size_limit() {
+        let upstream = spawn_upstream(
+            Router::new().fallback(|body: axum::body::Bytes| async move { body.len().to_string() }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.max_decompressed_request_bytes = Some(64 * 1024);
+        let state = test_state(config);
+        let send = |body: Vec<u8>| {
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri("/sonarr/api/v3/command")
+                .header(header::CONTENT_ENCODING, "gzip")
+                .body(Body::from(body))
+                .unwrap();
+            proxy_handler(State(state.clone()), req)
+        };
+
+        // A few KB that inflate to 10 MB
+        let bomb = gzip(&vec![0u8; 10 * 1024 * 1024]);
+        assert!(bomb.len() < 64 * 1024);
+        let err = send(bomb).await.unwrap_err();
+        assert_eq!(err.into_response().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Within the limit the body is forwarded still compressed
+        let small = gzip(&[b'a'; 1024]);
+        let res = send(small.clone()).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], small.len().to_string().as_bytes());
+
+        let err = send(b"not gzip".to_vec()).await.unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(_)));
+    }
+
+    #[tokio::test]
+    async fn test_app_rate_limit() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream.clone();
+        config.arr_apps[0].rate_limit = Some(crate::config::RateLimit {
+            requests_per_second: 0.5,
+            burst: Some(2),
+            per_client: false,
+        });
+        let mut unlimited = config.arr_apps[0].clone();
+        unlimited.name = "radarr".to_string();
+        unlimited.rate_limit = None;
+        config.arr_apps.push(unlimited);
+        let state = test_state(config);
+
+        let send = |path: &str| {
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            proxy_handler(State(state.clone()), req)
+        };
+        for _ in 0..2 {
+            assert_eq!(send("/sonarr/api").await.unwrap().status(), StatusCode::OK);
+        }
+        let res = send("/sonarr/api").await.unwrap_err().into_response();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "2");
+
+        for _ in 0..5 {
+            assert_eq!(send("/radarr/api").await.unwrap().status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_per_client_rate_limit_behind_trusted_proxy() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].rate_limit = Some(crate::config::RateLimit {
+            requests_per_second: 0.5,
+            burst: Some(1),
+            per_client: true,
+        });
+        config.server.trusted_proxies = vec!["10.0.0.2".parse().unwrap()];
+        let state = test_state(config);
+
+        let send = |client: &str| {
+            let mut req = Request::builder()
+                .uri("/sonarr/api")
+                .header("X-Forwarded-For", client)
+                .body(Body::empty())
+                .unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 2], 40000))));
+            proxy_handler(State(state.clone()), req)
+        };
+        assert!(send("203.0.113.7").await.is_ok());
+        assert!(matches!(
+            send("203.0.113.7").await,
+            Err(AppError::RateLimited(_))
+        ));
+        // Clients behind the same proxy have their own buckets
+        assert!(send("203.0.113.8").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_for_chain() {
+        let upstream = spawn_upstream(Router::new().fallback(|headers: HeaderMap| async move {
+            headers
+                .get("x-forwarded-for")
+                .map(|v| v.to_str().unwrap().to_string())
+                .unwrap_or_default()
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.trusted_proxies = vec!["10.0.0.2".parse().unwrap()];
+        let state = test_state(config);
+
+        for (peer, incoming, expected) in [
+            ([10, 0, 0, 2], None, "10.0.0.2"),
+            ([10, 0, 0, 2], Some("203.0.113.7"), "203.0.113.7, 10.0.0.2"),
+            ([203, 0, 113, 7], Some("1.2.3.4"), "203.0.113.7"),
+        ] {
+            let mut req = Request::builder().uri("/sonarr/api/v3/series");
+            if let Some(incoming) = incoming {
+                req = req.header("X-Forwarded-For", incoming);
+            }
+            let mut req = req.body(Body::empty()).unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from((peer, 40000))));
+            let res = proxy_handler(State(state.clone()), req).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes(), "{:?}", incoming);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_allow_and_deny_paths() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async { "ok" })).await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+
+        let status = |config: Config, path: &str| {
+            let req = Request::builder()
+                .uri(format!("/sonarr{}", path))
+                .body(Body::empty())
+                .unwrap();
+            async move {
+                match proxy_handler(State(test_state(config)), req).await {
+                    Ok(res) => res.status(),
+                    Err(e) => e.into_response().status(),
+                }
+            }
+        };
+
+        config.arr_apps[0].deny_paths = vec!["/api/v3/config/host".to_string()];
+        assert_eq!(
+            status(config.clone(), "/api/v3/config/host").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(config.clone(), "/api/v3/series").await,
+            StatusCode::OK
+        );
+        // The app ignores case and a trailing slash, and so does the rule
+        for path in [
+            "/API/v3/config/host",
+            "/api/v3/Config/HOST",
+            "/api/v3/config/host/",
+            "/api/v3/config/host//",
+            "/api/v3/config%2FHost/",
+        ] {
+            assert_eq!(
+                status(config.clone(), path).await,
+                StatusCode::FORBIDDEN,
+                "{}",
+                path
+            );
+        }
+
+        // With an allowlist, unlisted paths are refused and deny still wins
+        config.arr_apps[0].allow_paths = vec!["/api/*".to_string()];
+        assert_eq!(
+            status(config.clone(), "/api/v3/series?page=2").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(config.clone(), "/system/status").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(config.clone(), "/API/v3/series").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(config.clone(), "/api%2Fv3%2Fconfig/host").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(config, "/api/v3/config/host").await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_header_routing() {
+        let upstream = spawn_upstream(Router::new().fallback(
+            |uri: axum::http::Uri, headers: HeaderMap| async move {
+                format!("{} {}", uri, headers.contains_key(X_BOUNCARR_APP))
+            },
+        ))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+
+        let send = |config: Config| {
+            let req = Request::builder()
+                .uri("/api/v3/series?page=2")
+                .header(X_BOUNCARR_APP, "sonarr")
+                .body(Body::empty())
+                .unwrap();
+            async move {
+                match proxy_handler(State(test_state(config)), req).await {
+                    Ok(res) => {
+                        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                            .await
+                            .unwrap();
+                        (StatusCode::OK, String::from_utf8(body.to_vec()).unwrap())
+                    }
+                    Err(e) => (e.into_response().status(), String::new()),
+                }
+            }
+        };
+
+        // Ignored unless enabled: "api" is not an app
+        let (status, _) = send(config.clone()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        config.server.allow_header_routing = true;
+        let (status, body) = send(config).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "/api/v3/series?page=2 false");
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_prefix_header() {
+        let upstream = spawn_upstream(Router::new().fallback(|headers: HeaderMap| async move {
+            headers
+                .get(X_FORWARDED_PREFIX)
+                .map(|v| v.to_str().unwrap().to_string())
+                .unwrap_or_else(|| "none".to_string())
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+
+        for (enabled, expected) in [(false, "/spoofed"), (true, "/sonarr")] {
+            config.arr_apps[0].forwarded_prefix = enabled;
+            let state = test_state(config.clone());
+            let req = Request::builder()
+                .uri("/sonarr/api/v3/series")
+                .header(X_FORWARDED_PREFIX, "/spoofed")
+                .body(Body::empty())
+                .unwrap();
+            let res = proxy_handler(State(state), req).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_content_security_policy_rewrite() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async {
+            (
+                [(
+                    header::CONTENT_SECURITY_POLICY,
+                    "default-src 'self'; frame-ancestors 'none'",
+                )],
+                "ok",
+            )
+        }))
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+
+        // Passed through untouched by default
+        let state = test_state(config.clone());
+        let res = proxy_handler(State(state), series_request()).await.unwrap();
+        assert_eq!(
+            res.headers()[header::CONTENT_SECURITY_POLICY],
+            "default-src 'self'; frame-ancestors 'none'"
+        );
+
+        config.arr_apps[0].csp = Some(crate::config::CspRewrite {
+            set: [("frame-ancestors".to_string(), "'self'".to_string())].into(),
+            ..Default::default()
+        });
+        let state = test_state(config);
+        let res = proxy_handler(State(state), series_request()).await.unwrap();
+        assert_eq!(
+            res.headers()[header::CONTENT_SECURITY_POLICY],
+            "default-src 'self'; frame-ancestors 'self'"
+        );
+    }
+
+    /// POST whose body records whether anything tried to read it
+    fn tracked_upload() -> (Request<Body>, Arc<std::sync::atomic::AtomicBool>) {
+        let read = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = read.clone();
+        let body = futures_util::stream::once(async move {
+            flag.store(true, std::sync::atomic::Ordering::SeqCst);
+            Ok::<_, std::io::Error>(axum::body::Bytes::from_static(b"upload"))
+        });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sonarr/api/v3/command")
+            .body(Body::from_stream(body))
+            .unwrap();
+        (req, read)
+    }
+
+    #[tokio::test]
+    async fn test_probe_short_circuits_dead_upstream() {
+        let mut config = test_config();
+        config.arr_apps[0].probe = Some(Default::default());
+        let state = test_state(config);
+
+        let (req, read) = tracked_upload();
+        let err = proxy_handler(State(state), req).await.unwrap_err();
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert!(!read.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_probe_result_is_cached() {
+        let probes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = probes.clone();
+        let upstream = spawn_upstream(
+            Router::new()
+                .route(
+                    "/ping",
+                    axum::routing::get(move || async move {
+                        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        "pong"
+                    }),
+                )
+                .fallback(|| async { "created" }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].probe = Some(Default::default());
+        let state = test_state(config);
+
+        for _ in 0..2 {
+            let (req, read) = tracked_upload();
+            let res = proxy_handler(State(state.clone()), req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(read.load(std::sync::atomic::Ordering::SeqCst));
+        }
+        // Safe methods are never probed
+        proxy_handler(State(state), series_request()).await.unwrap();
+        assert_eq!(probes.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    fn size_limited_state(upstream: String, policy: OversizedBodyPolicy) -> Arc<AppState> {
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.server.max_response_bytes = Some(150);
+        config.server.oversized_response_body = policy;
+        test_state(config)
+    }
+
+    #[tokio::test]
+    async fn test_response_over_declared_size_rejected() {
+        let upstream = spawn_upstream(Router::new().fallback(|| async { vec![b'x'; 1000] })).await;
+        let state = size_limited_state(upstream, OversizedBodyPolicy::Reject);
+
+        let err = proxy_handler(State(state), series_request())
+            .await
+            .unwrap_err();
+        assert_eq!(err.into_response().status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_response_over_size_aborted() {
+        let interval = std::time::Duration::from_millis(10);
+        let upstream = spawn_trickling_upstream(interval, &[b'x'; 100], interval, 3).await;
+        let state = size_limited_state(upstream, OversizedBodyPolicy::Reject);
+
+        // The first chunk fits, so the response starts before the limit is hit
+        let res = proxy_handler(State(state), series_request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(
+            axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_response_over_size_truncated() {
+        let interval = std::time::Duration::from_millis(10);
+        let upstream = spawn_trickling_upstream(interval, &[b'x'; 100], interval, 3).await;
+        let state = size_limited_state(upstream, OversizedBodyPolicy::Truncate);
+
+        let res = proxy_handler(State(state), series_request()).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.len(), 150);
+
+        let upstream = spawn_upstream(Router::new().fallback(|| async { vec![b'x'; 1000] })).await;
+        let state = size_limited_state(upstream, OversizedBodyPolicy::Truncate);
+        let res = proxy_handler(State(state), series_request()).await.unwrap();
+        assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.len(), 150);
+    }
+
+    #[test]
+    fn test_collapse_slashes() {
+        assert_eq!(collapse_slashes("//api/v3///series/"), "/api/v3/series/");
+        assert_eq!(
+            collapse_slashes("/api//series?path=//media//tv"),
+            "/api/series?path=//media//tv"
+        );
+        assert_eq!(collapse_slashes("/"), "/");
+    }
+
+    #[test]
+    fn test_strip_repeated_app_prefix() {
+        assert_eq!(strip_repeated_app_prefix("/sonarr/api", "sonarr"), "/api");
+        assert_eq!(
+            strip_repeated_app_prefix("/sonarr/Sonarr/api?x=1", "sonarr"),
+            "/api?x=1"
+        );
+        assert_eq!(strip_repeated_app_prefix("/sonarr?x=1", "sonarr"), "?x=1");
+        assert_eq!(strip_repeated_app_prefix("/sonarr", "sonarr"), "");
+        assert_eq!(
+            strip_repeated_app_prefix("/sonarrx/api", "sonarr"),
+            "/sonarrx/api"
+        );
+        assert_eq!(
+            strip_repeated_app_prefix("/api/sonarr", "sonarr"),
+            "/api/sonarr"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collapse_duplicate_app_prefix() {
+        let upstream = spawn_upstream(
+            Router::new().fallback(|uri: axum::http::Uri| async move { uri.to_string() }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+
+        for (enabled, path, expected) in [
+            (
+                false,
+                "/sonarr/sonarr/api/v3/series",
+                "/sonarr/api/v3/series",
+            ),
+            (true, "/sonarr/sonarr/api/v3/series", "/api/v3/series"),
+            (true, "/sonarr/SONARR/sonarr/api?page=1", "/api?page=1"),
+            (true, "/sonarr/sonarr", "/"),
+            (true, "/sonarr/api/sonarr", "/api/sonarr"),
+            (true, "/sonarr/sonarrx/api", "/sonarrx/api"),
+        ] {
+            config.server.collapse_duplicate_app_prefix = enabled;
+            let state = test_state(config.clone());
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            let res = proxy_handler(State(state), req).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes(), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_normalize_slashes() {
+        let upstream = spawn_upstream(
+            Router::new().fallback(|uri: axum::http::Uri| async move { uri.to_string() }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+
+        for (enabled, expected) in [
+            (false, "//api/v3//series?q=a//b"),
+            (true, "/api/v3/series?q=a//b"),
+        ] {
+            config.server.normalize_slashes = enabled;
+            let state = test_state(config.clone());
+            let req = Request::builder()
+                .uri("/sonarr//api/v3//series?q=a//b")
+                .body(Body::empty())
+                .unwrap();
+            let res = proxy_handler(State(state), req).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route_by_host() {
+        use tower::ServiceExt;
+
+        let upstream = spawn_upstream(
+            Router::new().fallback(|uri: axum::http::Uri| async move { uri.to_string() }),
+        )
+        .await;
+        let mut config = test_config();
+        config.arr_apps[0].url = upstream;
+        config.arr_apps[0].host = Some("sonarr.example.com".to_string());
+        let app = crate::build_router(test_state(config));
+        let token = crate::test_support::access_token(true);
+
+        for (host, path, expected) in [
+            ("sonarr.example.com", "/api/v3/series", "/api/v3/series"),
+            ("Sonarr.Example.com:8443", "/", "/"),
+            // Path-based routing still works on other hosts
+            (
+                "bouncarr.example.com",
+                "/sonarr/api/v3/series",
+                "/api/v3/series",
+            ),
+        ] {
+            let req = Request::builder()
+                .uri(path)
+                .header(header::HOST, host)
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{} {}", host, path);
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes());
+        }
+
+        // Other hosts still get the login redirect at the root
+        let req = Request::builder()
+            .uri("/")
+            .header(header::HOST, "bouncarr.example.com")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+    }
+
+    #[test]
+    fn test_route_precedence() {
+        let mut config = test_config();
+        config.arr_apps[0].host = Some("sonarr.example.com".to_string());
+        let mut radarr = config.arr_apps[0].clone();
+        radarr.name = "radarr".to_string();
+        radarr.host = None;
+        config.arr_apps.push(radarr);
+
+        let req = Request::builder()
+            .uri("/radarr/api/v3/movie")
+            .header(header::HOST, "sonarr.example.com")
+            .body(Body::empty())
+            .unwrap();
+
+        let route = resolve_route(&config, &req).unwrap();
+        assert_eq!(route.app.name, "sonarr");
+        assert_eq!(route.rule, RouteRule::Host);
+
+        config.server.route_precedence = RoutePrecedence::PathFirst;
+        let route = resolve_route(&config, &req).unwrap();
+        assert_eq!(route.app.name, "radarr");
+        assert_eq!(route.rule, RouteRule::Path);
+
+        // Paths naming no app still fall back to the host
+        let req = Request::builder()
+            .uri("/api/v3/series")
+            .header(header::HOST, "sonarr.example.com")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(resolve_route(&config, &req).unwrap().app.name, "sonarr");
+    }
 }
@@ -0,0 +1,277 @@
+use crate::error::{AppError, Result};
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+use futures_util::StreamExt;
+use http_body_util::BodyExt;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// Header clients use to mark retries of the same mutation
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Header added to responses replayed from the cache
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Largest response body kept for replay. Larger responses are streamed
+/// through and not cached.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Keys kept at once. When full, the oldest entry makes room.
+const MAX_ENTRIES: usize = 1024;
+
+/// Upstream response stored for replay
+#[derive(Clone)]
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+struct Entry {
+    created: Instant,
+    ttl: Duration,
+    response: Arc<OnceCell<CachedResponse>>,
+}
+
+/// Why a forwarded response was not cached
+enum Uncached {
+    Failed(AppError),
+    /// Body over `MAX_BODY_BYTES`, passed through as a stream
+    TooLarge(Response),
+}
+
+/// Short-lived cache of responses keyed by `Idempotency-Key`
+///
+/// The first request for a key is forwarded upstream; duplicates arriving
+/// while it is in flight wait for it, and later duplicates within the TTL get
+/// the stored response without contacting the upstream again. Failed forwards
+/// and responses over `MAX_BODY_BYTES` are not cached, so a retry after them
+/// is forwarded normally. At most `MAX_ENTRIES` keys are kept.
+#[derive(Default)]
+pub struct IdempotencyCache {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl IdempotencyCache {
+    /// Return the cached response for `key`, or run `forward` and cache its
+    /// result for `ttl`
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `forward`, or error if its body cannot be read
+    pub async fn get_or_forward<F, Fut>(
+        &self,
+        key: String,
+        ttl: Duration,
+        forward: F,
+    ) -> Result<Response>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Response>>,
+    {
+        let cell = {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            entries.retain(|_, entry| now.duration_since(entry.created) < entry.ttl);
+            if entries.len() >= MAX_ENTRIES
+                && !entries.contains_key(&key)
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.created)
+                    .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+            entries
+                .entry(key.clone())
+                .or_insert_with(|| Entry {
+                    created: now,
+                    ttl,
+                    response: Arc::new(OnceCell::new()),
+                })
+                .response
+                .clone()
+        };
+
+        let mut replayed = true;
+        let result = cell
+            .get_or_try_init(|| async {
+                replayed = false;
+                let response = forward().await.map_err(Uncached::Failed)?;
+                CachedResponse::from_response(response).await
+            })
+            .await;
+
+        let cached = match result {
+            Ok(cached) => cached,
+            Err(Uncached::Failed(e)) => return Err(e),
+            Err(Uncached::TooLarge(response)) => {
+                tracing::debug!("Response too large to cache for idempotency key");
+                self.entries
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&key);
+                return Ok(response);
+            }
+        };
+        if replayed {
+            tracing::debug!("Replaying cached response for idempotency key");
+        }
+
+        cached.to_response(replayed)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+impl CachedResponse {
+    /// Read the whole body, unless it turns out to be over `MAX_BODY_BYTES`
+    async fn from_response(response: Response) -> std::result::Result<Self, Uncached> {
+        let declared = response
+            .headers()
+            .get(axum::http::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+        if declared.is_some_and(|len| len > MAX_BODY_BYTES) {
+            return Err(Uncached::TooLarge(response));
+        }
+
+        let (parts, mut body) = response.into_parts();
+        let mut chunks: Vec<Bytes> = Vec::new();
+        let mut len = 0;
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(|e| {
+                Uncached::Failed(AppError::ProxyError(format!(
+                    "Failed to read response body: {}",
+                    e
+                )))
+            })?;
+            let Ok(chunk) = frame.into_data() else {
+                continue;
+            };
+            len += chunk.len();
+            chunks.push(chunk);
+
+            if len > MAX_BODY_BYTES {
+                // Send what was read, then the rest as it arrives
+                let rest = body.into_data_stream();
+                let stream = futures_util::stream::iter(chunks.into_iter().map(Ok)).chain(rest);
+                return Err(Uncached::TooLarge(Response::from_parts(
+                    parts,
+                    Body::from_stream(stream),
+                )));
+            }
+        }
+
+        Ok(Self {
+            status: parts.status,
+            headers: parts.headers,
+            body: chunks.concat().into(),
+        })
+    }
+
+    fn to_response(&self, replayed: bool) -> Result<Response> {
+        let mut response = Response::builder()
+            .status(self.status)
+            .body(Body::from(self.body.clone()))
+            .map_err(|e| AppError::ProxyError(format!("Failed to build response: {}", e)))?;
+        *response.headers_mut() = self.headers.clone();
+        if replayed {
+            response
+                .headers_mut()
+                .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn forward_body(
+        cache: &IdempotencyCache,
+        key: &str,
+        calls: &AtomicUsize,
+        body: Body,
+    ) -> (bool, Bytes) {
+        let response = cache
+            .get_or_forward(key.to_string(), Duration::from_secs(60), || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(Response::new(body))
+            })
+            .await
+            .unwrap();
+        let replayed = response.headers().contains_key(REPLAYED_HEADER);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (replayed, body)
+    }
+
+    #[tokio::test]
+    async fn test_small_response_replayed() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicUsize::new(0);
+        for replay in [false, true] {
+            let (replayed, body) = forward_body(&cache, "a", &calls, Body::from("ok")).await;
+            assert_eq!(replayed, replay);
+            assert_eq!(&body[..], b"ok");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_large_response_streamed_not_cached() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicUsize::new(0);
+        let large = vec![7u8; MAX_BODY_BYTES + 1];
+
+        for _ in 0..2 {
+            // A stream of unknown length is only found to be too large while
+            // reading it
+            let chunks = large
+                .chunks(64 * 1024)
+                .map(|chunk| Ok::<_, std::io::Error>(Bytes::copy_from_slice(chunk)))
+                .collect::<Vec<_>>();
+            let body = Body::from_stream(futures_util::stream::iter(chunks));
+            let (replayed, body) = forward_body(&cache, "big", &calls, body).await;
+            assert!(!replayed);
+            assert_eq!(body.len(), large.len());
+            assert!(body.iter().all(|&b| b == 7));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_entries_bounded() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicUsize::new(0);
+        for i in 0..MAX_ENTRIES + 10 {
+            forward_body(&cache, &i.to_string(), &calls, Body::from("ok")).await;
+        }
+        assert_eq!(cache.len(), MAX_ENTRIES);
+
+        // The newest keys are kept, the oldest made room
+        let (replayed, _) = forward_body(
+            &cache,
+            &(MAX_ENTRIES + 9).to_string(),
+            &calls,
+            Body::from("ok"),
+        )
+        .await;
+        assert!(replayed);
+        let (replayed, _) = forward_body(&cache, "0", &calls, Body::from("ok")).await;
+        assert!(!replayed);
+    }
+}
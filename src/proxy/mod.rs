@@ -1,4 +1,16 @@
+pub mod app_clients;
+pub mod body;
+pub mod connection_limit;
+pub mod cors;
+pub mod csp;
+pub mod forwarded;
 pub mod handler;
+pub mod idempotency;
+pub mod probe;
+pub mod rate_limit;
+pub mod set_cookie;
+pub mod user_agent;
+pub mod warm_up;
 pub mod websocket;
 
 pub use handler::proxy_handler;
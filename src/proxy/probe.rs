@@ -0,0 +1,52 @@
+use crate::config::{ArrApp, UpstreamProbe};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Recent liveness probe results, per app
+#[derive(Default)]
+pub struct UpstreamProbes {
+    results: Mutex<HashMap<String, (Instant, bool)>>,
+}
+
+impl UpstreamProbes {
+    /// Whether the app answered its probe recently
+    ///
+    /// Results are reused for `cache_seconds`; concurrent requests with an
+    /// expired result may each probe.
+    pub async fn is_alive(
+        &self,
+        client: &reqwest::Client,
+        app: &ArrApp,
+        probe: &UpstreamProbe,
+    ) -> bool {
+        let max_age = Duration::from_secs(probe.cache_seconds);
+        if let Some((checked, alive)) = self.lock().get(&app.name).copied()
+            && checked.elapsed() < max_age
+        {
+            return alive;
+        }
+
+        let url = format!("{}{}", app.base_url().trim_end_matches('/'), probe.path);
+        let alive = match client
+            .get(&url)
+            .timeout(Duration::from_secs(probe.timeout_seconds))
+            .send()
+            .await
+        {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::warn!("Upstream '{}' failed its probe: {}", app.name, e);
+                false
+            }
+        };
+
+        self.lock()
+            .insert(app.name.clone(), (Instant::now(), alive));
+        alive
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Instant, bool)>> {
+        self.results.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,165 @@
+use crate::config::RateLimit;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets kept before full (idle) ones are dropped
+///
+/// After a pass the threshold becomes twice the buckets left, so pruning
+/// stays amortized when most buckets are busy.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Bucket identity: app name and, for per-client limits, the client IP
+type BucketKey = (String, Option<IpAddr>);
+
+/// Token buckets for apps with a `rate_limit`
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<BucketKey, Bucket>,
+    /// Size at which the next prune pass runs
+    prune_at: usize,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Limit the bucket was last used with, so it can be pruned on its own
+    requests_per_second: f64,
+    burst: f64,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        self.requests_per_second = limit.requests_per_second;
+        self.burst = limit.burst() as f64;
+        self.tokens = self.tokens_at(now);
+        self.updated = now;
+    }
+
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * self.requests_per_second).min(self.burst)
+    }
+}
+
+impl RateLimiter {
+    /// Take a token from the app's bucket (or the client's, with `per_client`)
+    ///
+    /// Returns how long until a token is available when the bucket is empty.
+    pub fn check(&self, app: &str, client: Option<IpAddr>, limit: &RateLimit) -> Option<Duration> {
+        let key = (app.to_string(), client.filter(|_| limit.per_client));
+        let now = Instant::now();
+        let mut state = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let Buckets { buckets, prune_at } = &mut *state;
+
+        if buckets.len() > (*prune_at).max(PRUNE_THRESHOLD) {
+            // A full bucket is the same as a new one
+            buckets.retain(|_, bucket| bucket.tokens_at(now) < bucket.burst);
+            *prune_at = buckets.len() * 2;
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: limit.burst() as f64,
+            updated: now,
+            requests_per_second: limit.requests_per_second,
+            burst: limit.burst() as f64,
+        });
+        bucket.refill(limit, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return None;
+        }
+        Some(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / limit.requests_per_second,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(per_client: bool) -> RateLimit {
+        RateLimit {
+            requests_per_second: 1.0,
+            burst: Some(2),
+            per_client,
+        }
+    }
+
+    #[test]
+    fn test_burst_then_limited() {
+        let limiter = RateLimiter::default();
+        let limit = limit(false);
+
+        assert!(limiter.check("sonarr", None, &limit).is_none());
+        assert!(limiter.check("sonarr", None, &limit).is_none());
+        let wait = limiter.check("sonarr", None, &limit).unwrap();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_secs(1));
+
+        // Apps have separate buckets
+        assert!(limiter.check("radarr", None, &limit).is_none());
+    }
+
+    #[test]
+    fn test_per_client_buckets() {
+        let limiter = RateLimiter::default();
+        let (a, b) = (
+            Some(IpAddr::from([10, 0, 0, 1])),
+            Some(IpAddr::from([10, 0, 0, 2])),
+        );
+
+        let global = limit(false);
+        assert!(limiter.check("sonarr", a, &global).is_none());
+        assert!(limiter.check("sonarr", b, &global).is_none());
+        assert!(limiter.check("sonarr", a, &global).is_some());
+
+        let per_client = limit(true);
+        assert!(limiter.check("radarr", a, &per_client).is_none());
+        assert!(limiter.check("radarr", a, &per_client).is_none());
+        assert!(limiter.check("radarr", a, &per_client).is_some());
+        assert!(limiter.check("radarr", b, &per_client).is_none());
+    }
+
+    #[test]
+    fn test_tokens_refill() {
+        let limiter = RateLimiter::default();
+        let limit = RateLimit {
+            requests_per_second: 50.0,
+            burst: Some(1),
+            per_client: false,
+        };
+        assert!(limiter.check("sonarr", None, &limit).is_none());
+        assert!(limiter.check("sonarr", None, &limit).is_some());
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(limiter.check("sonarr", None, &limit).is_none());
+    }
+
+    #[test]
+    fn test_idle_buckets_pruned_across_apps() {
+        let limiter = RateLimiter::default();
+        let fast = RateLimit {
+            requests_per_second: 1000.0,
+            burst: Some(1),
+            per_client: true,
+        };
+        for i in 0..=PRUNE_THRESHOLD as u32 {
+            let client = Some(IpAddr::from(i.to_be_bytes()));
+            assert!(limiter.check("sonarr", client, &fast).is_none());
+        }
+        std::thread::sleep(Duration::from_millis(5));
+
+        // Another app's request drops the refilled sonarr buckets
+        let slow = limit(true);
+        assert!(limiter.check("radarr", None, &slow).is_none());
+        let state = limiter.buckets.lock().unwrap();
+        assert_eq!(state.buckets.len(), 1);
+        assert_eq!(state.prune_at, 0);
+    }
+}
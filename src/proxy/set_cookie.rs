@@ -0,0 +1,123 @@
+use crate::config::{CookieRewrite, SameSiteMode};
+use axum::http::{HeaderMap, HeaderValue, header};
+
+/// Apply an app's cookie attribute rewrite to the upstream `Set-Cookie`
+/// response headers
+///
+/// Only `SameSite` and `Secure` are touched; the cookie itself and its other
+/// attributes are kept as sent.
+pub fn apply(rewrite: &CookieRewrite, headers: &mut HeaderMap) {
+    let cookies: Vec<HeaderValue> = headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .cloned()
+        .collect();
+    if cookies.is_empty() {
+        return;
+    }
+
+    headers.remove(header::SET_COOKIE);
+    for cookie in cookies {
+        let rewritten = cookie
+            .to_str()
+            .ok()
+            .and_then(|cookie| HeaderValue::from_str(&rewrite_cookie(cookie, rewrite)).ok());
+        match rewritten {
+            Some(value) => headers.append(header::SET_COOKIE, value),
+            None => {
+                tracing::warn!("Passing through Set-Cookie header that could not be rewritten");
+                headers.append(header::SET_COOKIE, cookie)
+            }
+        };
+    }
+}
+
+/// Replace the `SameSite` and `Secure` attributes of a `Set-Cookie` value
+fn rewrite_cookie(cookie: &str, rewrite: &CookieRewrite) -> String {
+    let mut parts = cookie.split(';').map(str::trim);
+    let mut attributes: Vec<String> = parts.next().into_iter().map(String::from).collect();
+
+    for attribute in parts.filter(|a| !a.is_empty()) {
+        let name = attribute.split('=').next().unwrap_or_default().trim();
+        let replaced = (rewrite.same_site.is_some() && name.eq_ignore_ascii_case("samesite"))
+            || (rewrite.secure.is_some() && name.eq_ignore_ascii_case("secure"));
+        if !replaced {
+            attributes.push(attribute.to_string());
+        }
+    }
+
+    if let Some(same_site) = rewrite.same_site {
+        attributes.push(
+            match same_site {
+                SameSiteMode::Strict => "SameSite=Strict",
+                SameSiteMode::Lax => "SameSite=Lax",
+                SameSiteMode::None => "SameSite=None",
+            }
+            .to_string(),
+        );
+    }
+    if rewrite.secure == Some(true) {
+        attributes.push("Secure".to_string());
+    }
+
+    attributes.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPSTREAM: &str = "SonarrAuth=abc123; Path=/; Expires=Wed, 21 Oct 2026 07:28:00 GMT; Secure; HttpOnly; SameSite=Strict";
+
+    fn headers(cookies: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for cookie in cookies {
+            headers.append(header::SET_COOKIE, cookie.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_rewrite_same_site_and_drop_secure() {
+        let rewrite = CookieRewrite {
+            same_site: Some(SameSiteMode::Lax),
+            secure: Some(false),
+        };
+        let mut headers = headers(&[UPSTREAM, "theme=dark"]);
+        apply(&rewrite, &mut headers);
+
+        let cookies: Vec<_> = headers
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            cookies,
+            [
+                "SonarrAuth=abc123; Path=/; Expires=Wed, 21 Oct 2026 07:28:00 GMT; HttpOnly; SameSite=Lax",
+                "theme=dark; SameSite=Lax",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_secure_keeps_same_site() {
+        let rewrite = CookieRewrite {
+            same_site: None,
+            secure: Some(true),
+        };
+        let mut headers = headers(&["session=xyz; path=/; secure; samesite=none"]);
+        apply(&rewrite, &mut headers);
+        assert_eq!(
+            headers[header::SET_COOKIE],
+            "session=xyz; path=/; samesite=none; Secure"
+        );
+    }
+
+    #[test]
+    fn test_default_passes_through() {
+        let mut headers = headers(&[UPSTREAM]);
+        apply(&CookieRewrite::default(), &mut headers);
+        assert_eq!(headers[header::SET_COOKIE], UPSTREAM);
+    }
+}
@@ -0,0 +1,95 @@
+use regex::{Regex, RegexBuilder};
+
+/// Blocklist of client user agents
+///
+/// Each pattern is either a case-insensitive substring, or a case-insensitive
+/// regular expression when wrapped in slashes (e.g. `/^curl\//`).
+pub struct UserAgentFilter {
+    substrings: Vec<String>,
+    regexes: Vec<Regex>,
+}
+
+impl UserAgentFilter {
+    /// Compile the configured patterns
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a `/regex/` pattern is invalid
+    pub fn new(patterns: &[String]) -> Result<Self, String> {
+        let mut substrings = Vec::new();
+        let mut regexes = Vec::new();
+
+        for pattern in patterns {
+            if let Some(expr) = pattern
+                .strip_prefix('/')
+                .and_then(|p| p.strip_suffix('/'))
+                .filter(|p| !p.is_empty())
+            {
+                let regex = RegexBuilder::new(expr)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| format!("Invalid user agent pattern '{}': {}", pattern, e))?;
+                regexes.push(regex);
+            } else if !pattern.is_empty() {
+                substrings.push(pattern.to_lowercase());
+            }
+        }
+
+        Ok(Self {
+            substrings,
+            regexes,
+        })
+    }
+
+    /// Whether a request with this user agent should be rejected
+    ///
+    /// Requests without a user agent are never blocked.
+    pub fn is_blocked(&self, user_agent: Option<&str>) -> bool {
+        let Some(user_agent) = user_agent else {
+            return false;
+        };
+        let lowered = user_agent.to_lowercase();
+
+        self.substrings.iter().any(|s| lowered.contains(s))
+            || self.regexes.iter().any(|r| r.is_match(user_agent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(patterns: &[&str]) -> UserAgentFilter {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        UserAgentFilter::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn test_substring_match_is_case_insensitive() {
+        let filter = filter(&["masscan", "ZGrab"]);
+        assert!(filter.is_blocked(Some(
+            "Masscan/1.3 (https://github.com/robertdavidgraham/masscan)"
+        )));
+        assert!(filter.is_blocked(Some("Mozilla/5.0 zgrab/0.x")));
+        assert!(!filter.is_blocked(Some("Mozilla/5.0 (X11; Linux x86_64) Firefox/130.0")));
+    }
+
+    #[test]
+    fn test_regex_pattern() {
+        let filter = filter(&[r"/^curl\//"]);
+        assert!(filter.is_blocked(Some("curl/8.5.0")));
+        assert!(filter.is_blocked(Some("CURL/8.5.0")));
+        assert!(!filter.is_blocked(Some("Mozilla/5.0 curl/8.5.0")));
+    }
+
+    #[test]
+    fn test_missing_user_agent_allowed() {
+        let filter = filter(&["bot"]);
+        assert!(!filter.is_blocked(None));
+    }
+
+    #[test]
+    fn test_invalid_regex_rejected() {
+        assert!(UserAgentFilter::new(&["/(unclosed/".to_string()]).is_err());
+    }
+}
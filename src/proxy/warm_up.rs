@@ -0,0 +1,81 @@
+use crate::AppState;
+use futures_util::future::join_all;
+use std::time::Duration;
+
+/// Longest a single warm-up request may take
+const WARM_UP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Open a pooled connection to every app by sending it a `HEAD` request
+///
+/// Any response counts as success. Apps that can't be reached are logged as
+/// warnings. Returns the names of the apps that were reached.
+pub async fn warm_up(state: &AppState) -> Vec<String> {
+    let requests = state.config.arr_apps.iter().map(|app| async move {
+        let result = state
+            .app_clients
+            .client_for(app, &state.http_client)
+            .head(app.base_url())
+            .timeout(WARM_UP_TIMEOUT)
+            .send()
+            .await;
+        match result {
+            Ok(_) => {
+                tracing::debug!("Warmed up connection to '{}'", app.name);
+                Some(app.name.clone())
+            }
+            Err(e) => {
+                tracing::warn!("Warm-up of '{}' failed: {}", app.name, e);
+                None
+            }
+        }
+    });
+
+    join_all(requests).await.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{capture_logs, spawn_upstream, test_config, test_state};
+    use axum::{Router, routing::get};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_warm_up_connects_to_every_app() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let mut config = test_config();
+        let template = config.arr_apps[0].clone();
+        config.arr_apps.clear();
+        for name in ["sonarr", "radarr"] {
+            let counter = hits.clone();
+            let upstream = spawn_upstream(Router::new().route(
+                "/",
+                get(move || async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }),
+            ))
+            .await;
+            config.arr_apps.push(crate::config::ArrApp {
+                name: name.to_string(),
+                url: upstream,
+                ..template.clone()
+            });
+        }
+        // Nothing listens on the discard port
+        config.arr_apps.push(crate::config::ArrApp {
+            name: "lidarr".to_string(),
+            url: "http://127.0.0.1:9".to_string(),
+            ..template
+        });
+        let state = test_state(config);
+
+        let (_guard, logs) = capture_logs();
+        let mut reached = warm_up(&state).await;
+        reached.sort();
+
+        assert_eq!(reached, ["radarr", "sonarr"]);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(logs.contents().contains("Warm-up of 'lidarr' failed"));
+    }
+}
@@ -1,16 +1,109 @@
 use crate::error::{AppError, Result};
+use crate::metrics::Metrics;
+use crate::proxy::connection_limit::ConnectionGuard;
 use axum::{
     body::Body,
     extract::{FromRequestParts, WebSocketUpgrade, ws::WebSocket},
-    http::Request,
+    http::{HeaderValue, Request, header},
     response::Response,
 };
 use futures_util::{SinkExt, StreamExt};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message as TungsteniteMessage};
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::Instant;
+use tokio_tungstenite::{
+    WebSocketStream, connect_async,
+    tungstenite::{
+        Error as TungsteniteError,
+        client::IntoClientRequest,
+        protocol::{CloseFrame as TungsteniteCloseFrame, Message as TungsteniteMessage},
+    },
+};
+#[cfg(unix)]
+use tokio_tungstenite::{
+    client_async, tungstenite::handshake::client::Request as HandshakeRequest,
+};
+
+/// Settings for a single proxied WebSocket connection
+#[derive(Clone)]
+pub struct WebSocketOptions {
+    /// Name of the *arr app the connection belongs to
+    pub app_name: String,
+    /// Registry to export frame counters to. `None` disables frame tracking.
+    pub frame_metrics: Option<Arc<Metrics>>,
+    /// Per-IP connection slot, held for as long as the connection is open
+    pub connection_guard: Option<Arc<ConnectionGuard>>,
+    /// Close the connection after this long without a frame in either
+    /// direction. `None` keeps idle connections open.
+    pub idle_timeout: Option<Duration>,
+    /// Close the connection this long after it was opened, whatever the
+    /// traffic. `None` keeps it open indefinitely.
+    pub max_lifetime: Option<Duration>,
+    /// Connect to the upstream over this Unix socket instead of TCP
+    pub unix_socket: Option<PathBuf>,
+    /// `Origin` header sent in the upstream handshake. `None` sends none.
+    pub origin: Option<HeaderValue>,
+}
+
+/// Frame and byte counts for one direction of a proxied WebSocket
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameCounters {
+    pub text: u64,
+    pub binary: u64,
+    pub ping: u64,
+    pub pong: u64,
+    pub close: u64,
+    pub bytes: u64,
+}
+
+impl FrameCounters {
+    fn record(&mut self, kind: &'static str, len: usize) {
+        let counters = self;
+        match kind {
+            "text" => counters.text += 1,
+            "binary" => counters.binary += 1,
+            "ping" => counters.ping += 1,
+            "pong" => counters.pong += 1,
+            _ => counters.close += 1,
+        }
+        counters.bytes += len as u64;
+    }
+
+    /// Frames of every type
+    fn frames(&self) -> u64 {
+        self.text + self.binary + self.ping + self.pong + self.close
+    }
+
+    fn export(&self, metrics: &Metrics, app_name: &str, direction: &str) {
+        for (kind, count) in [
+            ("text", self.text),
+            ("binary", self.binary),
+            ("ping", self.ping),
+            ("pong", self.pong),
+            ("close", self.close),
+        ] {
+            metrics.add(
+                "bouncarr_websocket_frames_total",
+                &[("app", app_name), ("direction", direction), ("type", kind)],
+                count,
+            );
+        }
+        metrics.add(
+            "bouncarr_websocket_bytes_total",
+            &[("app", app_name), ("direction", direction)],
+            self.bytes,
+        );
+    }
+}
 
 pub async fn proxy_websocket_connection(
     req: Request<Body>,
     target_url: String,
+    options: WebSocketOptions,
 ) -> Result<Response> {
     // Extract WebSocketUpgrade from the request
     let (mut parts, _body) = req.into_parts();
@@ -25,110 +118,709 @@ pub async fn proxy_websocket_connection(
         }
     };
 
-    Ok(ws.on_upgrade(move |socket| handle_websocket_proxy(socket, target_url)))
+    Ok(ws.on_upgrade(move |socket| handle_websocket_proxy(socket, target_url, options)))
 }
 
-pub async fn handle_websocket_proxy(mut client_socket: WebSocket, target_url: String) {
-    use axum::extract::ws::Message;
-
+pub async fn handle_websocket_proxy(
+    client_socket: WebSocket,
+    target_url: String,
+    options: WebSocketOptions,
+) {
     tracing::debug!("Connecting to upstream WebSocket");
 
     // Connect to the upstream WebSocket server (URL should already be ws://)
-    let upstream_result = connect_async(&target_url).await;
+    let mut request = match target_url.as_str().into_client_request() {
+        Ok(request) => request,
+        Err(e) => return close_with_error(client_socket, e).await,
+    };
+    if let Some(origin) = &options.origin {
+        request.headers_mut().insert(header::ORIGIN, origin.clone());
+    }
 
-    let (upstream_ws, _response) = match upstream_result {
-        Ok(conn) => {
-            tracing::debug!("WebSocket connection established");
-            conn
+    #[cfg(unix)]
+    if let Some(socket) = options.unix_socket.clone() {
+        match connect_unix(&socket, request).await {
+            Ok(upstream_ws) => forward_frames(client_socket, upstream_ws, options).await,
+            Err(e) => close_with_error(client_socket, e).await,
         }
-        Err(e) => {
-            tracing::error!("Failed to connect to upstream WebSocket: {}", e);
-            // Send close frame with error to client
-            let error_message = format!("Failed to connect to upstream: {}", e);
-            let _ = client_socket
-                .send(Message::Close(Some(axum::extract::ws::CloseFrame {
-                    code: 1011, // Internal server error
-                    reason: error_message.into(),
-                })))
-                .await;
-            return;
+        return;
+    }
+
+    match connect_async(request).await {
+        Ok((upstream_ws, _response)) => {
+            #[cfg(debug_assertions)]
+            tracing::debug!("Upstream WebSocket response: {:?}", _response);
+            forward_frames(client_socket, upstream_ws, options).await
         }
-    };
+        Err(e) => close_with_error(client_socket, e).await,
+    }
+}
+
+/// Open the upstream WebSocket over an app's Unix socket
+#[cfg(unix)]
+async fn connect_unix(
+    socket: &Path,
+    request: HandshakeRequest,
+) -> std::result::Result<WebSocketStream<tokio::net::UnixStream>, TungsteniteError> {
+    let stream = tokio::net::UnixStream::connect(socket).await?;
+    let (upstream_ws, _response) = client_async(request, stream).await?;
+    Ok(upstream_ws)
+}
 
-    #[cfg(debug_assertions)]
-    tracing::debug!("Upstream WebSocket response: {:?}", _response);
+/// Tell the client the upstream connection could not be made
+async fn close_with_error(mut client_socket: WebSocket, e: TungsteniteError) {
+    tracing::error!("Failed to connect to upstream WebSocket: {}", e);
+    // Send close frame with error to client
+    let error_message = format!("Failed to connect to upstream: {}", e);
+    let _ = client_socket
+        .send(axum::extract::ws::Message::Close(Some(
+            axum::extract::ws::CloseFrame {
+                code: 1011, // Internal server error
+                reason: error_message.into(),
+            },
+        )))
+        .await;
+}
+
+/// One end of a proxied WebSocket
+#[derive(Clone, Copy)]
+enum Side {
+    Client,
+    Upstream,
+}
+
+impl std::fmt::Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Side::Client => "client",
+            Side::Upstream => "upstream",
+        })
+    }
+}
+
+/// Why a proxied WebSocket was torn down
+enum Ending {
+    /// A side sent a close frame (code and reason, if any) or its stream ended
+    Closed(Side, Option<(u16, String)>),
+    /// Reading from or writing to a side failed
+    Failed(Side, String),
+    IdleTimeout,
+    MaxLifetime,
+    InvalidClientText,
+    InvalidUpstreamText,
+}
+
+/// Whether a read failed on a text frame that isn't valid UTF-8
+fn is_invalid_text(e: Option<&TungsteniteError>) -> bool {
+    matches!(e, Some(TungsteniteError::Utf8))
+}
+
+/// Relay frames between the client and upstream until either side closes
+async fn forward_frames<S>(
+    client_socket: WebSocket,
+    upstream_ws: WebSocketStream<S>,
+    options: WebSocketOptions,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    use axum::extract::ws::Message;
+
+    tracing::debug!("WebSocket connection established");
+    let opened = Instant::now();
+
+    // Released when the connection ends
+    let _connection_guard = options.connection_guard;
 
     // Split both WebSocket connections
     let (mut client_sink, mut client_stream) = client_socket.split();
     let (mut upstream_sink, mut upstream_stream) = upstream_ws.split();
 
+    let mut client_counters = FrameCounters::default();
+    let mut upstream_counters = FrameCounters::default();
+    let client_counts = &mut client_counters;
+    let upstream_counts = &mut upstream_counters;
+
+    // Sinks are borrowed so both sides can be closed after an idle timeout
+    let client_tx = &mut client_sink;
+    let upstream_tx = &mut upstream_sink;
+    let last_activity = Mutex::new(Instant::now());
+    let touch = || *last_activity.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+
     // Create two tasks to forward messages in both directions
     let client_to_upstream = async move {
         while let Some(msg) = client_stream.next().await {
+            touch();
             let result = match msg {
-                Ok(Message::Text(text)) => upstream_sink.send(TungsteniteMessage::Text(text)).await,
+                Ok(Message::Text(text)) => {
+                    client_counts.record("text", text.len());
+                    upstream_tx.send(TungsteniteMessage::Text(text)).await
+                }
                 Ok(Message::Binary(data)) => {
-                    upstream_sink
+                    client_counts.record("binary", data.len());
+                    upstream_tx
                         .send(TungsteniteMessage::Binary(data.to_vec()))
                         .await
                 }
                 Ok(Message::Ping(data)) => {
-                    upstream_sink
+                    client_counts.record("ping", data.len());
+                    upstream_tx
                         .send(TungsteniteMessage::Ping(data.to_vec()))
                         .await
                 }
                 Ok(Message::Pong(data)) => {
-                    upstream_sink
+                    client_counts.record("pong", data.len());
+                    upstream_tx
                         .send(TungsteniteMessage::Pong(data.to_vec()))
                         .await
                 }
-                Ok(Message::Close(_)) => {
-                    let _ = upstream_sink.send(TungsteniteMessage::Close(None)).await;
-                    break;
+                Ok(Message::Close(frame)) => {
+                    client_counts.record("close", 0);
+                    let _ = upstream_tx.send(TungsteniteMessage::Close(None)).await;
+                    let frame = frame.map(|frame| (frame.code, frame.reason.into_owned()));
+                    return Ending::Closed(Side::Client, frame);
                 }
-                Err(e) => {
-                    tracing::debug!("Client WebSocket error: {}", e);
-                    break;
+                Err(e)
+                    if is_invalid_text(
+                        std::error::Error::source(&e).and_then(|e| e.downcast_ref()),
+                    ) =>
+                {
+                    return Ending::InvalidClientText;
                 }
+                Err(e) => return Ending::Failed(Side::Client, e.to_string()),
             };
 
-            if result.is_err() {
-                tracing::debug!("Failed to send message to upstream, closing connection");
-                break;
+            if let Err(e) = result {
+                return Ending::Failed(Side::Upstream, format!("send failed: {}", e));
             }
         }
+        Ending::Closed(Side::Client, None)
     };
 
     let upstream_to_client = async move {
         while let Some(msg) = upstream_stream.next().await {
+            touch();
             let result = match msg {
-                Ok(TungsteniteMessage::Text(text)) => client_sink.send(Message::Text(text)).await,
+                Ok(TungsteniteMessage::Text(text)) => {
+                    upstream_counts.record("text", text.len());
+                    client_tx.send(Message::Text(text)).await
+                }
                 Ok(TungsteniteMessage::Binary(data)) => {
-                    client_sink.send(Message::Binary(data)).await
+                    upstream_counts.record("binary", data.len());
+                    client_tx.send(Message::Binary(data)).await
                 }
-                Ok(TungsteniteMessage::Ping(data)) => client_sink.send(Message::Ping(data)).await,
-                Ok(TungsteniteMessage::Pong(data)) => client_sink.send(Message::Pong(data)).await,
-                Ok(TungsteniteMessage::Close(_)) => {
-                    let _ = client_sink.send(Message::Close(None)).await;
-                    break;
+                Ok(TungsteniteMessage::Ping(data)) => {
+                    upstream_counts.record("ping", data.len());
+                    client_tx.send(Message::Ping(data)).await
                 }
-                Err(e) => {
-                    tracing::debug!("Upstream WebSocket error: {}", e);
-                    break;
+                Ok(TungsteniteMessage::Pong(data)) => {
+                    upstream_counts.record("pong", data.len());
+                    client_tx.send(Message::Pong(data)).await
+                }
+                Ok(TungsteniteMessage::Close(frame)) => {
+                    upstream_counts.record("close", 0);
+                    let _ = client_tx.send(Message::Close(None)).await;
+                    let frame =
+                        frame.map(|frame| (u16::from(frame.code), frame.reason.into_owned()));
+                    return Ending::Closed(Side::Upstream, frame);
                 }
+                Err(e) if is_invalid_text(Some(&e)) => return Ending::InvalidUpstreamText,
+                Err(e) => return Ending::Failed(Side::Upstream, e.to_string()),
                 _ => continue,
             };
 
-            if result.is_err() {
-                tracing::debug!("Failed to send message to client, closing connection");
+            if let Err(e) = result {
+                return Ending::Failed(Side::Client, format!("send failed: {}", e));
+            }
+        }
+        Ending::Closed(Side::Upstream, None)
+    };
+
+    let idle_watchdog = async {
+        let Some(timeout) = options.idle_timeout else {
+            return std::future::pending().await;
+        };
+        loop {
+            let deadline = *last_activity.lock().unwrap_or_else(|e| e.into_inner()) + timeout;
+            if Instant::now() >= deadline {
                 break;
             }
+            tokio::time::sleep_until(deadline).await;
+        }
+    };
+
+    let lifetime_limit = async {
+        match options.max_lifetime {
+            Some(lifetime) => tokio::time::sleep(lifetime).await,
+            None => std::future::pending().await,
         }
     };
 
     // Run both forwarding tasks concurrently
-    tokio::select! {
-        _ = client_to_upstream => {},
-        _ = upstream_to_client => {},
+    let ending = tokio::select! {
+        ending = client_to_upstream => ending,
+        ending = upstream_to_client => ending,
+        _ = idle_watchdog => Ending::IdleTimeout,
+        _ = lifetime_limit => Ending::MaxLifetime,
+    };
+
+    // Text frames must be valid UTF-8 (RFC 6455 section 8.1): the connection
+    // to the peer that sent one that isn't is failed with 1007
+    let (client_close, upstream_close) = match &ending {
+        Ending::Closed(..) | Ending::Failed(..) => (None, None),
+        Ending::IdleTimeout => {
+            tracing::debug!(
+                "WebSocket for '{}' idle for {:?}, closing",
+                options.app_name,
+                options.idle_timeout
+            );
+            (Some((1001, "Idle timeout")), Some((1001, "Idle timeout")))
+        }
+        Ending::MaxLifetime => {
+            tracing::debug!(
+                "WebSocket for '{}' reached its maximum lifetime of {:?}, closing",
+                options.app_name,
+                options.max_lifetime
+            );
+            (
+                Some((1000, "Maximum lifetime reached")),
+                Some((1000, "Maximum lifetime reached")),
+            )
+        }
+        Ending::InvalidClientText => {
+            tracing::debug!(
+                "Client sent invalid UTF-8 text frame to '{}', closing",
+                options.app_name
+            );
+            (
+                Some((1007, "Invalid UTF-8 in text frame")),
+                Some((1001, "Client went away")),
+            )
+        }
+        Ending::InvalidUpstreamText => {
+            tracing::warn!(
+                "Upstream '{}' sent invalid UTF-8 text frame, closing",
+                options.app_name
+            );
+            (
+                Some((1011, "Upstream sent invalid data")),
+                Some((1007, "Invalid UTF-8 in text frame")),
+            )
+        }
+    };
+    if let Some((code, reason)) = client_close {
+        let _ = client_sink
+            .send(Message::Close(Some(axum::extract::ws::CloseFrame {
+                code,
+                reason: reason.into(),
+            })))
+            .await;
+    }
+    if let Some((code, reason)) = upstream_close {
+        let _ = upstream_sink
+            .send(TungsteniteMessage::Close(Some(TungsteniteCloseFrame {
+                code: code.into(),
+                reason: reason.into(),
+            })))
+            .await;
+    }
+
+    // The close code and reason are those of the side that ended the
+    // connection, or the ones the proxy sent when it did
+    let (initiator, close) = match ending {
+        Ending::Closed(side, Some((code, reason))) => (
+            side.to_string(),
+            format!("code {}, reason '{}'", code, reason),
+        ),
+        Ending::Closed(side, None) => (side.to_string(), "no close frame".to_string()),
+        Ending::Failed(side, e) => ("error".to_string(), format!("{} error: {}", side, e)),
+        Ending::IdleTimeout | Ending::MaxLifetime => {
+            let (code, reason) = client_close.unwrap_or_default();
+            (
+                "proxy".to_string(),
+                format!("code {}, reason '{}'", code, reason),
+            )
+        }
+        Ending::InvalidClientText | Ending::InvalidUpstreamText => {
+            let (code, reason) = client_close.unwrap_or_default();
+            (
+                "error".to_string(),
+                format!("code {}, reason '{}'", code, reason),
+            )
+        }
+    };
+    tracing::debug!(
+        "WebSocket for '{}' closed by {} ({}) after {:?}: {} frames client->upstream, {} frames upstream->client",
+        options.app_name,
+        initiator,
+        close,
+        opened.elapsed(),
+        client_counters.frames(),
+        upstream_counters.frames()
+    );
+
+    if let Some(metrics) = &options.frame_metrics {
+        tracing::debug!(
+            "WebSocket for '{}' frames: client->upstream {:?}, upstream->client {:?}",
+            options.app_name,
+            client_counters,
+            upstream_counters
+        );
+        client_counters.export(metrics, &options.app_name, "client_to_upstream");
+        upstream_counters.export(metrics, &options.app_name, "upstream_to_client");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::spawn_upstream;
+    use axum::{Router, extract::ws::Message, routing::any};
+
+    /// Upstream that echoes text and binary frames back to the sender
+    async fn spawn_echo_upstream() -> String {
+        let router = Router::new().route(
+            "/ws",
+            any(|ws: WebSocketUpgrade| async move {
+                ws.on_upgrade(|mut socket| async move {
+                    while let Some(Ok(msg)) = socket.recv().await {
+                        let echo = matches!(msg, Message::Text(_) | Message::Binary(_));
+                        if matches!(msg, Message::Close(_))
+                            || (echo && socket.send(msg).await.is_err())
+                        {
+                            break;
+                        }
+                    }
+                })
+            }),
+        );
+        spawn_upstream(router).await.replacen("http://", "ws://", 1)
+    }
+
+    /// Proxy that forwards `/ws` to the given upstream WebSocket URL
+    async fn spawn_proxy(target_url: String, options: WebSocketOptions) -> String {
+        let router = Router::new().route(
+            "/ws",
+            any(move |req: Request<Body>| async move {
+                proxy_websocket_connection(req, format!("{}/ws", target_url), options).await
+            }),
+        );
+        spawn_upstream(router).await.replacen("http://", "ws://", 1)
+    }
+
+    #[tokio::test]
+    async fn test_frame_counters_match_scripted_exchange() {
+        let metrics = Arc::new(Metrics::default());
+        let upstream = spawn_echo_upstream().await;
+        let proxy = spawn_proxy(
+            upstream,
+            WebSocketOptions {
+                app_name: "sonarr".to_string(),
+                frame_metrics: Some(metrics.clone()),
+                connection_guard: None,
+                idle_timeout: None,
+                max_lifetime: None,
+                unix_socket: None,
+                origin: None,
+            },
+        )
+        .await;
+
+        let (mut client, _) = connect_async(format!("{}/ws", proxy)).await.unwrap();
+        client
+            .send(TungsteniteMessage::Text("hello".to_string()))
+            .await
+            .unwrap();
+        client
+            .send(TungsteniteMessage::Binary(vec![1, 2, 3]))
+            .await
+            .unwrap();
+        client
+            .send(TungsteniteMessage::Ping(vec![9]))
+            .await
+            .unwrap();
+
+        // Wait for both echoes plus the pong relayed from upstream (the proxy
+        // also answers the ping itself, so two pongs arrive)
+        let (mut echoes, mut pongs) = (0, 0);
+        while echoes < 2 || pongs < 2 {
+            match client.next().await.unwrap().unwrap() {
+                TungsteniteMessage::Text(_) | TungsteniteMessage::Binary(_) => echoes += 1,
+                TungsteniteMessage::Pong(_) => pongs += 1,
+                _ => {}
+            }
+        }
+        client.close(None).await.unwrap();
+
+        let frames = |direction: &str, kind: &str| {
+            metrics.get(
+                "bouncarr_websocket_frames_total",
+                &[("app", "sonarr"), ("direction", direction), ("type", kind)],
+            )
+        };
+        let bytes = |direction: &str| {
+            metrics.get(
+                "bouncarr_websocket_bytes_total",
+                &[("app", "sonarr"), ("direction", direction)],
+            )
+        };
+
+        // Counters are exported once the connection has been torn down
+        for _ in 0..100 {
+            if frames("client_to_upstream", "close") > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(frames("client_to_upstream", "text"), 1);
+        assert_eq!(frames("client_to_upstream", "binary"), 1);
+        assert_eq!(frames("client_to_upstream", "ping"), 1);
+        assert_eq!(frames("client_to_upstream", "close"), 1);
+        assert_eq!(bytes("client_to_upstream"), 5 + 3 + 1);
+
+        assert_eq!(frames("upstream_to_client", "text"), 1);
+        assert_eq!(frames("upstream_to_client", "binary"), 1);
+        assert_eq!(frames("upstream_to_client", "pong"), 1);
+        assert_eq!(bytes("upstream_to_client"), 5 + 3 + 1);
+    }
+
+    #[tokio::test]
+    async fn test_frame_counters_disabled() {
+        let upstream = spawn_echo_upstream().await;
+        let proxy = spawn_proxy(
+            upstream,
+            WebSocketOptions {
+                app_name: "sonarr".to_string(),
+                frame_metrics: None,
+                connection_guard: None,
+                idle_timeout: None,
+                max_lifetime: None,
+                unix_socket: None,
+                origin: None,
+            },
+        )
+        .await;
+
+        let (mut client, _) = connect_async(format!("{}/ws", proxy)).await.unwrap();
+        client
+            .send(TungsteniteMessage::Text("hello".to_string()))
+            .await
+            .unwrap();
+        let echo = client.next().await.unwrap().unwrap();
+        assert_eq!(echo, TungsteniteMessage::Text("hello".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_closes_connection() {
+        let upstream = spawn_echo_upstream().await;
+        let proxy = spawn_proxy(
+            upstream,
+            WebSocketOptions {
+                app_name: "sonarr".to_string(),
+                frame_metrics: None,
+                connection_guard: None,
+                idle_timeout: Some(Duration::from_millis(200)),
+                max_lifetime: None,
+                unix_socket: None,
+                origin: None,
+            },
+        )
+        .await;
+
+        let (mut client, _) = connect_async(format!("{}/ws", proxy)).await.unwrap();
+        // Traffic keeps the connection open past the timeout
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            client
+                .send(TungsteniteMessage::Text("hello".to_string()))
+                .await
+                .unwrap();
+            let echo = client.next().await.unwrap().unwrap();
+            assert_eq!(echo, TungsteniteMessage::Text("hello".to_string()));
+        }
+
+        let closed = tokio::time::timeout(Duration::from_secs(5), client.next())
+            .await
+            .expect("idle connection should be closed");
+        match closed {
+            Some(Ok(TungsteniteMessage::Close(Some(frame)))) => {
+                assert_eq!(u16::from(frame.code), 1001);
+            }
+            other => panic!("expected close frame, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_lifetime_closes_active_connection() {
+        let upstream = spawn_echo_upstream().await;
+        let proxy = spawn_proxy(
+            upstream,
+            WebSocketOptions {
+                app_name: "sonarr".to_string(),
+                frame_metrics: None,
+                connection_guard: None,
+                idle_timeout: Some(Duration::from_secs(60)),
+                max_lifetime: Some(Duration::from_millis(300)),
+                unix_socket: None,
+                origin: None,
+            },
+        )
+        .await;
+
+        let (mut client, _) = connect_async(format!("{}/ws", proxy)).await.unwrap();
+        let opened = std::time::Instant::now();
+        // Keep exchanging frames until the proxy closes the connection
+        let frame = loop {
+            client
+                .send(TungsteniteMessage::Text("hello".to_string()))
+                .await
+                .unwrap();
+            let msg = tokio::time::timeout(Duration::from_secs(5), client.next())
+                .await
+                .expect("connection should be closed");
+            match msg {
+                Some(Ok(TungsteniteMessage::Text(_))) => {
+                    tokio::time::sleep(Duration::from_millis(20)).await
+                }
+                Some(Ok(TungsteniteMessage::Close(Some(frame)))) => break frame,
+                other => panic!("expected echo or close frame, got {:?}", other),
+            }
+        };
+
+        assert_eq!(u16::from(frame.code), 1000);
+        assert!(opened.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_utf8_text_frame_closes_with_1007() {
+        use tokio_tungstenite::tungstenite::protocol::frame::{
+            Frame,
+            coding::{CloseCode, Data, OpCode},
+        };
+
+        let upstream = spawn_echo_upstream().await;
+        let proxy = spawn_proxy(
+            upstream,
+            WebSocketOptions {
+                app_name: "sonarr".to_string(),
+                frame_metrics: None,
+                connection_guard: None,
+                idle_timeout: None,
+                max_lifetime: None,
+                unix_socket: None,
+                origin: None,
+            },
+        )
+        .await;
+
+        let (mut client, _) = connect_async(format!("{}/ws", proxy)).await.unwrap();
+        let invalid = Frame::message(vec![0x68, 0xff, 0xfe], OpCode::Data(Data::Text), true);
+        client
+            .send(TungsteniteMessage::Frame(invalid))
+            .await
+            .unwrap();
+
+        let closed = tokio::time::timeout(Duration::from_secs(5), client.next())
+            .await
+            .expect("connection should be closed");
+        match closed {
+            Some(Ok(TungsteniteMessage::Close(Some(frame)))) => {
+                assert_eq!(frame.code, CloseCode::Invalid);
+                assert_eq!(u16::from(frame.code), 1007);
+            }
+            other => panic!("expected close frame, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_logged_with_initiator_and_code() {
+        use axum::extract::ws::CloseFrame;
+        use tokio_tungstenite::tungstenite::protocol::frame::{
+            Frame,
+            coding::{CloseCode, Data, OpCode},
+        };
+
+        // Upstream that closes with 4001 when told to
+        let upstream = Router::new().route(
+            "/ws",
+            any(|ws: WebSocketUpgrade| async move {
+                ws.on_upgrade(|mut socket| async move {
+                    while let Some(Ok(msg)) = socket.recv().await {
+                        if msg == Message::Text("close".to_string()) {
+                            let frame = CloseFrame {
+                                code: 4001,
+                                reason: "upstream done".into(),
+                            };
+                            let _ = socket.send(Message::Close(Some(frame))).await;
+                        }
+                    }
+                })
+            }),
+        );
+        let upstream = spawn_upstream(upstream)
+            .await
+            .replacen("http://", "ws://", 1);
+
+        let cases = [
+            (
+                "client",
+                "closed by client (code 4000, reason 'client done')",
+                1,
+            ),
+            (
+                "upstream",
+                "closed by upstream (code 4001, reason 'upstream done')",
+                1,
+            ),
+            (
+                "error",
+                "closed by error (code 1007, reason 'Invalid UTF-8 in text frame')",
+                0,
+            ),
+        ];
+        for (script, expected, frames) in cases {
+            let (_guard, logs) = crate::test_support::capture_logs();
+            let proxy = spawn_proxy(
+                upstream.clone(),
+                WebSocketOptions {
+                    app_name: "sonarr".to_string(),
+                    frame_metrics: None,
+                    connection_guard: None,
+                    idle_timeout: None,
+                    max_lifetime: None,
+                    unix_socket: None,
+                    origin: None,
+                },
+            )
+            .await;
+
+            let (mut client, _) = connect_async(format!("{}/ws", proxy)).await.unwrap();
+            let msg = match script {
+                "client" => TungsteniteMessage::Close(Some(TungsteniteCloseFrame {
+                    code: CloseCode::from(4000),
+                    reason: "client done".into(),
+                })),
+                "upstream" => TungsteniteMessage::Text("close".to_string()),
+                _ => TungsteniteMessage::Frame(Frame::message(
+                    vec![0x68, 0xff],
+                    OpCode::Data(Data::Text),
+                    true,
+                )),
+            };
+            client.send(msg).await.unwrap();
+            while let Some(Ok(_)) = client.next().await {}
+
+            let mut contents = String::new();
+            for _ in 0..100 {
+                contents = logs.contents();
+                if contents.contains("closed by") {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(contents.contains(expected), "{}: {}", script, contents);
+            let counts = format!("{} frames client->upstream", frames);
+            assert!(contents.contains(&counts), "{}", contents);
+        }
     }
 }
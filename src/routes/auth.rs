@@ -1,15 +1,51 @@
 use crate::AppState;
-use crate::auth::jwt::TokenType;
+use crate::auth::cookies::{
+    REDIRECT_COOKIE_NAME, access_cookie_until, refresh_cookie, same_site_for,
+};
+use crate::auth::jwt::{Claims, TokenType};
+use crate::auth::login_webhook::{self, LoginEvent, LoginOutcome};
+use crate::auth::middleware::{bearer_token, presented_access_cookie};
+use crate::config::{Config, SecurityConfig};
 use crate::error::{AppError, Result};
-use axum::{Json, extract::State};
+use crate::jellyfin::types::UserInfo;
+use axum::{
+    Json,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tower_cookies::{Cookie, Cookies};
+use tower_cookies::{Cookie, Cookies, cookie::SameSite};
+
+/// Methods accepted by the `/bouncarr/api/auth/*` endpoints
+const AUTH_ALLOWED_METHODS: &str = "POST, OPTIONS";
 
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+    /// Return the tokens in the response body instead of setting cookies
+    #[serde(default)]
+    pub token_response: bool,
+    /// Token profile (`security.token_profiles`) setting the access token
+    /// lifetime
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JellyfinTokenRequest {
+    /// Jellyfin `AccessToken` of an existing Jellyfin session
+    pub access_token: String,
+    /// Return the tokens in the response body instead of setting cookies
+    #[serde(default)]
+    pub token_response: bool,
+    /// Token profile (`security.token_profiles`) setting the access token
+    /// lifetime
+    #[serde(default)]
+    pub profile: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -17,18 +53,31 @@ pub struct LoginResponse {
     pub success: bool,
     pub username: String,
     pub is_admin: bool,
+    /// Where to send the user after login
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<String>,
+    /// Access token, only in token mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    /// Refresh token, only in token mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
 }
 
 pub async fn login(
     State(state): State<Arc<AppState>>,
     cookies: Cookies,
+    headers: HeaderMap,
+    client: Option<ConnectInfo<SocketAddr>>,
     Json(req): Json<LoginRequest>,
 ) -> Result<Json<LoginResponse>> {
+    check_origin(&state.config.security, &headers)?;
+
     // Validate input
-    validate_login_request(&req)?;
+    validate_login_request(&req, &state.config.security)?;
 
     // Authenticate with Jellyfin
-    let (user_info, _jellyfin_token) = match state
+    let (user_info, jellyfin_token) = match state
         .jellyfin_client
         .authenticate(&req.username, &req.password)
         .await
@@ -36,140 +85,569 @@ pub async fn login(
         Ok(result) => result,
         Err(e) => {
             tracing::warn!("Failed login attempt for user '{}': {}", req.username, e);
+            if matches!(e, AppError::AuthenticationFailed(_)) {
+                report_login(&state, &req.username, client, LoginOutcome::Failure);
+            }
             return Err(e);
         }
     };
 
     // Check if user is an administrator
-    if !user_info.is_administrator {
+    let user_info = promote_bootstrap_admin(&state.config.security, user_info);
+    if !user_info.is_administrator && !state.config.admits_non_admins() {
         tracing::warn!("Non-admin user '{}' attempted to login", user_info.username);
+        report_login(&state, &user_info.username, client, LoginOutcome::Forbidden);
         return Err(AppError::Forbidden);
     }
 
     tracing::info!("User '{}' logged in successfully", user_info.username);
+    report_login(&state, &user_info.username, client, LoginOutcome::Success);
 
-    // Create JWT tokens
-    let access_token = state.jwt_manager.create_access_token(&user_info)?;
-    let refresh_token = state.jwt_manager.create_refresh_token(&user_info)?;
-
-    // Set cookies
-    // Note: Cookie::new requires ownership, so cloning cookie names is necessary
-    let mut access_cookie = Cookie::new(state.config.security.cookie_name.clone(), access_token);
-    access_cookie.set_http_only(true);
-    access_cookie.set_secure(state.config.security.secure_cookies);
-    access_cookie.set_same_site(tower_cookies::cookie::SameSite::Lax);
-    access_cookie.set_path("/");
-    // Set max age to end of day to match JWT expiration
-    let now = chrono::Utc::now();
-    let end_of_day = now
-        .date_naive()
-        .and_hms_opt(23, 59, 59)
-        .ok_or_else(|| {
-            crate::error::AppError::Internal(anyhow::anyhow!(
-                "Failed to create end of day timestamp"
-            ))
-        })?
-        .and_utc();
-    let seconds_until_eod = (end_of_day - now).num_seconds();
-    access_cookie.set_max_age(tower_cookies::cookie::time::Duration::seconds(
-        seconds_until_eod,
-    ));
-    cookies.add(access_cookie);
+    issue_session(
+        &state,
+        &cookies,
+        same_site_for(&state.config.security, &headers),
+        user_info,
+        Some(jellyfin_token),
+        req.token_response,
+        req.profile.as_deref(),
+    )
+}
 
-    let mut refresh_cookie = Cookie::new(
-        state.config.security.refresh_cookie_name.clone(),
-        refresh_token,
+/// Exchange an existing Jellyfin access token for a Bouncarr session
+pub async fn jellyfin_token_login(
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+    headers: HeaderMap,
+    client: Option<ConnectInfo<SocketAddr>>,
+    Json(req): Json<JellyfinTokenRequest>,
+) -> Result<Json<LoginResponse>> {
+    check_origin(&state.config.security, &headers)?;
+
+    if req.access_token.is_empty() || req.access_token.len() > 1024 {
+        return Err(AppError::AuthenticationFailed(
+            "Invalid Jellyfin access token".to_string(),
+        ));
+    }
+
+    // Validate the token by asking Jellyfin who it belongs to
+    let user_info = match state.jellyfin_client.get_me(&req.access_token).await {
+        Ok(user_info) => user_info,
+        Err(e) => {
+            tracing::warn!("Failed Jellyfin token exchange: {}", e);
+            return Err(e);
+        }
+    };
+
+    let user_info = promote_bootstrap_admin(&state.config.security, user_info);
+    if !user_info.is_administrator && !state.config.admits_non_admins() {
+        tracing::warn!(
+            "Non-admin user '{}' attempted Jellyfin token exchange",
+            user_info.username
+        );
+        report_login(&state, &user_info.username, client, LoginOutcome::Forbidden);
+        return Err(AppError::Forbidden);
+    }
+
+    tracing::info!(
+        "User '{}' logged in with a Jellyfin access token",
+        user_info.username
     );
-    refresh_cookie.set_http_only(true);
-    refresh_cookie.set_secure(state.config.security.secure_cookies);
-    refresh_cookie.set_same_site(tower_cookies::cookie::SameSite::Lax);
-    refresh_cookie.set_path("/");
-    let refresh_max_age = state.config.security.refresh_token_expiry_days as i64 * 86400;
-    refresh_cookie.set_max_age(tower_cookies::cookie::time::Duration::seconds(
-        refresh_max_age,
-    ));
-    cookies.add(refresh_cookie);
+    report_login(&state, &user_info.username, client, LoginOutcome::Success);
+
+    // The Jellyfin token belongs to another client's session, so it isn't kept
+    issue_session(
+        &state,
+        &cookies,
+        same_site_for(&state.config.security, &headers),
+        user_info,
+        None,
+        req.token_response,
+        req.profile.as_deref(),
+    )
+}
+
+/// Keep browsers and proxies from storing a login page or auth API response
+pub async fn no_store(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers.insert(header::PRAGMA, HeaderValue::from_static("no-cache"));
+    headers.append(header::VARY, HeaderValue::from_static("Cookie"));
+    response
+}
+
+/// Send a login attempt to `security.login_webhook_url`, if configured
+///
+/// Unsuccessful attempts are only sent with `login_webhook_failures`.
+fn report_login(
+    state: &AppState,
+    username: &str,
+    client: Option<ConnectInfo<SocketAddr>>,
+    outcome: LoginOutcome,
+) {
+    let security = &state.config.security;
+    let Some(url) = &security.login_webhook_url else {
+        return;
+    };
+    if outcome != LoginOutcome::Success && !security.login_webhook_failures {
+        return;
+    }
+    let ip = client.map(|ConnectInfo(addr)| addr.ip());
+    login_webhook::notify(
+        &state.http_client,
+        url,
+        LoginEvent::new(username, ip, outcome),
+    );
+}
+
+/// Create access and refresh tokens for an authenticated user
+///
+/// Browsers get them as HttpOnly cookies. In token mode, for clients that
+/// cannot use cookies, or with `disable_cookies`, they are returned in the
+/// response body instead. The Jellyfin token, if given, is kept server-side
+/// with the session. A token profile sets the access token lifetime and is
+/// remembered in the refresh token.
+/// End the Jellyfin sessions behind tokens Bouncarr no longer keeps, in the
+/// background, as `logout` does for a single session
+fn end_jellyfin_sessions(state: &AppState, jellyfin_tokens: Vec<String>) {
+    if jellyfin_tokens.is_empty() {
+        return;
+    }
+    let jellyfin_client = state.jellyfin_client.clone();
+    tokio::spawn(async move {
+        for jellyfin_token in jellyfin_tokens {
+            if let Err(e) = jellyfin_client.logout(&jellyfin_token).await {
+                tracing::warn!("Failed to end Jellyfin session: {}", e);
+            }
+        }
+    });
+}
+
+/// End the Jellyfin sessions of sessions that expired or went idle, every
+/// `interval`
+pub async fn end_expired_jellyfin_sessions(state: Arc<AppState>, interval: std::time::Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        end_jellyfin_sessions(&state, state.sessions.take_expired_jellyfin_tokens());
+    }
+}
+
+fn issue_session(
+    state: &AppState,
+    cookies: &Cookies,
+    same_site: SameSite,
+    user_info: UserInfo,
+    jellyfin_token: Option<String>,
+    token_response: bool,
+    profile: Option<&str>,
+) -> Result<Json<LoginResponse>> {
+    // Check the profile before a session is created for it
+    if let Some(profile) = profile
+        && !state.config.security.token_profiles.contains_key(profile)
+    {
+        return Err(AppError::BadRequest(format!(
+            "Unknown token profile '{}'",
+            profile
+        )));
+    }
 
-    Ok(Json(LoginResponse {
+    if state.config.security.single_session {
+        let (revoked, jellyfin_tokens) = state.sessions.revoke_user(&user_info.user_id);
+        if revoked > 0 {
+            tracing::info!(
+                "Ended {} earlier session(s) of '{}'",
+                revoked,
+                user_info.username
+            );
+        }
+        end_jellyfin_sessions(state, jellyfin_tokens);
+    }
+
+    // Create JWT tokens for a new session
+    let sid = state.sessions.create(&user_info.user_id, jellyfin_token);
+    let (access_token, exp) = state
+        .jwt_manager
+        .create_access_token_for(&user_info, &sid, profile)?;
+    let refresh_token = new_refresh_token(state, &user_info, &sid, profile)?;
+
+    let mut response = LoginResponse {
         success: true,
         username: user_info.username,
         is_admin: user_info.is_administrator,
-    }))
+        redirect: None,
+        access_token: None,
+        refresh_token: None,
+    };
+
+    if token_response || state.config.security.disable_cookies {
+        response.access_token = Some(access_token);
+        response.refresh_token = Some(refresh_token);
+    } else {
+        // Set cookies
+        cookies.add(access_cookie_until(
+            &state.config.security,
+            same_site,
+            access_token,
+            exp,
+        ));
+        cookies.add(refresh_cookie(
+            &state.config.security,
+            same_site,
+            refresh_token,
+        ));
+        response.redirect = Some(take_redirect(state, cookies));
+    }
+
+    Ok(Json(response))
+}
+
+/// Create a refresh token, recording it with `track_refresh_tokens`
+fn new_refresh_token(
+    state: &AppState,
+    user_info: &UserInfo,
+    sid: &str,
+    profile: Option<&str>,
+) -> Result<String> {
+    let (token, jti) = state
+        .jwt_manager
+        .create_refresh_token_for(user_info, sid, profile)?;
+    let security = &state.config.security;
+    if security.track_refresh_tokens {
+        let exp =
+            chrono::Utc::now().timestamp() + security.refresh_token_lifetime().as_secs() as i64;
+        state.refresh_tokens.record(&jti, sid, exp);
+    }
+    Ok(token)
+}
+
+/// Consume the redirect cookie set by the login page
+///
+/// Falls back to the configured default when the cookie is missing or holds
+/// an unsafe target.
+fn take_redirect(state: &AppState, cookies: &Cookies) -> String {
+    let default = &state.config.server.default_redirect;
+    let Some(cookie) = cookies.get(REDIRECT_COOKIE_NAME) else {
+        return default.clone();
+    };
+    cookies.remove(Cookie::from(REDIRECT_COOKIE_NAME));
+
+    match urlencoding::decode(cookie.value()) {
+        Ok(target) if Config::is_safe_redirect(&target) => target.into_owned(),
+        _ => {
+            tracing::warn!("Ignoring unsafe redirect cookie: {}", cookie.value());
+            default.clone()
+        }
+    }
 }
 
 pub async fn refresh(
     State(state): State<Arc<AppState>>,
     cookies: Cookies,
+    headers: HeaderMap,
 ) -> Result<Json<LoginResponse>> {
-    // Get refresh token from cookie
-    let refresh_token = cookies
-        .get(&state.config.security.refresh_cookie_name)
-        .ok_or(AppError::Unauthorized)?
-        .value()
-        .to_string();
+    check_origin(&state.config.security, &headers)?;
+
+    // Get refresh token from cookie, or the Authorization header when
+    // cookies are disabled
+    let refresh_token = if state.config.security.disable_cookies {
+        bearer_token(&headers)
+            .ok_or(AppError::Unauthorized)?
+            .to_string()
+    } else {
+        cookies
+            .get(&state.config.security.refresh_cookie_name)
+            .ok_or(AppError::Unauthorized)?
+            .value()
+            .to_string()
+    };
 
     // Validate refresh token
     let claims = state
         .jwt_manager
         .validate_token(&refresh_token, TokenType::Refresh)?;
 
-    // Fetch fresh user data from Jellyfin
-    let user_info = state.jellyfin_client.get_user(&claims.sub).await?;
+    if state.config.security.track_refresh_tokens && !state.refresh_tokens.accepts(&claims) {
+        tracing::warn!("Rejected unrecorded refresh token for {}", claims.username);
+        return Err(AppError::Unauthorized);
+    }
 
-    // Check if still an administrator
-    if !user_info.is_administrator {
-        return Err(AppError::Forbidden);
+    // Revoked or idle sessions cannot be refreshed
+    let idle_timeout = state.config.security.idle_session_timeout();
+    if !state.sessions.is_active(&claims.sid, idle_timeout) {
+        return Err(AppError::Unauthorized);
     }
 
-    // Create new access token
-    let access_token = state.jwt_manager.create_access_token(&user_info)?;
-
-    // Set new access token cookie
-    let mut access_cookie = Cookie::new(state.config.security.cookie_name.clone(), access_token);
-    access_cookie.set_http_only(true);
-    access_cookie.set_secure(state.config.security.secure_cookies);
-    access_cookie.set_same_site(tower_cookies::cookie::SameSite::Lax);
-    access_cookie.set_path("/");
-    // Set max age to end of day to match JWT expiration
-    let now = chrono::Utc::now();
-    let end_of_day = now
-        .date_naive()
-        .and_hms_opt(23, 59, 59)
-        .ok_or_else(|| {
-            crate::error::AppError::Internal(anyhow::anyhow!(
-                "Failed to create end of day timestamp"
-            ))
-        })?
-        .and_utc();
-    let seconds_until_eod = (end_of_day - now).num_seconds();
-    access_cookie.set_max_age(tower_cookies::cookie::time::Duration::seconds(
-        seconds_until_eod,
-    ));
-    cookies.add(access_cookie);
+    // Fetch fresh user data from Jellyfin. While Jellyfin is unavailable the
+    // token's own claims can stand in for a short while, if configured.
+    let security = &state.config.security;
+    let (user_info, not_after) = match fetch_session_user(&state, &claims).await {
+        Ok(user_info) => (promote_bootstrap_admin(security, user_info), None),
+        Err(e) if is_transient(&e) && security.refresh_fallback_seconds.is_some() => {
+            tracing::warn!(
+                "Jellyfin unavailable, refreshing {} from token claims: {}",
+                claims.username,
+                e
+            );
+            let fallback = security.refresh_fallback_seconds.unwrap_or_default() as i64;
+            let user_info = UserInfo {
+                user_id: claims.sub.clone(),
+                username: claims.username.clone(),
+                is_administrator: claims.is_admin,
+                groups: claims.groups.clone(),
+            };
+            let not_after = (chrono::Utc::now().timestamp() + fallback).min(claims.exp);
+            (user_info, Some(not_after))
+        }
+        Err(e) => return Err(e),
+    };
 
-    Ok(Json(LoginResponse {
+    // Check if still an administrator, unless some app admits anyone
+    if !user_info.is_administrator && !state.config.admits_non_admins() {
+        return Err(AppError::Forbidden);
+    }
+
+    // Create new access token and set its cookie, or return it in the body
+    // when cookies are disabled
+    let same_site = same_site_for(security, &headers);
+    let profile = state.jwt_manager.known_profile(&claims);
+    let mut response = LoginResponse {
         success: true,
-        username: user_info.username,
+        username: user_info.username.clone(),
         is_admin: user_info.is_administrator,
-    }))
+        redirect: None,
+        access_token: None,
+        refresh_token: None,
+    };
+
+    // Renew a refresh token close to expiry for users still verified with
+    // Jellyfin. The session id is kept, so revoking it still ends both.
+    if not_after.is_none()
+        && let Some(window_days) = security.refresh_reissue_window_days
+        && claims.exp - chrono::Utc::now().timestamp() < window_days as i64 * 86400
+    {
+        tracing::debug!("Re-issuing refresh token for {}", user_info.username);
+        let refresh_token = new_refresh_token(&state, &user_info, &claims.sid, profile)?;
+        if let Some(jti) = &claims.jti {
+            state.refresh_tokens.remove(jti);
+        }
+        if security.disable_cookies {
+            response.refresh_token = Some(refresh_token);
+        } else {
+            cookies.add(refresh_cookie(security, same_site, refresh_token));
+        }
+    }
+    let (access_token, until) = match not_after {
+        Some(not_after) => {
+            let (access_token, exp) = state.jwt_manager.create_rolling_access_token(
+                &user_info,
+                &claims.sid,
+                not_after,
+            )?;
+            (access_token, exp)
+        }
+        None => state
+            .jwt_manager
+            .create_access_token_for(&user_info, &claims.sid, profile)?,
+    };
+    if security.disable_cookies {
+        response.access_token = Some(access_token);
+    } else {
+        cookies.add(access_cookie_until(
+            security,
+            same_site,
+            access_token,
+            until,
+        ));
+    }
+
+    Ok(Json(response))
 }
 
 pub async fn logout(
     State(state): State<Arc<AppState>>,
     cookies: Cookies,
+    headers: HeaderMap,
 ) -> Result<Json<serde_json::Value>> {
+    check_origin(&state.config.security, &headers)?;
+
+    // End the session so copies of its tokens stop working too, along with
+    // the Jellyfin session opened at login
+    if let Some(sid) = session_id(&state, &cookies, &headers) {
+        state.refresh_tokens.remove_session(&sid);
+        if let Some(jellyfin_token) = state.sessions.revoke(&sid)
+            && let Err(e) = state.jellyfin_client.logout(&jellyfin_token).await
+        {
+            tracing::warn!("Failed to end Jellyfin session on logout: {}", e);
+        }
+    }
+
+    if state.config.security.disable_cookies {
+        return Ok(Json(serde_json::json!({ "success": true })));
+    }
+
     // Remove cookies
     // Note: Clones are necessary as Cookie::new/from require ownership of strings
     cookies.remove(Cookie::from(state.config.security.cookie_name.clone()));
     cookies.remove(Cookie::from(
         state.config.security.refresh_cookie_name.clone(),
     ));
+    for name in &state.config.security.legacy_cookie_names {
+        if cookies.get(name).is_some() {
+            cookies.remove(Cookie::from(name.clone()));
+        }
+    }
 
     Ok(Json(serde_json::json!({ "success": true })))
 }
 
+/// Fetch the user a session belongs to from Jellyfin, as the user themselves
+/// if their Jellyfin session from login is known
+///
+/// Transient failures are retried `refresh_retries` times with a doubling
+/// delay.
+async fn fetch_session_user(state: &AppState, claims: &Claims) -> Result<UserInfo> {
+    let security = &state.config.security;
+    let mut attempt = 0;
+    loop {
+        let result = match state.sessions.jellyfin_token(&claims.sid) {
+            Some(jellyfin_token) => state.jellyfin_client.get_me(&jellyfin_token).await,
+            None => state.jellyfin_client.get_user(&claims.sub).await,
+        };
+        match result {
+            Err(e) if is_transient(&e) && attempt < security.refresh_retries => {
+                let delay = security
+                    .refresh_retry_delay_ms
+                    .saturating_mul(1 << attempt.min(16));
+                attempt += 1;
+                tracing::debug!(
+                    "Jellyfin unavailable on refresh for {} ({}), retry {} in {}ms",
+                    claims.username,
+                    e,
+                    attempt,
+                    delay
+                );
+                tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Treat the `bootstrap_admin` as an administrator, warning every time
+/// Jellyfin's answer is overridden
+fn promote_bootstrap_admin(security: &SecurityConfig, mut user_info: UserInfo) -> UserInfo {
+    if !user_info.is_administrator && security.is_bootstrap_admin(&user_info.username) {
+        tracing::warn!(
+            "Treating non-admin '{}' as administrator because security.bootstrap_admin \
+            is set. Make them a Jellyfin administrator and remove bootstrap_admin!",
+            user_info.username
+        );
+        user_info.is_administrator = true;
+    }
+    user_info
+}
+
+/// Whether a Jellyfin error means it is unavailable rather than that it
+/// rejected the request
+fn is_transient(e: &AppError) -> bool {
+    matches!(
+        e,
+        AppError::RequestFailed(_) | AppError::UpstreamUnavailable(_)
+    )
+}
+
+/// Session id from the request's refresh or access token cookie, or from
+/// the bearer token when cookies are disabled
+fn session_id(state: &AppState, cookies: &Cookies, headers: &HeaderMap) -> Option<String> {
+    let security = &state.config.security;
+    if security.disable_cookies {
+        let token = bearer_token(headers)?;
+        return [TokenType::Refresh, TokenType::Access]
+            .into_iter()
+            .find_map(|token_type| state.jwt_manager.validate_token(token, token_type).ok())
+            .map(|claims| claims.sid);
+    }
+    [
+        (
+            cookies.get(&security.refresh_cookie_name),
+            TokenType::Refresh,
+        ),
+        (
+            presented_access_cookie(cookies, security),
+            TokenType::Access,
+        ),
+    ]
+    .into_iter()
+    .find_map(|(cookie, token_type)| {
+        state
+            .jwt_manager
+            .validate_token(cookie?.value(), token_type)
+            .ok()
+    })
+    .map(|claims| claims.sid)
+}
+
+/// Answer `OPTIONS` requests (e.g. CORS preflights) on the auth endpoints
+pub async fn auth_options() -> Response {
+    (
+        StatusCode::NO_CONTENT,
+        [(header::ALLOW, AUTH_ALLOWED_METHODS)],
+    )
+        .into_response()
+}
+
+/// Reject methods that are not registered on an auth endpoint
+pub async fn auth_method_not_allowed() -> Response {
+    (
+        [(header::ALLOW, AUTH_ALLOWED_METHODS)],
+        AppError::MethodNotAllowed,
+    )
+        .into_response()
+}
+
+/// Reject cross-origin requests to the session endpoints when
+/// `check_login_origin` is enabled
+///
+/// The origin comes from `Origin`, or `Referer` if that is missing. It must
+/// match the request's `Host` or one of `login_allowed_origins`.
+fn check_origin(security: &SecurityConfig, headers: &HeaderMap) -> Result<()> {
+    if !security.check_login_origin {
+        return Ok(());
+    }
+
+    let header_value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let Some(source) = header_value(header::ORIGIN).or_else(|| header_value(header::REFERER))
+    else {
+        return Ok(());
+    };
+
+    let origin = url::Url::parse(source).ok().filter(|url| url.has_host());
+    let allowed = origin.is_some_and(|origin| {
+        let serialized = origin.origin().ascii_serialization();
+        let authority = match origin.port() {
+            Some(port) => format!("{}:{}", origin.host_str().unwrap_or_default(), port),
+            None => origin.host_str().unwrap_or_default().to_string(),
+        };
+        header_value(header::HOST).is_some_and(|host| host.eq_ignore_ascii_case(&authority))
+            || security.login_allowed_origins.iter().any(|allowed| {
+                allowed
+                    .trim_end_matches('/')
+                    .eq_ignore_ascii_case(&serialized)
+            })
+    });
+
+    if !allowed {
+        tracing::warn!("Rejected session request from origin '{}'", source);
+        return Err(AppError::AccessDenied(
+            "Cross-origin request rejected".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 /// Validate login request input
-fn validate_login_request(req: &LoginRequest) -> Result<()> {
+fn validate_login_request(req: &LoginRequest, security: &SecurityConfig) -> Result<()> {
     // Username validation
     if req.username.is_empty() {
         return Err(AppError::AuthenticationFailed(
@@ -199,56 +677,1047 @@ fn validate_login_request(req: &LoginRequest) -> Result<()> {
         ));
     }
 
+    // Configured pre-checks fail like a wrong password, without revealing
+    // which check it was or asking Jellyfin
+    let denylisted = security
+        .username_denylist
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(&req.username));
+    let too_short =
+        |value: &str, min: Option<usize>| min.is_some_and(|min| value.chars().count() < min);
+    if denylisted
+        || too_short(&req.username, security.min_username_length)
+        || too_short(&req.password, security.min_password_length)
+    {
+        tracing::debug!("Login for '{}' rejected by pre-checks", req.username);
+        return Err(AppError::AuthenticationFailed(
+            "Invalid username or password".to_string(),
+        ));
+    }
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::{
+        Router,
+        body::Body,
+        http::{HeaderMap, Request},
+        routing::{get, post},
+    };
+    use tower::ServiceExt;
+    use tower_cookies::CookieManagerLayer;
 
     fn test_login_request(username: &str, password: &str) -> LoginRequest {
         LoginRequest {
             username: username.to_string(),
             password: password.to_string(),
+            token_response: false,
+            profile: None,
+        }
+    }
+
+    async fn jellyfin_token_app(is_admin: bool) -> Router {
+        jellyfin_token_app_with(is_admin, |_| {}).await
+    }
+
+    async fn jellyfin_token_app_with(
+        is_admin: bool,
+        configure: impl FnOnce(&mut crate::config::SecurityConfig),
+    ) -> Router {
+        use crate::test_support::{jellyfin_user, spawn_upstream, test_config, test_state};
+
+        let user = jellyfin_user("user-1", "alice", is_admin);
+        let jellyfin = spawn_upstream(Router::new().route(
+            "/Users/Me",
+            get(move |headers: HeaderMap| async move {
+                match headers.get("X-MediaBrowser-Token") {
+                    Some(token) if token == "jf-token" => Ok(Json(user)),
+                    _ => Err(StatusCode::UNAUTHORIZED),
+                }
+            }),
+        ))
+        .await;
+
+        let mut config = test_config();
+        config.jellyfin.url = jellyfin;
+        configure(&mut config.security);
+        Router::new()
+            .route("/token", post(jellyfin_token_login))
+            .layer(CookieManagerLayer::new())
+            .with_state(test_state(config))
+    }
+
+    fn token_request(token: &str) -> Request<Body> {
+        token_request_with(serde_json::json!({ "access_token": token }))
+    }
+
+    fn token_request_with(body: serde_json::Value) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/token")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_jellyfin_token_login_issues_session() {
+        let app = jellyfin_token_app(true).await;
+        let res = app.oneshot(token_request("jf-token")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let cookies: Vec<_> = res
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect();
+        assert!(cookies.iter().any(|c| c.starts_with("bouncarr_token=")));
+        assert!(cookies.iter().any(|c| c.starts_with("bouncarr_refresh=")));
+    }
+
+    #[tokio::test]
+    async fn test_disable_cookies_returns_tokens_in_body() {
+        let app = jellyfin_token_app_with(true, |security| security.disable_cookies = true).await;
+        let res = app.oneshot(token_request("jf-token")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["access_token"].is_string());
+        assert!(body["refresh_token"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_same_site_follows_sec_fetch_site() {
+        let app = jellyfin_token_app_with(true, |security| {
+            security.secure_cookies = true;
+            security.same_site = crate::config::SameSiteMode::Strict;
+            security.adaptive_same_site = true;
+        })
+        .await;
+
+        for (fetch_site, expected) in [
+            (None, "SameSite=Strict"),
+            (Some("same-origin"), "SameSite=Strict"),
+            (Some("cross-site"), "SameSite=None"),
+        ] {
+            let mut req = token_request("jf-token");
+            if let Some(fetch_site) = fetch_site {
+                req.headers_mut()
+                    .insert("sec-fetch-site", fetch_site.parse().unwrap());
+            }
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            let cookies: Vec<_> = res.headers().get_all(header::SET_COOKIE).iter().collect();
+            assert_eq!(cookies.len(), 2);
+            for cookie in cookies {
+                assert!(cookie.to_str().unwrap().contains(expected), "{:?}", cookie);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cross_site_keeps_same_site_without_secure_cookies() {
+        let app = jellyfin_token_app_with(true, |security| {
+            security.adaptive_same_site = true;
+        })
+        .await;
+        let mut req = token_request("jf-token");
+        req.headers_mut()
+            .insert("sec-fetch-site", "cross-site".parse().unwrap());
+
+        let res = app.oneshot(req).await.unwrap();
+        for cookie in res.headers().get_all(header::SET_COOKIE) {
+            assert!(cookie.to_str().unwrap().contains("SameSite=Lax"));
+        }
+    }
+
+    /// Mock Jellyfin issuing "jf-session" at login and recording logouts
+    async fn spawn_session_jellyfin(logouts: Arc<std::sync::Mutex<Vec<String>>>) -> String {
+        use crate::test_support::{jellyfin_user, spawn_upstream};
+
+        let token_of = |headers: &HeaderMap| {
+            headers
+                .get("X-MediaBrowser-Token")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        spawn_upstream(
+            Router::new()
+                .route(
+                    "/Users/AuthenticateByName",
+                    post(|| async {
+                        Json(serde_json::json!({
+                            "User": jellyfin_user("user-1", "alice", true),
+                            "AccessToken": "jf-session",
+                        }))
+                    }),
+                )
+                .route(
+                    "/Users/Me",
+                    get(move |headers: HeaderMap| async move {
+                        if token_of(&headers) == "jf-session" {
+                            Ok(Json(jellyfin_user("user-1", "alice", true)))
+                        } else {
+                            Err(StatusCode::UNAUTHORIZED)
+                        }
+                    }),
+                )
+                .route(
+                    "/Sessions/Logout",
+                    post(move |headers: HeaderMap| async move {
+                        logouts.lock().unwrap().push(token_of(&headers));
+                        StatusCode::NO_CONTENT
+                    }),
+                ),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_jellyfin_token_kept_with_session() {
+        use crate::test_support::{test_config, test_state};
+
+        let logouts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut config = test_config();
+        config.jellyfin.url = spawn_session_jellyfin(logouts.clone()).await;
+        let state = test_state(config);
+        let app = Router::new()
+            .route("/login", post(login))
+            .route("/refresh", post(refresh))
+            .route("/logout", post(logout))
+            .layer(CookieManagerLayer::new())
+            .with_state(state.clone());
+
+        let res = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/login")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"username":"alice","password":"pw"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookies: Vec<String> = res
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap().split(';').next().unwrap().to_string())
+            .collect();
+        let refresh_token = cookies
+            .iter()
+            .find_map(|c| c.strip_prefix("bouncarr_refresh="))
+            .unwrap();
+        let sid = state
+            .jwt_manager
+            .validate_token(refresh_token, TokenType::Refresh)
+            .unwrap()
+            .sid;
+
+        // Stored at login and used for user-scoped calls such as refresh
+        assert_eq!(
+            state.sessions.jellyfin_token(&sid).as_deref(),
+            Some("jf-session")
+        );
+        let with_cookies = |uri: &str| {
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header(header::COOKIE, cookies.join("; "))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let res = app.clone().oneshot(with_cookies("/refresh")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Cleared, and the Jellyfin session ended, on logout
+        let res = app.oneshot(with_cookies("/logout")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(state.sessions.jellyfin_token(&sid), None);
+        assert_eq!(*logouts.lock().unwrap(), vec!["jf-session".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_track_refresh_tokens() {
+        use crate::test_support::{test_config, test_state};
+
+        let mut config = test_config();
+        config.jellyfin.url = spawn_session_jellyfin(Default::default()).await;
+        config.security.track_refresh_tokens = true;
+        config.security.refresh_reissue_window_days = Some(31);
+        let state = test_state(config);
+        let app = Router::new()
+            .route("/login", post(login))
+            .route("/refresh", post(refresh))
+            .route("/logout", post(logout))
+            .layer(CookieManagerLayer::new())
+            .with_state(state.clone());
+        let with_refresh = |uri: &str, token: &str| {
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header(header::COOKIE, format!("bouncarr_refresh={}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let res = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/login")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"username":"alice","password":"pw","token_response":true}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let issued = body["refresh_token"].as_str().unwrap().to_string();
+        let claims = state
+            .jwt_manager
+            .validate_token(&issued, TokenType::Refresh)
+            .unwrap();
+        assert!(claims.jti.is_some());
+
+        // Refreshing rotates the refresh token, retiring the old one
+        let res = app
+            .clone()
+            .oneshot(with_refresh("/refresh", &issued))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let rotated = res
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .find_map(|c| {
+                let cookie = c.to_str().unwrap().split(';').next().unwrap();
+                cookie.strip_prefix("bouncarr_refresh=").map(String::from)
+            })
+            .unwrap();
+        let res = app
+            .clone()
+            .oneshot(with_refresh("/refresh", &issued))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let rotated_claims = state
+            .jwt_manager
+            .validate_token(&rotated, TokenType::Refresh)
+            .unwrap();
+        assert!(state.refresh_tokens.accepts(&rotated_claims));
+
+        // Validly signed for the same session, but never issued
+        let user = UserInfo {
+            user_id: claims.sub.clone(),
+            username: claims.username.clone(),
+            is_administrator: true,
+            groups: Vec::new(),
+        };
+        let forged = state
+            .jwt_manager
+            .create_refresh_token(&user, &claims.sid)
+            .unwrap();
+        let res = app
+            .clone()
+            .oneshot(with_refresh("/refresh", &forged))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // Logging out removes the record
+        let res = app
+            .clone()
+            .oneshot(with_refresh("/logout", &rotated))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!state.refresh_tokens.accepts(&rotated_claims));
+    }
+
+    #[tokio::test]
+    async fn test_token_mode_returns_tokens_in_body() {
+        let app = jellyfin_token_app(true).await;
+        let req = token_request_with(serde_json::json!({
+            "access_token": "jf-token",
+            "token_response": true,
+        }));
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let state = crate::test_support::test_state(crate::test_support::test_config());
+        let access = body["access_token"].as_str().unwrap();
+        let refresh = body["refresh_token"].as_str().unwrap();
+        assert!(
+            state
+                .jwt_manager
+                .validate_token(access, TokenType::Access)
+                .is_ok()
+        );
+        assert!(
+            state
+                .jwt_manager
+                .validate_token(refresh, TokenType::Refresh)
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_profile_picked_at_login() {
+        let app = jellyfin_token_app_with(true, |security| {
+            security.token_profiles.insert(
+                "automation".to_string(),
+                crate::config::TokenProfile {
+                    access_token_hours: 168,
+                },
+            );
+        })
+        .await;
+        let req = token_request_with(serde_json::json!({
+            "access_token": "jf-token",
+            "token_response": true,
+            "profile": "automation",
+        }));
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let state = crate::test_support::test_state(crate::test_support::test_config());
+        let access = state
+            .jwt_manager
+            .validate_token(body["access_token"].as_str().unwrap(), TokenType::Access)
+            .unwrap();
+        let expected = chrono::Utc::now().timestamp() + 168 * 3600;
+        assert!((access.exp - expected).abs() <= 5);
+        let refresh = state
+            .jwt_manager
+            .validate_token(body["refresh_token"].as_str().unwrap(), TokenType::Refresh)
+            .unwrap();
+        assert_eq!(refresh.profile.as_deref(), Some("automation"));
+
+        let req = token_request_with(serde_json::json!({
+            "access_token": "jf-token",
+            "profile": "unknown",
+        }));
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_single_session_ends_earlier_sessions() {
+        use crate::test_support::{jellyfin_user, spawn_upstream, test_config, test_state};
+
+        let jellyfin = spawn_upstream(Router::new().route(
+            "/Users/Me",
+            get(|headers: HeaderMap| async move {
+                match headers.get("X-MediaBrowser-Token").map(|t| t.as_bytes()) {
+                    Some(b"alice-token") => Ok(Json(jellyfin_user("user-1", "alice", true))),
+                    Some(b"bob-token") => Ok(Json(jellyfin_user("user-2", "bob", true))),
+                    _ => Err(StatusCode::UNAUTHORIZED),
+                }
+            }),
+        ))
+        .await;
+        let mut config = test_config();
+        config.jellyfin.url = jellyfin;
+        config.security.single_session = true;
+        let state = test_state(config);
+        let app = Router::new()
+            .route("/token", post(jellyfin_token_login))
+            .layer(CookieManagerLayer::new())
+            .with_state(state.clone());
+
+        let login = |token: &'static str| {
+            let app = app.clone();
+            let state = state.clone();
+            async move {
+                let req = token_request_with(serde_json::json!({
+                    "access_token": token,
+                    "token_response": true,
+                }));
+                let res = app.oneshot(req).await.unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                state
+                    .jwt_manager
+                    .validate_token(body["access_token"].as_str().unwrap(), TokenType::Access)
+                    .unwrap()
+                    .sid
+            }
+        };
+
+        let alice_first = login("alice-token").await;
+        let bob = login("bob-token").await;
+        let alice_second = login("alice-token").await;
+
+        assert!(!state.sessions.is_active(&alice_first, None));
+        assert!(state.sessions.is_active(&alice_second, None));
+        assert!(state.sessions.is_active(&bob, None));
+    }
+
+    #[tokio::test]
+    async fn test_single_session_ends_earlier_jellyfin_sessions() {
+        use crate::test_support::{test_config, test_state};
+
+        let logouts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut config = test_config();
+        config.jellyfin.url = spawn_session_jellyfin(logouts.clone()).await;
+        config.security.single_session = true;
+        let state = test_state(config);
+        let app = Router::new()
+            .route("/login", post(login))
+            .layer(CookieManagerLayer::new())
+            .with_state(state);
+
+        for _ in 0..2 {
+            let res = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/login")
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .body(Body::from(
+                            r#"{"username":"alice","password":"pw","token_response":true}"#,
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        // The first login's Jellyfin session is ended in the background
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while logouts.lock().unwrap().is_empty() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(*logouts.lock().unwrap(), vec!["jf-session".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_idle_sessions_end_jellyfin_sessions() {
+        use crate::test_support::{test_config, test_state};
+        use std::time::Duration;
+
+        let logouts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut config = test_config();
+        config.jellyfin.url = spawn_session_jellyfin(logouts.clone()).await;
+        let state = test_state(config);
+
+        let sid = state
+            .sessions
+            .create("user-1", Some("jf-session".to_string()));
+        state.sessions.backdate(&sid, Duration::from_secs(601));
+        assert!(
+            !state
+                .sessions
+                .is_active(&sid, Some(Duration::from_secs(600)))
+        );
+
+        tokio::spawn(end_expired_jellyfin_sessions(
+            state.clone(),
+            Duration::from_millis(10),
+        ));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while logouts.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(*logouts.lock().unwrap(), vec!["jf-session".to_string()]);
+    }
+
+    /// Webhook receiver passing on every payload it gets
+    async fn spawn_webhook_receiver() -> (
+        String,
+        tokio::sync::mpsc::UnboundedReceiver<serde_json::Value>,
+    ) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let url = crate::test_support::spawn_upstream(Router::new().route(
+            "/hook",
+            post(move |Json(payload): Json<serde_json::Value>| async move {
+                tx.send(payload).unwrap();
+                StatusCode::NO_CONTENT
+            }),
+        ))
+        .await;
+        (format!("{}/hook", url), rx)
+    }
+
+    #[tokio::test]
+    async fn test_login_webhook() {
+        let (url, mut payloads) = spawn_webhook_receiver().await;
+        let app = jellyfin_token_app_with(true, |security| {
+            security.login_webhook_url = Some(url);
+        })
+        .await;
+
+        let mut req = token_request("jf-token");
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 10], 51000))));
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let payload = tokio::time::timeout(std::time::Duration::from_secs(5), payloads.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payload["username"], "alice");
+        assert_eq!(payload["ip"], "192.0.2.10");
+        assert_eq!(payload["outcome"], "success");
+        let timestamp = payload["timestamp"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(timestamp).is_ok());
+
+        // Failed attempts are not reported by default
+        let res = app.oneshot(token_request("bad-token")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(payloads.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_login_webhook_failures() {
+        let (url, mut payloads) = spawn_webhook_receiver().await;
+        let app = jellyfin_token_app_with(false, |security| {
+            security.login_webhook_url = Some(url);
+            security.login_webhook_failures = true;
+        })
+        .await;
+
+        let res = app.oneshot(token_request("jf-token")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let payload = tokio::time::timeout(std::time::Duration::from_secs(5), payloads.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payload["username"], "alice");
+        assert_eq!(payload["outcome"], "forbidden");
+        assert!(payload["ip"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_login_webhook_unreachable_does_not_fail_login() {
+        let app = jellyfin_token_app_with(true, |security| {
+            security.login_webhook_url = Some("http://127.0.0.1:1/hook".to_string());
+        })
+        .await;
+        let res = app.oneshot(token_request("jf-token")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_cookie_mode_keeps_tokens_out_of_body() {
+        let app = jellyfin_token_app(true).await;
+        let res = app.oneshot(token_request("jf-token")).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body.get("access_token").is_none());
+        assert!(body.get("refresh_token").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_jellyfin_token_login_rejects_invalid_token() {
+        let app = jellyfin_token_app(true).await;
+        let res = app.oneshot(token_request("bad-token")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_admin_treated_as_admin() {
+        let (_guard, logs) = crate::test_support::capture_logs();
+        let app = jellyfin_token_app_with(false, |security| {
+            security.bootstrap_admin = Some("Alice".to_string());
+        })
+        .await;
+        let res = app.oneshot(token_request("jf-token")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["is_admin"], true);
+        assert!(logs.contents().contains("security.bootstrap_admin is set"));
+
+        // Only the named user is promoted, and only while configured
+        for bootstrap_admin in [Some("bob".to_string()), None] {
+            let app = jellyfin_token_app_with(false, |security| {
+                security.bootstrap_admin = bootstrap_admin;
+            })
+            .await;
+            let res = app.oneshot(token_request("jf-token")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jellyfin_token_login_rejects_non_admin() {
+        let app = jellyfin_token_app(false).await;
+        let res = app.oneshot(token_request("jf-token")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_non_admin_login_when_an_app_admits_them() {
+        let app = jellyfin_token_app_with(false, |security| security.require_admin = false).await;
+        let res = app.oneshot(token_request("jf-token")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    async fn login_with_redirect_cookie(value: &str) -> (serde_json::Value, Vec<String>) {
+        let app = jellyfin_token_app(true).await;
+        let mut req = token_request("jf-token");
+        req.headers_mut().insert(
+            header::COOKIE,
+            format!("{}={}", REDIRECT_COOKIE_NAME, value)
+                .parse()
+                .unwrap(),
+        );
+
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookies = res
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (serde_json::from_slice(&body).unwrap(), cookies)
+    }
+
+    #[tokio::test]
+    async fn test_login_consumes_redirect_cookie() {
+        let (body, cookies) = login_with_redirect_cookie("%2Fradarr%2Fmovies%3Fsort%3Dtitle").await;
+        assert_eq!(body["redirect"], "/radarr/movies?sort=title");
+        // The cookie is cleared once used
+        assert!(
+            cookies
+                .iter()
+                .any(|c| c.starts_with("bouncarr_redirect=;") && c.contains("Max-Age=0"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_rejects_unsafe_redirect_cookie() {
+        for value in ["%2F%2Fevil.example.com", "https%3A%2F%2Fevil.example.com"] {
+            let (body, _) = login_with_redirect_cookie(value).await;
+            assert_eq!(body["redirect"], "/");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_without_redirect_cookie_uses_default() {
+        let app = jellyfin_token_app(true).await;
+        let res = app.oneshot(token_request("jf-token")).await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["redirect"], "/");
+    }
+
+    /// App serving `/refresh` against a Jellyfin whose `/Users/{id}` answers
+    /// with 503 `failures` times before succeeding, plus a refresh request
+    /// for a live session
+    async fn flaky_refresh_app(
+        failures: usize,
+        configure: impl FnOnce(&mut SecurityConfig),
+    ) -> (Router, Request<Body>, Arc<std::sync::atomic::AtomicUsize>) {
+        use crate::test_support::{jellyfin_user, spawn_upstream, test_config, test_state};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let jellyfin = spawn_upstream(Router::new().route(
+            "/Users/:id",
+            get(move || async move {
+                if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                    Err(StatusCode::SERVICE_UNAVAILABLE)
+                } else {
+                    Ok(Json(jellyfin_user("user-1", "alice", true)))
+                }
+            }),
+        ))
+        .await;
+
+        let mut config = test_config();
+        config.jellyfin.url = jellyfin;
+        config.security.refresh_retry_delay_ms = 1;
+        configure(&mut config.security);
+        let state = test_state(config);
+
+        let user = UserInfo {
+            user_id: "user-1".to_string(),
+            username: "alice".to_string(),
+            is_administrator: true,
+            groups: Vec::new(),
+        };
+        let sid = state.sessions.create(&user.user_id, None);
+        let refresh_token = state.jwt_manager.create_refresh_token(&user, &sid).unwrap();
+        let req = Request::builder()
+            .method("POST")
+            .uri("/refresh")
+            .header(
+                header::COOKIE,
+                format!("bouncarr_refresh={}", refresh_token),
+            )
+            .body(Body::empty())
+            .unwrap();
+
+        let app = Router::new()
+            .route("/refresh", post(refresh))
+            .layer(CookieManagerLayer::new())
+            .with_state(state);
+        (app, req, calls)
+    }
+
+    fn access_cookie_max_age(res: &Response) -> Option<i64> {
+        res.headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .find(|c| c.starts_with("bouncarr_token="))
+            .map(|c| {
+                c.split(';')
+                    .find_map(|attr| attr.trim().strip_prefix("Max-Age="))
+                    .map_or(-1, |age| age.parse().unwrap())
+            })
+    }
+
+    #[tokio::test]
+    async fn test_refresh_retries_transient_jellyfin_failure() {
+        let (app, req, calls) = flaky_refresh_app(1, |security| security.refresh_retries = 2).await;
+
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(access_cookie_max_age(&res).is_some());
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_with_jellyfin_down() {
+        // Without a fallback the refresh fails once the retries are used up
+        let (app, req, calls) =
+            flaky_refresh_app(usize::MAX, |security| security.refresh_retries = 2).await;
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        // With one, a short-lived token is issued from the refresh token claims
+        let (app, req, _) = flaky_refresh_app(usize::MAX, |security| {
+            security.refresh_retries = 1;
+            security.refresh_fallback_seconds = Some(60);
+        })
+        .await;
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let max_age = access_cookie_max_age(&res).unwrap();
+        assert!((55..=60).contains(&max_age), "max age {}", max_age);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_reissues_refresh_token_near_expiry() {
+        let reissued = |res: &Response| {
+            res.headers()
+                .get_all(header::SET_COOKIE)
+                .iter()
+                .any(|c| c.to_str().unwrap().starts_with("bouncarr_refresh="))
+        };
+
+        // The 30 day token from login is well outside a 7 day window
+        for window in [None, Some(7)] {
+            let (app, req, _) =
+                flaky_refresh_app(0, |security| security.refresh_reissue_window_days = window)
+                    .await;
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(!reissued(&res));
+        }
+
+        let (app, req, _) = flaky_refresh_app(0, |security| {
+            security.refresh_reissue_window_days = Some(31)
+        })
+        .await;
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(reissued(&res));
+        assert!(access_cookie_max_age(&res).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_with_bearer_when_cookies_disabled() {
+        let (app, req, _) = flaky_refresh_app(0, |security| security.disable_cookies = true).await;
+
+        // The refresh cookie is ignored
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let (app, req, _) = flaky_refresh_app(0, |security| security.disable_cookies = true).await;
+        let refresh_token = req.headers()[header::COOKIE]
+            .to_str()
+            .unwrap()
+            .strip_prefix("bouncarr_refresh=")
+            .unwrap()
+            .to_string();
+        let req = Request::builder()
+            .method("POST")
+            .uri("/refresh")
+            .header(header::AUTHORIZATION, format!("Bearer {}", refresh_token))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["access_token"].is_string());
+    }
+
+    fn origin_headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, "bouncarr.example.com".parse().unwrap());
+        for (name, value) in pairs {
+            headers.insert(name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_check_origin_same_origin_passes() {
+        let mut security = security();
+        security.check_login_origin = true;
+
+        for headers in [
+            origin_headers(&[(header::ORIGIN, "https://bouncarr.example.com")]),
+            origin_headers(&[(
+                header::REFERER,
+                "https://Bouncarr.example.com/bouncarr/login",
+            )]),
+            // Non-browser clients send neither header
+            origin_headers(&[]),
+        ] {
+            assert!(check_origin(&security, &headers).is_ok());
         }
     }
 
+    #[test]
+    fn test_check_origin_rejects_cross_origin() {
+        let mut security = security();
+        let headers = origin_headers(&[(header::ORIGIN, "https://evil.example.net")]);
+        // Off by default
+        assert!(check_origin(&security, &headers).is_ok());
+
+        security.check_login_origin = true;
+        assert!(matches!(
+            check_origin(&security, &headers),
+            Err(AppError::AccessDenied(_))
+        ));
+        let null_origin = origin_headers(&[(header::ORIGIN, "null")]);
+        assert!(check_origin(&security, &null_origin).is_err());
+
+        security.login_allowed_origins = vec!["https://evil.example.net/".to_string()];
+        assert!(check_origin(&security, &headers).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cross_origin_login_rejected() {
+        let app =
+            jellyfin_token_app_with(true, |security| security.check_login_origin = true).await;
+        let mut req = token_request("jf-token");
+        req.headers_mut()
+            .insert(header::HOST, "bouncarr.example.com".parse().unwrap());
+        req.headers_mut()
+            .insert(header::ORIGIN, "https://evil.example.net".parse().unwrap());
+
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    fn security() -> SecurityConfig {
+        crate::test_support::test_config().security
+    }
+
     #[test]
     fn test_validate_login_valid() {
         let req = test_login_request("testuser", "testpass");
-        assert!(validate_login_request(&req).is_ok());
+        assert!(validate_login_request(&req, &security()).is_ok());
     }
 
     #[test]
     fn test_validate_login_empty_username() {
         let req = test_login_request("", "testpass");
-        assert!(validate_login_request(&req).is_err());
+        assert!(validate_login_request(&req, &security()).is_err());
     }
 
     #[test]
     fn test_validate_login_empty_password() {
         let req = test_login_request("testuser", "");
-        assert!(validate_login_request(&req).is_err());
+        assert!(validate_login_request(&req, &security()).is_err());
     }
 
     #[test]
     fn test_validate_login_username_too_long() {
         let long_username = "a".repeat(256);
         let req = test_login_request(&long_username, "testpass");
-        assert!(validate_login_request(&req).is_err());
+        assert!(validate_login_request(&req, &security()).is_err());
     }
 
     #[test]
     fn test_validate_login_password_too_long() {
         let long_password = "a".repeat(1025);
         let req = test_login_request("testuser", &long_password);
-        assert!(validate_login_request(&req).is_err());
+        assert!(validate_login_request(&req, &security()).is_err());
     }
 
     #[test]
     fn test_validate_login_username_with_control_chars() {
         let req = test_login_request("test\nuser", "testpass");
-        assert!(validate_login_request(&req).is_err());
+        assert!(validate_login_request(&req, &security()).is_err());
     }
 
     #[test]
@@ -256,11 +1725,61 @@ mod tests {
         // Max valid username (255 chars)
         let username = "a".repeat(255);
         let req = test_login_request(&username, "testpass");
-        assert!(validate_login_request(&req).is_ok());
+        assert!(validate_login_request(&req, &security()).is_ok());
 
         // Max valid password (1024 chars)
         let password = "a".repeat(1024);
         let req = test_login_request("testuser", &password);
-        assert!(validate_login_request(&req).is_ok());
+        assert!(validate_login_request(&req, &security()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_login_denylisted_username() {
+        let mut security = security();
+        security.username_denylist = vec!["admin".to_string(), "root".to_string()];
+
+        let req = test_login_request("Admin", "testpass");
+        assert!(validate_login_request(&req, &security).is_err());
+        let req = test_login_request("alice", "testpass");
+        assert!(validate_login_request(&req, &security).is_ok());
+    }
+
+    #[test]
+    fn test_validate_login_minimum_lengths() {
+        let mut security = security();
+        security.min_username_length = Some(3);
+        security.min_password_length = Some(8);
+
+        let req = test_login_request("al", "long-enough");
+        assert!(validate_login_request(&req, &security).is_err());
+        let req = test_login_request("alice", "short");
+        assert!(validate_login_request(&req, &security).is_err());
+        let req = test_login_request("alice", "long-enough");
+        assert!(validate_login_request(&req, &security).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_precheck_rejects_without_contacting_jellyfin() {
+        use crate::test_support::{test_config, test_state};
+
+        // Jellyfin is unreachable in the test config, so reaching it would
+        // fail with a 502 rather than a 401
+        let mut config = test_config();
+        config.security.username_denylist = vec!["admin".to_string()];
+        let app = Router::new()
+            .route("/login", post(login))
+            .layer(CookieManagerLayer::new())
+            .with_state(test_state(config));
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                serde_json::json!({ "username": "admin", "password": "hunter22" }).to_string(),
+            ))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
     }
 }
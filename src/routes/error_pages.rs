@@ -0,0 +1,176 @@
+use crate::AppState;
+use crate::error::GeneratedError;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Custom error pages from `server.error_pages`, read once at startup
+#[derive(Default)]
+pub struct ErrorPages {
+    pages: HashMap<u16, Bytes>,
+}
+
+impl ErrorPages {
+    /// Read every configured page into memory
+    ///
+    /// # Errors
+    ///
+    /// Returns error naming the page if a status code is not an error status
+    /// or a file can't be read
+    pub fn load(paths: &HashMap<u16, String>) -> Result<Self, String> {
+        let mut pages = HashMap::new();
+        for (&status, path) in paths {
+            if !(400..=599).contains(&status) {
+                return Err(format!(
+                    "server.error_pages: {} is not an error status code",
+                    status
+                ));
+            }
+            let page = std::fs::read(path).map_err(|e| {
+                format!(
+                    "server.error_pages: cannot read page for {} from '{}': {}",
+                    status, path, e
+                )
+            })?;
+            pages.insert(status, Bytes::from(page));
+        }
+
+        Ok(Self { pages })
+    }
+
+    fn get(&self, status: u16) -> Option<Bytes> {
+        self.pages.get(&status).cloned()
+    }
+}
+
+/// Serve the configured error page to browsers for Bouncarr's own error
+/// responses
+///
+/// The status and other headers are kept. Responses from upstreams, and
+/// statuses without a configured page, are left as they are.
+pub async fn error_pages_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let is_browser = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"));
+
+    let response = next.run(req).await;
+    if !is_browser || response.extensions().get::<GeneratedError>().is_none() {
+        return response;
+    }
+    let Some(page) = state.error_pages.get(response.status().as_u16()) else {
+        return response;
+    };
+
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    Response::from_parts(parts, Body::from(page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_router;
+    use crate::test_support::{test_config, test_state};
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    fn request(uri: &str, accept: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn body_text(res: Response) -> String {
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_configured_error_page_served() {
+        let dir = crate::test_support::temp_dir();
+        let page = dir.path().join("503.html");
+        std::fs::write(&page, "<h1>Back soon</h1>").unwrap();
+
+        let mut config = test_config();
+        config.server.maintenance_mode = true;
+        config
+            .server
+            .error_pages
+            .insert(503, page.to_string_lossy().into_owned());
+        let app = build_router(test_state(config));
+
+        let res = app
+            .clone()
+            .oneshot(request("/bouncarr/login", "text/html"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(body_text(res).await, "<h1>Back soon</h1>");
+
+        // API clients keep getting JSON
+        let res = app
+            .oneshot(request("/bouncarr/login", "application/json"))
+            .await
+            .unwrap();
+        assert_eq!(
+            body_text(res).await,
+            r#"{"error":"Bouncarr is under maintenance"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_response_without_page_for_status() {
+        let dir = crate::test_support::temp_dir();
+        let page = dir.path().join("404.html");
+        std::fs::write(&page, "<h1>Not here</h1>").unwrap();
+
+        let mut config = test_config();
+        config.server.maintenance_mode = true;
+        config
+            .server
+            .error_pages
+            .insert(404, page.to_string_lossy().into_owned());
+        let app = build_router(test_state(config));
+
+        let res = app
+            .oneshot(request("/bouncarr/login", "text/html"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body_text(res).await.contains("Down for maintenance"));
+    }
+
+    #[test]
+    fn test_missing_page_is_an_error() {
+        let paths = HashMap::from([(404, "/nonexistent/bouncarr/404.html".to_string())]);
+        let err = ErrorPages::load(&paths).err().unwrap();
+        assert!(err.contains("/nonexistent/bouncarr/404.html"));
+
+        let paths = HashMap::from([(200, "/dev/null".to_string())]);
+        assert!(ErrorPages::load(&paths).is_err());
+    }
+}
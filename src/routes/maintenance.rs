@@ -0,0 +1,256 @@
+use crate::AppState;
+use crate::auth::jwt::TokenType;
+use crate::auth::middleware::extract_token;
+use crate::error::{AppError, GeneratedError};
+use crate::jellyfin::types::UserInfo;
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tower_cookies::Cookies;
+
+/// Paths that keep working during maintenance
+const MAINTENANCE_EXEMPT_PATHS: &[&str] = &["/health", "/metrics", "/bouncarr/api/maintenance"];
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Maintenance {
+    pub enabled: bool,
+}
+
+/// Read whether maintenance mode is on
+pub async fn get_maintenance(State(state): State<Arc<AppState>>) -> Json<Maintenance> {
+    Json(Maintenance {
+        enabled: state.maintenance.load(Ordering::Relaxed),
+    })
+}
+
+/// Switch maintenance mode on or off at runtime
+pub async fn set_maintenance(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<UserInfo>,
+    Json(req): Json<Maintenance>,
+) -> Json<Maintenance> {
+    state.maintenance.store(req.enabled, Ordering::Relaxed);
+    tracing::warn!(
+        "Maintenance mode {} by {}",
+        if req.enabled { "enabled" } else { "disabled" },
+        user.username
+    );
+
+    Json(req)
+}
+
+/// Answer every request with a 503 while maintenance mode is on
+///
+/// `/health` stays up. With `server.maintenance_admin_bypass`, requests
+/// carrying a valid admin token of an active session are let through.
+pub async fn maintenance_middleware(
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if !state.maintenance.load(Ordering::Relaxed)
+        || MAINTENANCE_EXEMPT_PATHS.contains(&req.uri().path())
+        || (state.config.server.maintenance_admin_bypass && is_admin(&state, &cookies, &req))
+    {
+        return next.run(req).await;
+    }
+
+    let is_browser = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"));
+
+    if is_browser {
+        let mut response =
+            (StatusCode::SERVICE_UNAVAILABLE, Html(MAINTENANCE_PAGE)).into_response();
+        response.extensions_mut().insert(GeneratedError);
+        response
+    } else {
+        AppError::Maintenance.into_response()
+    }
+}
+
+/// Whether the request carries an admin token of a session that is still
+/// active
+fn is_admin(state: &AppState, cookies: &Cookies, req: &Request<Body>) -> bool {
+    let idle_timeout = state.config.security.idle_session_timeout();
+    extract_token(req, cookies, &state.config.security)
+        .and_then(|token| state.jwt_manager.validate_token(&token, TokenType::Access))
+        .is_ok_and(|claims| claims.is_admin && state.sessions.is_active(&claims.sid, idle_timeout))
+}
+
+const MAINTENANCE_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Bouncarr - Maintenance</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
+            min-height: 100vh;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+            margin: 0;
+            color: #333;
+        }
+        .card {
+            background: white;
+            border-radius: 12px;
+            box-shadow: 0 20px 60px rgba(0, 0, 0, 0.3);
+            padding: 40px;
+            max-width: 400px;
+            text-align: center;
+        }
+    </style>
+</head>
+<body>
+    <div class="card">
+        <h1>Down for maintenance</h1>
+        <p>Bouncarr is undergoing planned maintenance. Please try again later.</p>
+    </div>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use crate::build_router;
+    use crate::test_support::{access_token, test_config, test_state};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode, header},
+    };
+    use tower::ServiceExt;
+
+    fn request(uri: &str, accept: &str, token: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri(uri).header(header::ACCEPT, accept);
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode_responses() {
+        let mut config = test_config();
+        config.server.maintenance_mode = true;
+        let app = build_router(test_state(config));
+
+        let res = app
+            .clone()
+            .oneshot(request("/bouncarr/login", "text/html", None))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(
+            res.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html")
+        );
+
+        let res = app
+            .clone()
+            .oneshot(request(
+                "/sonarr/api/v3/series",
+                "application/json",
+                Some(&access_token(true)),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], br#"{"error":"Bouncarr is under maintenance"}"#);
+
+        let res = app
+            .oneshot(request("/health", "application/json", None))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_admin_bypass() {
+        let mut config = test_config();
+        config.server.maintenance_mode = true;
+        config.server.maintenance_admin_bypass = true;
+        let state = test_state(config);
+        let app = build_router(state.clone());
+
+        // Admins reach the proxy (which fails since no upstream runs)
+        let res = app
+            .clone()
+            .oneshot(request(
+                "/sonarr/api/v3/series",
+                "application/json",
+                Some(&access_token(true)),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+
+        let res = app
+            .clone()
+            .oneshot(request("/sonarr/api/v3/series", "application/json", None))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // A revoked session no longer bypasses maintenance
+        state.sessions.revoke("test-session");
+        let res = app
+            .oneshot(request(
+                "/sonarr/api/v3/series",
+                "application/json",
+                Some(&access_token(true)),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_toggled_at_runtime() {
+        let state = test_state(test_config());
+        let app = build_router(state.clone());
+
+        let res = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/bouncarr/api/maintenance")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(
+                        header::AUTHORIZATION,
+                        format!("Bearer {}", access_token(true)),
+                    )
+                    .body(Body::from(r#"{"enabled":true}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app
+            .oneshot(request("/bouncarr/login", "text/html", None))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -1,11 +1,26 @@
+pub mod admin;
 pub mod auth;
+pub mod error_pages;
+pub mod maintenance;
+pub mod readiness;
 pub mod ui;
 
+pub use admin::*;
 pub use auth::*;
+pub use error_pages::*;
+pub use maintenance::*;
+pub use readiness::*;
 pub use ui::*;
 
-use axum::Json;
+use crate::AppState;
+use axum::{
+    Json,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
 use serde_json::json;
+use std::sync::Arc;
 
 pub async fn health_check() -> Json<serde_json::Value> {
     Json(json!({
@@ -13,3 +28,11 @@ pub async fn health_check() -> Json<serde_json::Value> {
         "service": "bouncarr"
     }))
 }
+
+pub async fn metrics(State(state): State<Arc<AppState>>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+        .into_response()
+}
@@ -0,0 +1,138 @@
+use crate::AppState;
+use crate::error::AppError;
+use axum::{
+    Json,
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// Paths that keep working while waiting for Jellyfin
+const STARTUP_EXEMPT_PATHS: &[&str] = &["/health", "/ready", "/metrics"];
+
+/// Readiness probe: 200 once requests are being served, 503 while still
+/// waiting for Jellyfin at startup
+pub async fn ready_check(State(state): State<Arc<AppState>>) -> Response {
+    if !state.ready.load(Ordering::Relaxed) {
+        return AppError::StartingUp.into_response();
+    }
+    Json(serde_json::json!({ "status": "ready" })).into_response()
+}
+
+/// Answer every request with a 503 until Jellyfin has been reached
+///
+/// Only active with `jellyfin.wait_for_ready`; `/health` stays up.
+pub async fn readiness_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if state.ready.load(Ordering::Relaxed) || STARTUP_EXEMPT_PATHS.contains(&req.uri().path()) {
+        return next.run(req).await;
+    }
+
+    AppError::StartingUp.into_response()
+}
+
+/// Probe Jellyfin every `interval` until it answers, then start serving
+/// requests
+///
+/// Gives up waiting after `jellyfin.ready_timeout_seconds` and starts
+/// serving anyway, so a Jellyfin outage can't keep Bouncarr down for good.
+pub async fn wait_for_jellyfin(state: Arc<AppState>, interval: Duration) {
+    let max_wait = Duration::from_secs(state.config.jellyfin.ready_timeout_seconds);
+    let probe = async {
+        loop {
+            match state.jellyfin_client.ping().await {
+                Ok(()) => return,
+                Err(e) => tracing::debug!("Jellyfin not ready yet: {}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    };
+
+    match tokio::time::timeout(max_wait, probe).await {
+        Ok(()) => tracing::info!("Jellyfin is reachable, accepting requests"),
+        Err(_) => tracing::warn!(
+            "Jellyfin still unreachable after {:?}, accepting requests anyway",
+            max_wait
+        ),
+    }
+    state.ready.store(true, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_router;
+    use crate::test_support::{spawn_upstream, test_config, test_state};
+    use axum::{Router, http::StatusCode, routing::get};
+    use std::sync::atomic::AtomicBool;
+    use tower::ServiceExt;
+
+    fn request(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_held_until_jellyfin_is_up() {
+        // Mock Jellyfin that fails its probe until switched on
+        let up = Arc::new(AtomicBool::new(false));
+        let jellyfin_up = up.clone();
+        let jellyfin = Router::new().route(
+            "/System/Info/Public",
+            get(move || async move {
+                if jellyfin_up.load(Ordering::Relaxed) {
+                    StatusCode::OK
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+            }),
+        );
+
+        let mut config = test_config();
+        config.jellyfin.url = spawn_upstream(jellyfin).await;
+        config.jellyfin.wait_for_ready = true;
+        let state = test_state(config);
+        let app = build_router(state.clone());
+        tokio::spawn(wait_for_jellyfin(state.clone(), Duration::from_millis(10)));
+
+        let res = app
+            .clone()
+            .oneshot(request("/bouncarr/login"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            &body[..],
+            br#"{"error":"Bouncarr is starting up, waiting for Jellyfin"}"#
+        );
+        let res = app.clone().oneshot(request("/health")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        up.store(true, Ordering::Relaxed);
+        while !state.ready.load(Ordering::Relaxed) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let res = app.oneshot(request("/bouncarr/login")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_waiting_after_max_wait() {
+        let mut config = test_config();
+        config.jellyfin.wait_for_ready = true;
+        config.jellyfin.ready_timeout_seconds = 0;
+        let state = test_state(config);
+
+        wait_for_jellyfin(state.clone(), Duration::from_millis(10)).await;
+        assert!(state.ready.load(Ordering::Relaxed));
+    }
+}
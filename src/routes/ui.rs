@@ -1,7 +1,70 @@
-use axum::response::{Html, IntoResponse, Response};
+use crate::AppState;
+use crate::auth::cookies::{redirect_cookie, same_site_for};
+use crate::config::Config;
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::{Html, IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tower_cookies::Cookies;
 
-pub async fn serve_login_page() -> Response {
-    let html = r#"<!DOCTYPE html>
+#[derive(Debug, Deserialize)]
+pub struct LoginPageQuery {
+    /// Where to send the user after a successful login
+    pub redirect: Option<String>,
+}
+
+pub async fn serve_login_page(
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+    headers: HeaderMap,
+    Query(query): Query<LoginPageQuery>,
+) -> Response {
+    let target = resolve_redirect(
+        query.redirect.as_deref(),
+        &state.config.server.default_redirect,
+    );
+
+    // Remember the requested target so it survives reloads and multi-step
+    // logins; the login handler consumes it
+    if let Some(requested) = query.redirect.as_deref()
+        && !state.config.security.disable_cookies
+        && Config::is_safe_redirect(requested)
+    {
+        let same_site = same_site_for(&state.config.security, &headers);
+        cookies.add(redirect_cookie(
+            &state.config.security,
+            same_site,
+            requested,
+        ));
+    }
+
+    // Embed as a JSON string literal, escaping '<' so it cannot close the script tag
+    let target_js = serde_json::to_string(target)
+        .unwrap_or_else(|_| "\"/\"".to_string())
+        .replace('<', "\\u003c");
+
+    Html(LOGIN_PAGE.replace("__REDIRECT_TARGET__", &target_js)).into_response()
+}
+
+/// Pick the post-login redirect target
+///
+/// Uses the `redirect` parameter when it is a safe relative path, falling
+/// back to the configured default otherwise.
+fn resolve_redirect<'a>(requested: Option<&'a str>, default: &'a str) -> &'a str {
+    match requested {
+        Some(target) if Config::is_safe_redirect(target) => target,
+        Some(target) => {
+            tracing::warn!("Ignoring unsafe login redirect target: {}", target);
+            default
+        }
+        None => default,
+    }
+}
+
+const LOGIN_PAGE: &str = r#"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta charset="UTF-8">
@@ -162,12 +225,9 @@ pub async fn serve_login_page() -> Response {
                 const data = await response.json();
 
                 if (response.ok && data.success) {
-                    // Check if there's a redirect parameter
-                    const urlParams = new URLSearchParams(window.location.search);
-                    const redirect = urlParams.get('redirect');
-
-                    // Redirect to the original page or fallback to root
-                    window.location.href = redirect || '/';
+                    // Redirect to the target resolved by the server, the original page
+                    // or the configured default
+                    window.location.href = data.redirect || __REDIRECT_TARGET__;
                 } else {
                     throw new Error(data.error || 'Login failed');
                 }
@@ -183,5 +243,79 @@ pub async fn serve_login_page() -> Response {
 </body>
 </html>"#;
 
-    Html(html).into_response()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::cookies::REDIRECT_COOKIE_NAME;
+    use crate::test_support::{test_config, test_state};
+
+    async fn login_page_with_cookies(redirect: Option<&str>, default: &str) -> (String, Cookies) {
+        let mut config = test_config();
+        config.server.default_redirect = default.to_string();
+        let query = LoginPageQuery {
+            redirect: redirect.map(String::from),
+        };
+        let cookies = Cookies::default();
+        let res = serve_login_page(
+            State(test_state(config)),
+            cookies.clone(),
+            HeaderMap::new(),
+            Query(query),
+        )
+        .await;
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (String::from_utf8(body.to_vec()).unwrap(), cookies)
+    }
+
+    async fn login_page(redirect: Option<&str>, default: &str) -> String {
+        login_page_with_cookies(redirect, default).await.0
+    }
+
+    #[tokio::test]
+    async fn test_default_redirect_used_without_param() {
+        let page = login_page(None, "/sonarr").await;
+        assert!(page.contains(r#"window.location.href = data.redirect || "/sonarr";"#));
+    }
+
+    #[tokio::test]
+    async fn test_valid_param_overrides_default() {
+        let page = login_page(Some("/radarr/movies"), "/sonarr").await;
+        assert!(page.contains(r#"window.location.href = data.redirect || "/radarr/movies";"#));
+    }
+
+    #[tokio::test]
+    async fn test_unsafe_param_falls_back_to_default() {
+        for unsafe_target in ["https://evil.example.com", "//evil.example.com", "/\\evil"] {
+            let page = login_page(Some(unsafe_target), "/sonarr").await;
+            assert!(page.contains(r#"window.location.href = data.redirect || "/sonarr";"#));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redirect_cannot_close_script_tag() {
+        let page = login_page(Some("/a</script><script>alert(1)//"), "/").await;
+        assert!(!page.contains("</script><script>alert"));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_param_stored_in_cookie() {
+        let (_, cookies) = login_page_with_cookies(Some("/radarr/movies?sort=title"), "/").await;
+        let cookie = cookies.get(REDIRECT_COOKIE_NAME).unwrap();
+        assert_eq!(cookie.value(), "%2Fradarr%2Fmovies%3Fsort%3Dtitle");
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(
+            cookie.max_age(),
+            Some(tower_cookies::cookie::time::Duration::seconds(300))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redirect_cookie_not_set_for_unsafe_or_missing_param() {
+        for redirect in [Some("//evil.example.com"), None] {
+            let (_, cookies) = login_page_with_cookies(redirect, "/").await;
+            assert!(cookies.get(REDIRECT_COOKIE_NAME).is_none());
+        }
+    }
 }
@@ -0,0 +1,176 @@
+//! Shared helpers for unit tests
+
+use crate::AppState;
+use crate::auth::JwtManager;
+use crate::auth::sessions::SessionStore;
+use crate::config::Config;
+use crate::jellyfin::JellyfinClient;
+use crate::proxy::user_agent::UserAgentFilter;
+use std::sync::Arc;
+
+/// Fresh temporary directory, removed with its contents when dropped
+pub fn temp_dir() -> tempfile::TempDir {
+    tempfile::Builder::new()
+        .prefix("bouncarr-")
+        .tempdir()
+        .expect("temporary directory should be created")
+}
+
+/// Minimal configuration used as a base for tests
+pub const TEST_CONFIG: &str = r#"
+jellyfin:
+  url: http://127.0.0.1:9
+  api_key: test-api-key
+arr_apps:
+  - name: sonarr
+    url: http://127.0.0.1:9
+server:
+  host: 127.0.0.1
+  port: 0
+  request_timeout_seconds: -1
+security:
+  access_token_expiry_hours: 24
+  refresh_token_expiry_days: 30
+  cookie_name: bouncarr_token
+  refresh_cookie_name: bouncarr_refresh
+  secure_cookies: false
+  jwt_secret: test-secret-key-for-testing
+"#;
+
+/// Parse the base test configuration
+pub fn test_config() -> Config {
+    serde_yaml::from_str(TEST_CONFIG).expect("test config should parse")
+}
+
+/// Build shared application state from a configuration
+pub fn test_state(config: Config) -> Arc<AppState> {
+    let jellyfin_client = JellyfinClient::new(
+        config.jellyfin.clone(),
+        config.server.request_timeout_seconds,
+    )
+    .expect("test Jellyfin client should build");
+    let jwt_manager = JwtManager::new(&config.security);
+
+    Arc::new(AppState {
+        jellyfin_client,
+        jwt_manager,
+        sessions: SessionStore::new(config.security.refresh_token_lifetime()),
+        refresh_tokens: Default::default(),
+        http_client: reqwest::Client::new(),
+        metrics: Default::default(),
+        idempotency_cache: Default::default(),
+        log_filter: crate::logging::detached_handle(),
+        maintenance: std::sync::atomic::AtomicBool::new(config.server.maintenance_mode),
+        ready: std::sync::atomic::AtomicBool::new(!config.jellyfin.wait_for_ready),
+        user_agent_filter: UserAgentFilter::new(&config.server.blocked_user_agents)
+            .expect("test user agent patterns should compile"),
+        connection_limiter: Default::default(),
+        rate_limiter: Default::default(),
+        upstream_probes: Default::default(),
+        error_pages: crate::routes::ErrorPages::load(&config.server.error_pages)
+            .expect("test error pages should load"),
+        app_clients: crate::proxy::app_clients::AppClients::build(&config)
+            .expect("test app clients should build"),
+        live_admin_checks: Default::default(),
+        config,
+    })
+}
+
+/// Serve a router on a random local port, returning its base URL
+pub async fn spawn_upstream(router: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("test upstream should bind");
+    let addr = listener.local_addr().expect("test upstream has an address");
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.ok();
+    });
+    format!("http://{}", addr)
+}
+
+/// Jellyfin `User` payload as returned by the Jellyfin API
+pub fn jellyfin_user(id: &str, name: &str, is_admin: bool) -> serde_json::Value {
+    serde_json::json!({
+        "Id": id,
+        "Name": name,
+        "Policy": { "IsAdministrator": is_admin },
+    })
+}
+
+/// Serve a mock Jellyfin returning the given user from `/Users/{id}`,
+/// returning its base URL
+pub async fn spawn_jellyfin(user: serde_json::Value) -> String {
+    let router = axum::Router::new().route(
+        "/Users/:id",
+        axum::routing::get(move || async move { axum::Json(user) }),
+    );
+    spawn_upstream(router).await
+}
+
+/// User info for an administrator
+pub fn test_user_info() -> crate::jellyfin::types::UserInfo {
+    crate::jellyfin::types::UserInfo {
+        user_id: "test-user-123".to_string(),
+        username: "testuser".to_string(),
+        is_administrator: true,
+        groups: Vec::new(),
+    }
+}
+
+/// Sign arbitrary claims with the test JWT secret
+pub fn sign_claims(claims: &crate::auth::jwt::Claims) -> String {
+    let secret = test_config()
+        .security
+        .jwt_secret
+        .expect("test config has a JWT secret");
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        claims,
+        &jsonwebtoken::EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .expect("test claims should encode")
+}
+
+/// Signed access token for the test user
+pub fn access_token(is_admin: bool) -> String {
+    let user = crate::jellyfin::types::UserInfo {
+        is_administrator: is_admin,
+        ..test_user_info()
+    };
+    JwtManager::new(&test_config().security)
+        .create_access_token(&user, "test-session")
+        .expect("test access token should be created")
+}
+
+/// Log output captured by [`capture_logs`]
+#[derive(Clone, Default)]
+pub struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Capture debug-level logs on the current thread until the guard is dropped
+pub fn capture_logs() -> (tracing::subscriber::DefaultGuard, CapturedLogs) {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    (tracing::subscriber::set_default(subscriber), logs)
+}